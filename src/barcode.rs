@@ -0,0 +1,580 @@
+//! Parsing for the retail barcodes a scanner can send. Every code is normalized to a 14 digit
+//! GTIN so that the same item is found in the catalog whichever symbology it was printed with.
+//!
+//! ```
+//!     use scanner_terminal::barcode::{self, BarcodeError, Symbology};
+//!
+//!     let upc_a = barcode::parse("042100005264").unwrap();
+//!     let upc_e = Symbology::UpcE.parse("04252614").unwrap();
+//!
+//!     assert_eq!(upc_a, upc_e);
+//!     assert_eq!(upc_a.to_string(), "00042100005264");
+//!
+//!     assert_eq!(
+//!         barcode::parse("042100005265"),
+//!         Err(BarcodeError::BadCheckDigit { expected: 4, found: 5 })
+//!     );
+//! ```
+
+use crate::{Sku, Unit};
+
+use rust_decimal::Decimal;
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+
+/// The barcode formats that can be parsed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Symbology {
+    UpcA,
+    UpcE,
+    Ean8,
+    Ean13,
+    Gtin14,
+}
+
+impl Symbology {
+    /// Number of digits in a code of this symbology, including the check digit.
+    pub fn digits(self) -> usize {
+        match self {
+            Symbology::UpcA => 12,
+            Symbology::UpcE => 8,
+            Symbology::Ean8 => 8,
+            Symbology::Ean13 => 13,
+            Symbology::Gtin14 => 14,
+        }
+    }
+
+    /// Parses `code` as this symbology, validating the check digit and normalizing it to a GTIN.
+    pub fn parse(self, code: &str) -> Result<Gtin, BarcodeError> {
+        let digits = parse_digits(code)?;
+
+        if digits.len() != self.digits() {
+            return Err(BarcodeError::InvalidLength {
+                symbology: self,
+                len: digits.len(),
+            });
+        }
+
+        let digits = match self {
+            Symbology::UpcE => expand_upc_e(&digits)?,
+            _ => digits,
+        };
+
+        let (data, found) = digits.split_at(digits.len() - 1);
+
+        let expected = check_digit(data);
+
+        if found[0] != expected {
+            return Err(BarcodeError::BadCheckDigit {
+                expected,
+                found: found[0],
+            });
+        }
+
+        let mut gtin = [0; 14];
+
+        gtin[14 - digits.len()..].copy_from_slice(&digits);
+
+        Ok(Gtin(gtin))
+    }
+}
+
+impl fmt::Display for Symbology {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Symbology::UpcA => "UPC-A",
+            Symbology::UpcE => "UPC-E",
+            Symbology::Ean8 => "EAN-8",
+            Symbology::Ean13 => "EAN-13",
+            Symbology::Gtin14 => "GTIN-14",
+        };
+
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BarcodeError {
+    /// The code contains something other than the digits 0-9.
+    InvalidCharacter(char),
+    /// The code doesn't have the number of digits its symbology needs.
+    InvalidLength { symbology: Symbology, len: usize },
+    /// No symbology has a code of this many digits.
+    UnknownLength(usize),
+    /// UPC-E codes can only use number system 0 or 1.
+    InvalidNumberSystem(u8),
+    /// The code was read but its check digit doesn't match the rest of the digits.
+    BadCheckDigit { expected: u8, found: u8 },
+    /// A variable measure rule has more decimal places than a `Decimal` can hold.
+    TooManyDecimals(u32),
+    /// A variable measure rule's item reference and price check digit leave no digits for the
+    /// value.
+    NoRoomForValue {
+        item_digits: usize,
+        check_digit: bool,
+    },
+}
+
+impl fmt::Display for BarcodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BarcodeError::InvalidCharacter(c) => write!(f, "invalid character {:?}", c),
+            BarcodeError::InvalidLength { symbology, len } => write!(
+                f,
+                "{} needs {} digits, got {}",
+                symbology,
+                symbology.digits(),
+                len
+            ),
+            BarcodeError::UnknownLength(len) => write!(f, "no barcode has {} digits", len),
+            BarcodeError::InvalidNumberSystem(ns) => {
+                write!(f, "UPC-E can't use number system {}", ns)
+            }
+            BarcodeError::BadCheckDigit { expected, found } => {
+                write!(f, "bad check digit {}, expected {}", found, expected)
+            }
+            BarcodeError::TooManyDecimals(decimals) => {
+                write!(f, "values can't have {} decimal places", decimals)
+            }
+            BarcodeError::NoRoomForValue {
+                item_digits,
+                check_digit,
+            } => write!(
+                f,
+                "{} item digits{} leave no room for a value",
+                item_digits,
+                if *check_digit {
+                    " and a check digit"
+                } else {
+                    ""
+                }
+            ),
+        }
+    }
+}
+
+impl Error for BarcodeError {}
+
+/// A 14 digit Global Trade Item Number, the form every parsed barcode is normalized to. Shorter
+/// codes are padded with leading zeros.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Gtin([u8; 14]);
+
+impl Gtin {
+    pub fn digits(&self) -> &[u8; 14] {
+        &self.0
+    }
+}
+
+impl fmt::Display for Gtin {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for d in &self.0 {
+            write!(f, "{}", d)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl From<Gtin> for Sku {
+    fn from(gtin: Gtin) -> Self {
+        Sku::new(gtin.to_string())
+    }
+}
+
+/// Parses a code, working out the symbology from its length. An 8 digit code is read as EAN-8,
+/// use `Symbology::UpcE.parse()` when the scanner reports a UPC-E.
+pub fn parse(code: &str) -> Result<Gtin, BarcodeError> {
+    let symbology = match code.trim().len() {
+        8 => Symbology::Ean8,
+        12 => Symbology::UpcA,
+        13 => Symbology::Ean13,
+        14 => Symbology::Gtin14,
+        len => return Err(BarcodeError::UnknownLength(len)),
+    };
+
+    symbology.parse(code)
+}
+
+/// Computes the GS1 check digit for `data`, which is every digit of the code except the check
+/// digit itself.
+pub fn check_digit(data: &[u8]) -> u8 {
+    let sum: u32 = data
+        .iter()
+        .rev()
+        .enumerate()
+        .map(|(i, d)| u32::from(*d) * if i % 2 == 0 { 3 } else { 1 })
+        .sum();
+
+    ((10 - sum % 10) % 10) as u8
+}
+
+fn parse_digits(code: &str) -> Result<Vec<u8>, BarcodeError> {
+    code.trim()
+        .chars()
+        .map(|c| {
+            c.to_digit(10)
+                .map(|d| d as u8)
+                .ok_or(BarcodeError::InvalidCharacter(c))
+        })
+        .collect()
+}
+
+/// Expands the 8 digits of a UPC-E into the 12 digits of the UPC-A it was compressed from. The
+/// check digit is carried over, UPC-E uses the check digit of its UPC-A.
+fn expand_upc_e(digits: &[u8]) -> Result<Vec<u8>, BarcodeError> {
+    let ns = digits[0];
+
+    if ns > 1 {
+        return Err(BarcodeError::InvalidNumberSystem(ns));
+    }
+
+    let d = &digits[1..7];
+
+    let (manufacturer, product) = match d[5] {
+        0..=2 => ([d[0], d[1], d[5], 0, 0], [0, 0, d[2], d[3], d[4]]),
+        3 => ([d[0], d[1], d[2], 0, 0], [0, 0, 0, d[3], d[4]]),
+        4 => ([d[0], d[1], d[2], d[3], 0], [0, 0, 0, 0, d[4]]),
+        _ => ([d[0], d[1], d[2], d[3], d[4]], [0, 0, 0, 0, d[5]]),
+    };
+
+    let mut upc_a = vec![ns];
+
+    upc_a.extend_from_slice(&manufacturer);
+    upc_a.extend_from_slice(&product);
+    upc_a.push(digits[7]);
+
+    Ok(upc_a)
+}
+
+/// What the value digits of a variable measure code hold.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Embedded {
+    Price,
+    /// A weight in the unit the store's scales print labels in.
+    Weight(Unit),
+}
+
+/// How the 10 digits after the prefix of a variable measure code are laid out: the item
+/// reference comes first, then an optional price check digit and then the value, which uses the
+/// rest of the digits, so there has to be at least one left for it.
+///
+/// The price check digit is skipped but not validated, the code's own check digit still is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EmbeddedRule {
+    kind: Embedded,
+    item_digits: usize,
+    check_digit: bool,
+    decimals: u32,
+}
+
+impl EmbeddedRule {
+    /// The value digits are a price with `decimals` decimal places.
+    pub fn price(item_digits: usize, decimals: u32) -> Self {
+        EmbeddedRule {
+            kind: Embedded::Price,
+            item_digits,
+            check_digit: false,
+            decimals,
+        }
+    }
+
+    /// The value digits are a weight in `unit` with `decimals` decimal places.
+    pub fn weight(item_digits: usize, decimals: u32, unit: Unit) -> Self {
+        EmbeddedRule {
+            kind: Embedded::Weight(unit),
+            ..EmbeddedRule::price(item_digits, decimals)
+        }
+    }
+
+    /// A price check digit sits between the item reference and the value.
+    pub fn with_check_digit(mut self) -> Self {
+        self.check_digit = true;
+        self
+    }
+
+    /// The usual UPC-A layout for number system 2: `2 IIIII C PPPP K`.
+    pub fn upc_price() -> Self {
+        EmbeddedRule::price(5, 2).with_check_digit()
+    }
+
+    fn validate(&self) -> Result<(), BarcodeError> {
+        // the most decimal places a Decimal has
+        if self.decimals > 28 {
+            return Err(BarcodeError::TooManyDecimals(self.decimals));
+        }
+
+        if self.value_start() >= 13 {
+            return Err(BarcodeError::NoRoomForValue {
+                item_digits: self.item_digits,
+                check_digit: self.check_digit,
+            });
+        }
+
+        Ok(())
+    }
+
+    fn value_start(&self) -> usize {
+        // skip the indicator digit, the 2 digit prefix and the item reference
+        self.item_digits
+            .saturating_add(3 + if self.check_digit { 1 } else { 0 })
+    }
+}
+
+/// A variable measure code that was scanned, split into the item to look up and the value
+/// printed inside the code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EmbeddedCode {
+    /// The code with every digit after the item reference zeroed and the check digit recomputed,
+    /// this is how the item is keyed in the catalog.
+    pub item: Gtin,
+    pub kind: Embedded,
+    pub value: Decimal,
+}
+
+/// The variable measure prefixes a store uses. Prefixes are the first 2 digits of the EAN-13, so
+/// UPC-A number system 2 is prefix `2` and the EAN-13 range is `20` to `29`.
+///
+/// ```
+///     use scanner_terminal::barcode::{self, Embedded, EmbeddedCodes, EmbeddedRule};
+///     use scanner_terminal::Unit;
+///
+///     use rust_decimal::Decimal;
+///
+///     let codes = EmbeddedCodes::new()
+///         .rule(2, EmbeddedRule::upc_price())
+///         .rule(21, EmbeddedRule::weight(5, 3, Unit::Kg));
+///
+///     // 2 01234 0 0349 check digit
+///     let deli = codes.decode(&barcode::parse("201234003496").unwrap()).unwrap().unwrap();
+///
+///     assert_eq!(deli.kind, Embedded::Price);
+///     assert_eq!(deli.value, Decimal::new(349, 2));
+///     assert_eq!(deli.item.to_string(), "00201234000006");
+///
+///     // 21 01234 01250 check digit, so 1.25 kg
+///     let produce = codes.decode(&barcode::parse("2101234012505").unwrap()).unwrap().unwrap();
+///
+///     assert_eq!(produce.kind, Embedded::Weight(Unit::Kg));
+///     assert_eq!(produce.value, Decimal::new(1250, 3));
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EmbeddedCodes {
+    rules: HashMap<u8, EmbeddedRule>,
+}
+
+impl EmbeddedCodes {
+    pub fn new() -> Self {
+        EmbeddedCodes::default()
+    }
+
+    /// Reads codes starting with `prefix` using `rule`, replacing any rule already set for it.
+    pub fn rule(mut self, prefix: u8, rule: EmbeddedRule) -> Self {
+        self.rules.insert(prefix, rule);
+        self
+    }
+
+    /// Checks that every rule leaves room for a value that can be represented.
+    pub(crate) fn validate(&self) -> Result<(), BarcodeError> {
+        self.rules.values().try_for_each(EmbeddedRule::validate)
+    }
+
+    /// Splits `gtin` if it has one of the configured prefixes, otherwise it is a regular code and
+    /// `None` is returned.
+    pub fn decode(&self, gtin: &Gtin) -> Result<Option<EmbeddedCode>, BarcodeError> {
+        let digits = gtin.digits();
+
+        if digits[0] != 0 {
+            return Ok(None);
+        }
+
+        let rule = match self.rules.get(&(digits[1] * 10 + digits[2])) {
+            Some(rule) => rule,
+            None => return Ok(None),
+        };
+
+        rule.validate()?;
+
+        let start = rule.value_start();
+
+        let value = digits[start..13]
+            .iter()
+            .fold(0_i64, |acc, d| acc * 10 + i64::from(*d));
+
+        let mut item = *digits;
+
+        for d in item[3 + rule.item_digits..13].iter_mut() {
+            *d = 0;
+        }
+
+        item[13] = check_digit(&item[..13]);
+
+        Ok(Some(EmbeddedCode {
+            item: Gtin(item),
+            kind: rule.kind,
+            value: Decimal::new(value, rule.decimals),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_normalizes_every_symbology() {
+        assert_eq!(
+            Symbology::UpcA.parse("036000291452").unwrap().to_string(),
+            "00036000291452"
+        );
+        assert_eq!(
+            Symbology::Ean13.parse("4006381333931").unwrap().to_string(),
+            "04006381333931"
+        );
+        assert_eq!(
+            Symbology::Ean8.parse("96385074").unwrap().to_string(),
+            "00000096385074"
+        );
+        assert_eq!(
+            Symbology::Gtin14
+                .parse("10012345678902")
+                .unwrap()
+                .to_string(),
+            "10012345678902"
+        );
+
+        // a UPC-A and the same code written as an EAN-13 are the same item
+        assert_eq!(parse("036000291452"), parse("0036000291452"));
+    }
+
+    #[test]
+    fn it_expands_upc_e() {
+        // one code for each way the last digit can compress the UPC-A
+        let cases = [
+            ("04252614", "042100005264"),
+            ("01234530", "012300000450"),
+            ("01234540", "012340000050"),
+            ("01234500", "012000003450"),
+            ("01234550", "012345000050"),
+        ];
+
+        for (upc_e, upc_a) in cases.iter() {
+            let data: Vec<u8> = upc_a[..11].bytes().map(|b| b - b'0').collect();
+
+            let check = check_digit(&data);
+
+            let upc_e = format!("{}{}", &upc_e[..7], check);
+            let upc_a = format!("{}{}", &upc_a[..11], check);
+
+            assert_eq!(Symbology::UpcE.parse(&upc_e), Symbology::UpcA.parse(&upc_a));
+        }
+
+        assert_eq!(
+            Symbology::UpcE.parse("24252614"),
+            Err(BarcodeError::InvalidNumberSystem(2))
+        );
+    }
+
+    #[test]
+    fn it_rejects_bad_codes() {
+        assert_eq!(
+            parse("036000291453"),
+            Err(BarcodeError::BadCheckDigit {
+                expected: 2,
+                found: 3
+            })
+        );
+        assert_eq!(
+            parse("03600029145X"),
+            Err(BarcodeError::InvalidCharacter('X'))
+        );
+        assert_eq!(parse("0360002914"), Err(BarcodeError::UnknownLength(10)));
+        assert_eq!(
+            Symbology::Ean13.parse("036000291452"),
+            Err(BarcodeError::InvalidLength {
+                symbology: Symbology::Ean13,
+                len: 12
+            })
+        );
+    }
+
+    #[test]
+    fn it_decodes_embedded_codes() {
+        let codes = EmbeddedCodes::new()
+            .rule(2, EmbeddedRule::upc_price())
+            .rule(20, EmbeddedRule::price(5, 2))
+            .rule(28, EmbeddedRule::weight(4, 3, Unit::Lb));
+
+        let code = |data: &str| {
+            let digits: Vec<u8> = data.bytes().map(|b| b - b'0').collect();
+
+            parse(&format!("{}{}", data, check_digit(&digits))).unwrap()
+        };
+
+        let decoded = codes.decode(&code("200042012345")).unwrap().unwrap();
+
+        assert_eq!(decoded.kind, Embedded::Price);
+        assert_eq!(decoded.value, Decimal::new(12345, 2));
+        assert_eq!(decoded.item, code("200042000000"));
+
+        let decoded = codes.decode(&code("280042012345")).unwrap().unwrap();
+
+        assert_eq!(decoded.kind, Embedded::Weight(Unit::Lb));
+        assert_eq!(decoded.value, Decimal::new(12345, 3));
+        assert_eq!(decoded.item, code("280042000000"));
+
+        // the price check digit isn't part of the price
+        let decoded = codes.decode(&code("21234570349")).unwrap().unwrap();
+
+        assert_eq!(decoded.value, Decimal::new(349, 2));
+        assert_eq!(decoded.item, code("21234500000"));
+
+        // prefixes the store hasn't set up are regular codes
+        assert_eq!(codes.decode(&code("290042012345")), Ok(None));
+        assert_eq!(codes.decode(&code("03600029145")), Ok(None));
+
+        // a Decimal only has room for 28 decimal places
+        let codes = EmbeddedCodes::new().rule(20, EmbeddedRule::price(5, 29));
+
+        assert_eq!(codes.validate(), Err(BarcodeError::TooManyDecimals(29)));
+        assert_eq!(
+            codes.decode(&code("200042012345")),
+            Err(BarcodeError::TooManyDecimals(29))
+        );
+
+        // the value needs at least one of the 10 digits after the prefix
+        assert_eq!(
+            EmbeddedCodes::new()
+                .rule(20, EmbeddedRule::price(9, 0))
+                .validate(),
+            Ok(())
+        );
+
+        let too_long = vec![
+            (EmbeddedRule::price(10, 0), 10, false),
+            (
+                EmbeddedRule::weight(9, 3, Unit::Kg).with_check_digit(),
+                9,
+                true,
+            ),
+            (
+                EmbeddedRule::price(usize::MAX, 2).with_check_digit(),
+                usize::MAX,
+                true,
+            ),
+        ];
+
+        for (rule, item_digits, check_digit) in too_long {
+            let codes = EmbeddedCodes::new().rule(20, rule);
+            let error = BarcodeError::NoRoomForValue {
+                item_digits,
+                check_digit,
+            };
+
+            assert_eq!(codes.validate(), Err(error.clone()));
+            assert_eq!(codes.decode(&code("200042012345")), Err(error));
+        }
+    }
+}
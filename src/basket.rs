@@ -0,0 +1,364 @@
+use crate::catalog::lookup;
+use crate::coupon::{self, Coupon, Requirement};
+use crate::journal::{Entry, Operation};
+use crate::loyalty::Redemption;
+use crate::member::{Level, Member};
+use crate::rounding::Tender;
+use crate::tax::Exemption;
+use crate::{base_price, checked_sum, Catalog, Sku, TerminalError, Unit, MAX_QUANTITY};
+
+use rust_decimal::Decimal;
+
+use std::collections::HashMap;
+use std::time::SystemTime;
+
+/// What is currently in a terminal, built up by applying journal operations.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct Basket {
+    pub(crate) items: HashMap<Sku, usize>,
+    pub(crate) weights: HashMap<Sku, Decimal>,
+    pub(crate) priced: Vec<(Sku, Decimal)>,
+    pub(crate) overrides: HashMap<Sku, Decimal>,
+    /// Coupons in the order they were redeemed.
+    pub(crate) coupons: Vec<Coupon>,
+    pub(crate) member: Option<Member>,
+    /// Loyalty points redeemed by the member, in the order they were redeemed.
+    pub(crate) points: Vec<(u64, Redemption)>,
+    pub(crate) exemption: Option<Exemption>,
+    pub(crate) tender: Option<Tender>,
+}
+
+impl Basket {
+    /// Builds a basket from nothing by applying every entry in order. Each operation is checked
+    /// against `catalog_at` the time it was recorded, for the member on the transaction by then.
+    /// `coupons` are the ones set up at the terminal that can be redeemed by code.
+    pub(crate) fn replay<'a, I, F>(
+        coupons: &[Coupon],
+        entries: I,
+        mut catalog_at: F,
+    ) -> Result<Self, TerminalError>
+    where
+        I: IntoIterator<Item = &'a Entry>,
+        F: FnMut(SystemTime, Option<Level>) -> Catalog,
+    {
+        let mut basket = Basket::default();
+        let mut priced: Option<(SystemTime, Option<Level>, Catalog)> = None;
+
+        for entry in entries {
+            let level = basket.member.as_ref().map(|m| m.level);
+
+            // entries done together are usually priced the same, so the catalog is kept until
+            // the time or the member's level changes
+            let catalog = match priced {
+                Some((time, at, ref catalog)) if time == entry.timestamp && at == level => catalog,
+                _ => {
+                    let catalog = catalog_at(entry.timestamp, level);
+
+                    &priced.insert((entry.timestamp, level, catalog)).2
+                }
+            };
+
+            basket.apply(catalog, coupons, &entry.operation)?;
+        }
+
+        Ok(basket)
+    }
+
+    /// Applies a single operation. Operations are checked before anything changes, so a failed
+    /// operation leaves the basket as it was.
+    pub(crate) fn apply(
+        &mut self,
+        catalog: &Catalog,
+        coupons: &[Coupon],
+        operation: &Operation,
+    ) -> Result<(), TerminalError> {
+        match operation {
+            Operation::Scan(item) => {
+                counted(catalog, item)?;
+
+                let count = self.items.get(item).copied().unwrap_or(0);
+
+                let count = count.checked_add(1).ok_or(TerminalError::Overflow)?;
+
+                if count > MAX_QUANTITY {
+                    return Err(TerminalError::InvalidQuantity(count));
+                }
+
+                self.items.insert(item.clone(), count);
+            }
+            Operation::ScanWeight { item, weight } => {
+                let unit = lookup(catalog, item)?.unit;
+
+                if !unit.is_weight() {
+                    return Err(TerminalError::WrongUnit {
+                        item: item.clone(),
+                        unit,
+                    });
+                }
+
+                if *weight <= Decimal::ZERO {
+                    return Err(TerminalError::InvalidWeight(*weight));
+                }
+
+                let total = self.weights.get(item).copied().unwrap_or(Decimal::ZERO);
+
+                let total = total.checked_add(*weight).ok_or(TerminalError::Overflow)?;
+
+                self.weights.insert(item.clone(), total);
+            }
+            Operation::ScanPriced { item, price } => {
+                lookup(catalog, item)?;
+
+                self.priced.push((item.clone(), *price));
+            }
+            Operation::Void(item) => self.shrink(catalog, |basket| {
+                if let Some(count) = basket.items.get_mut(item) {
+                    *count -= 1;
+
+                    if *count == 0 {
+                        basket.items.remove(item);
+                    }
+                } else if basket.weights.remove(item).is_none() {
+                    let last = basket
+                        .priced
+                        .iter()
+                        .rposition(|(priced, _)| priced == item)
+                        .ok_or_else(|| TerminalError::NotInBasket(item.clone()))?;
+
+                    basket.priced.remove(last);
+                }
+
+                basket.forget_override(item);
+
+                Ok(())
+            })?,
+            Operation::SetQuantity { item, quantity } => {
+                counted(catalog, item)?;
+
+                if *quantity > MAX_QUANTITY {
+                    return Err(TerminalError::InvalidQuantity(*quantity));
+                }
+
+                self.shrink(catalog, |basket| {
+                    if *quantity == 0 {
+                        basket.items.remove(item);
+
+                        basket.forget_override(item);
+                    } else {
+                        basket.items.insert(item.clone(), *quantity);
+                    }
+
+                    Ok(())
+                })?;
+            }
+            Operation::OverridePrice { item, price } => {
+                lookup(catalog, item)?;
+
+                if price.is_sign_negative() && !price.is_zero() {
+                    return Err(TerminalError::InvalidPrice(*price));
+                }
+
+                if !self.items.contains_key(item) && !self.weights.contains_key(item) {
+                    return Err(TerminalError::NotInBasket(item.clone()));
+                }
+
+                self.overrides.insert(item.clone(), *price);
+            }
+            Operation::Clear => *self = Basket::default(),
+            Operation::Redeem(code) => {
+                let coupon = coupons
+                    .iter()
+                    .find(|c| c.code == *code)
+                    .ok_or_else(|| TerminalError::UnknownCoupon(code.clone()))?;
+
+                self.redeem(catalog, coupon.clone())?;
+            }
+            Operation::ScanCoupon(data) => {
+                let coupon = coupon::parse(data)?.coupon(data, catalog)?;
+
+                self.redeem(catalog, coupon)?;
+            }
+            Operation::AttachMember(member) => {
+                if self.member.as_ref().map(|m| &m.id) != Some(&member.id) {
+                    self.points.clear();
+                }
+
+                self.member = Some(member.clone());
+            }
+            Operation::DetachMember => {
+                if self.member.take().is_none() {
+                    return Err(TerminalError::NoMember);
+                }
+
+                // the points were the member's
+                self.points.clear();
+            }
+            Operation::RedeemPoints { points, redemption } => {
+                if self.member.is_none() {
+                    return Err(TerminalError::NoMember);
+                }
+
+                self.points.push((*points, *redemption));
+            }
+            Operation::ExemptTax(exemption) => self.exemption = Some(exemption.clone()),
+            Operation::Tender(tender) => self.tender = Some(*tender),
+            Operation::ChargeTax => {
+                if self.exemption.take().is_none() {
+                    return Err(TerminalError::NotExempt);
+                }
+            }
+            // worked out by the journal before operations get here
+            Operation::Undo | Operation::Redo => {}
+        }
+
+        Ok(())
+    }
+
+    /// The points redeemed one way, all together.
+    pub(crate) fn redeemed(&self, redemption: Redemption) -> Result<u64, TerminalError> {
+        self.points
+            .iter()
+            .filter(|(_, r)| *r == redemption)
+            .try_fold(0_u64, |total, (points, _)| total.checked_add(*points))
+            .ok_or(TerminalError::Overflow)
+    }
+
+    fn redeem(&mut self, catalog: &Catalog, coupon: Coupon) -> Result<(), TerminalError> {
+        let uses = self
+            .coupons
+            .iter()
+            .filter(|c| c.code == coupon.code)
+            .count()
+            + 1;
+
+        if uses > coupon.limit {
+            return Err(TerminalError::CouponLimitReached(coupon.code));
+        }
+
+        if !self.qualifies(catalog, &coupon, uses)? {
+            return Err(TerminalError::CouponNotEligible(coupon.code));
+        }
+
+        self.coupons.push(coupon);
+
+        Ok(())
+    }
+
+    /// Makes a change that can take the basket below what its coupons need, on a copy so that
+    /// nothing changes unless the change and dropping the coupons both work.
+    fn shrink<F>(&mut self, catalog: &Catalog, change: F) -> Result<(), TerminalError>
+    where
+        F: FnOnce(&mut Basket) -> Result<(), TerminalError>,
+    {
+        let mut basket = self.clone();
+
+        change(&mut basket)?;
+        basket.drop_coupons(catalog)?;
+
+        *self = basket;
+
+        Ok(())
+    }
+
+    /// Takes out coupons whose requirement isn't met any more, later uses of a coupon going first.
+    fn drop_coupons(&mut self, catalog: &Catalog) -> Result<(), TerminalError> {
+        let redeemed = std::mem::take(&mut self.coupons);
+
+        for coupon in redeemed {
+            let uses = self
+                .coupons
+                .iter()
+                .filter(|c| c.code == coupon.code)
+                .count()
+                + 1;
+
+            if self.qualifies(catalog, &coupon, uses)? {
+                self.coupons.push(coupon);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Whether the basket meets `coupon`'s requirement `uses` times over.
+    fn qualifies(
+        &self,
+        catalog: &Catalog,
+        coupon: &Coupon,
+        uses: usize,
+    ) -> Result<bool, TerminalError> {
+        let priced = |item: &Sku| -> Vec<Decimal> {
+            self.priced
+                .iter()
+                .filter(|(p, _)| p == item)
+                .map(|(_, price)| *price)
+                .collect()
+        };
+
+        match coupon.requirement {
+            Requirement::Units(units) => {
+                let found: usize = coupon
+                    .items
+                    .iter()
+                    .map(|item| {
+                        self.items.get(item).copied().unwrap_or(0)
+                            + self.weights.contains_key(item) as usize
+                            + priced(item).len()
+                    })
+                    .sum();
+
+                Ok(units
+                    .checked_mul(uses)
+                    .is_some_and(|needed| found >= needed))
+            }
+            Requirement::Spend(spend) => {
+                let mut amounts = vec![];
+
+                for item in &coupon.items {
+                    let quantity = match (self.items.get(item), self.weights.get(item)) {
+                        (Some(count), _) => Decimal::from(*count as u64),
+                        (None, Some(weight)) => *weight,
+                        (None, None) => Decimal::ZERO,
+                    };
+
+                    if !quantity.is_zero() {
+                        amounts.push(
+                            base_price(item, lookup(catalog, item)?)?
+                                .checked_mul(quantity)
+                                .ok_or(TerminalError::Overflow)?,
+                        );
+                    }
+
+                    amounts.extend(priced(item));
+                }
+
+                let needed = spend
+                    .checked_mul(Decimal::from(uses as u64))
+                    .ok_or(TerminalError::Overflow)?;
+
+                Ok(checked_sum(amounts)? >= needed)
+            }
+        }
+    }
+
+    /// An override only lasts while its item is in the basket.
+    fn forget_override(&mut self, item: &Sku) {
+        if !self.items.contains_key(item) && !self.weights.contains_key(item) {
+            self.overrides.remove(item);
+        }
+    }
+}
+
+/// Makes sure `item` is in the catalog and sold by `Each`.
+fn counted(catalog: &Catalog, item: &Sku) -> Result<(), TerminalError> {
+    let unit = lookup(catalog, item)?.unit;
+
+    if unit != Unit::Each {
+        return Err(TerminalError::WrongUnit {
+            item: item.clone(),
+            unit,
+        });
+    }
+
+    Ok(())
+}
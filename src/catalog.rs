@@ -0,0 +1,412 @@
+use crate::member::Level;
+use crate::tax::Category;
+use crate::{Price, Sku, TerminalError};
+
+use rust_decimal::Decimal;
+
+use std::collections::HashMap;
+use std::fmt;
+use std::time::SystemTime;
+
+/// Every item a terminal can sell.
+pub type Catalog = HashMap<Sku, Item>;
+
+/// The unit an item is sold by. Items sold by `Each` are counted, everything else is weighed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Unit {
+    Each,
+    Kg,
+    Lb,
+    Oz,
+}
+
+impl Unit {
+    pub fn is_weight(self) -> bool {
+        self != Unit::Each
+    }
+
+    /// Kilograms in one of this unit, `None` for `Each`.
+    fn kg(self) -> Option<Decimal> {
+        match self {
+            Unit::Each => None,
+            Unit::Kg => Some(Decimal::ONE),
+            Unit::Lb => Some(Decimal::new(45_359_237, 8)),
+            Unit::Oz => Some(Decimal::new(45_359_237, 8) / Decimal::from(16)),
+        }
+    }
+
+    /// Converts `amount` of this unit into `to`, `None` if either of them isn't a weight.
+    ///
+    /// ```
+    /// # #[macro_use] extern crate rust_decimal_macros; fn main() {
+    ///     use scanner_terminal::Unit;
+    ///
+    ///     assert_eq!(Unit::Oz.convert(dec!(32), Unit::Lb), Some(dec!(2)));
+    ///     assert_eq!(Unit::Lb.convert(dec!(1), Unit::Kg), Some(dec!(0.45359237)));
+    ///     assert_eq!(Unit::Each.convert(dec!(1), Unit::Kg), None);
+    /// # }
+    /// ```
+    pub fn convert(self, amount: Decimal, to: Unit) -> Option<Decimal> {
+        if self == to {
+            return Some(amount);
+        }
+
+        amount.checked_mul(self.kg()?)?.checked_div(to.kg()?)
+    }
+}
+
+impl fmt::Display for Unit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Unit::Each => "ea",
+            Unit::Kg => "kg",
+            Unit::Lb => "lb",
+            Unit::Oz => "oz",
+        };
+
+        write!(f, "{}", name)
+    }
+}
+
+/// An item in the catalog. Counted items use all of their price tiers, weighed items are charged
+/// their `min: 0` price per unit of weight.
+///
+/// A `Vec<Price>` converts into an item sold by `Each`, so plain pricing can still be given to
+/// `Terminal::new()`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Item {
+    pub prices: Vec<Price>,
+    pub unit: Unit,
+    /// How the item is taxed, `General` unless set.
+    pub tax: Category,
+}
+
+impl Item {
+    pub fn new(prices: Vec<Price>) -> Self {
+        Item {
+            prices,
+            unit: Unit::Each,
+            tax: Category::General,
+        }
+    }
+
+    /// An item sold by weight at `price` per `unit`.
+    pub fn weighed(unit: Unit, price: Decimal) -> Self {
+        Item {
+            prices: vec![Price::new(0, price)],
+            unit,
+            tax: Category::General,
+        }
+    }
+
+    pub fn tax(mut self, category: Category) -> Self {
+        self.tax = category;
+        self
+    }
+
+    /// The `min: 0` price, charged per unit. The regular price is used over scheduled and member
+    /// ones, use `at()` first for the price at a given time and for a given member.
+    pub fn base_price(&self) -> Option<Decimal> {
+        self.prices
+            .iter()
+            .filter(|p| p.min == 0)
+            .min_by_key(|p| !p.is_regular())
+            .map(|p| p.price)
+    }
+
+    /// The item as it is priced at `time`, `utc_offset` minutes east of UTC, for a member at
+    /// `level` (`None` for customers who aren't members). Prices scheduled for other times or
+    /// kept for higher levels are left out, and a scheduled or member price that applies
+    /// replaces the regular price for the same `min`. When more than one of those applies for a
+    /// `min` the cheapest is used.
+    ///
+    /// ```
+    /// # #[macro_use] extern crate rust_decimal_macros; fn main() {
+    ///     use scanner_terminal::schedule::Schedule;
+    ///     use scanner_terminal::{Item, Price};
+    ///
+    ///     use std::time::{Duration, UNIX_EPOCH};
+    ///
+    ///     let ad_starts = UNIX_EPOCH + Duration::from_secs(1_790_000_000);
+    ///
+    ///     let item = Item::new(vec![
+    ///         Price::new(0, dec!(2)),
+    ///         Price::new(4, dec!(7)),
+    ///         Price::new(4, dec!(6)).during(Schedule::new().starts(ad_starts)),
+    ///     ]);
+    ///
+    ///     assert_eq!(item.at(ad_starts - Duration::from_secs(1), 0, None).prices, vec![Price::new(0, dec!(2)), Price::new(4, dec!(7))]);
+    ///     assert_eq!(item.at(ad_starts, 0, None).prices[1].price, dec!(6));
+    /// # }
+    /// ```
+    pub fn at(&self, time: SystemTime, utc_offset: i32, level: Option<Level>) -> Item {
+        let mut prices: Vec<Price> = vec![];
+
+        for p in &self.prices {
+            let in_effect = p
+                .schedule
+                .as_ref()
+                .is_none_or(|s| s.contains(time, utc_offset));
+
+            if !in_effect || p.level > level {
+                continue;
+            }
+
+            match prices.iter_mut().find(|q| q.min == p.min) {
+                Some(q) if !p.is_regular() && (q.is_regular() || p.price < q.price) => {
+                    *q = p.clone()
+                }
+                Some(_) => {}
+                None => prices.push(p.clone()),
+            }
+        }
+
+        Item {
+            prices,
+            unit: self.unit,
+            tax: self.tax,
+        }
+    }
+}
+
+impl From<Vec<Price>> for Item {
+    fn from(prices: Vec<Price>) -> Self {
+        Item::new(prices)
+    }
+}
+
+pub(crate) fn lookup<'a>(catalog: &'a Catalog, item: &Sku) -> Result<&'a Item, TerminalError> {
+    catalog
+        .get(item)
+        .ok_or_else(|| TerminalError::UnknownItem(item.clone()))
+}
+
+/// How bad a catalog problem is. Errors keep a `Terminal` from being built, warnings are kept on
+/// the terminal so they can be looked at later.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// A single problem found while validating a catalog.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IssueKind {
+    /// There is no regular `min: 0` price, so some counts can't always be priced.
+    MissingBasePrice,
+    /// More than one regular tier, or more than one tier for the same schedule and member level,
+    /// was given for the same `min`.
+    DuplicateMin(usize),
+    /// The tier for `min` has a price below zero.
+    NegativePrice { min: usize, price: Decimal },
+    /// A weighed item has a tier other than `min: 0`, tiers only apply to counted items.
+    TierOnWeighedItem(usize),
+    /// Buying the `min` units of this tier costs more than buying them one at a time.
+    BulkCostsMore {
+        min: usize,
+        price: Decimal,
+        singles: Decimal,
+    },
+}
+
+impl IssueKind {
+    pub fn severity(&self) -> Severity {
+        match self {
+            IssueKind::BulkCostsMore { .. } => Severity::Warning,
+            _ => Severity::Error,
+        }
+    }
+}
+
+impl fmt::Display for IssueKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            IssueKind::MissingBasePrice => write!(f, "no price at min: 0"),
+            IssueKind::DuplicateMin(min) => write!(f, "more than one price at min: {}", min),
+            IssueKind::TierOnWeighedItem(min) => {
+                write!(f, "price at min: {} on an item sold by weight", min)
+            }
+            IssueKind::NegativePrice { min, price } => {
+                write!(f, "negative price {} at min: {}", price, min)
+            }
+            IssueKind::BulkCostsMore {
+                min,
+                price,
+                singles,
+            } => write!(
+                f,
+                "{} for {} costs more than {} bought one at a time",
+                price, min, singles
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CatalogIssue {
+    pub item: Sku,
+    pub kind: IssueKind,
+}
+
+impl CatalogIssue {
+    pub fn severity(&self) -> Severity {
+        self.kind.severity()
+    }
+}
+
+impl fmt::Display for CatalogIssue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let severity = match self.severity() {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+
+        write!(f, "{}: item {}: {}", severity, self.item, self.kind)
+    }
+}
+
+/// Every problem found in a catalog, errors first, then ordered by item.
+///
+/// ```
+/// # #[macro_use] extern crate rust_decimal_macros; fn main() {
+///     use scanner_terminal::{validate, Catalog, IssueKind, Price};
+///
+///     let mut prices = Catalog::new();
+///
+///     prices.insert('A'.into(), vec![Price::new(4, dec!(7)), Price::new(4, dec!(6))].into());
+///     prices.insert('B'.into(), vec![Price::new(0, dec!(1)), Price::new(3, dec!(4))].into());
+///
+///     let report = validate(&prices);
+///
+///     assert!(!report.is_valid());
+///     assert_eq!(report.errors().count(), 2);
+///     assert_eq!(report.warnings().next().unwrap().kind, IssueKind::BulkCostsMore { min: 3, price: dec!(4), singles: dec!(3) });
+/// # }
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CatalogReport {
+    issues: Vec<CatalogIssue>,
+}
+
+impl CatalogReport {
+    pub(crate) fn missing_base_price(item: Sku) -> Self {
+        CatalogReport {
+            issues: vec![CatalogIssue {
+                item,
+                kind: IssueKind::MissingBasePrice,
+            }],
+        }
+    }
+
+    pub fn issues(&self) -> &[CatalogIssue] {
+        &self.issues
+    }
+
+    pub fn errors(&self) -> impl Iterator<Item = &CatalogIssue> {
+        self.issues
+            .iter()
+            .filter(|i| i.severity() == Severity::Error)
+    }
+
+    pub fn warnings(&self) -> impl Iterator<Item = &CatalogIssue> {
+        self.issues
+            .iter()
+            .filter(|i| i.severity() == Severity::Warning)
+    }
+
+    /// A catalog is valid as long as it has no errors, warnings are allowed.
+    pub fn is_valid(&self) -> bool {
+        self.errors().next().is_none()
+    }
+}
+
+impl fmt::Display for CatalogReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, issue) in self.issues.iter().enumerate() {
+            if i > 0 {
+                write!(f, "; ")?;
+            }
+
+            write!(f, "{}", issue)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Checks all of the pricing in a catalog and reports every problem found, rather than stopping
+/// at the first one.
+pub fn validate(catalog: &Catalog) -> CatalogReport {
+    let mut issues = vec![];
+
+    for (item, entry) in catalog {
+        let tiers = &entry.prices;
+
+        let mut found = |kind| {
+            issues.push(CatalogIssue {
+                item: item.clone(),
+                kind,
+            })
+        };
+
+        let base = tiers
+            .iter()
+            .find(|p| p.min == 0 && p.is_regular())
+            .map(|p| p.price);
+
+        if base.is_none() {
+            found(IssueKind::MissingBasePrice);
+        }
+
+        let mut mins: Vec<usize> = vec![];
+
+        for (i, p) in tiers.iter().enumerate() {
+            if tiers[..i]
+                .iter()
+                .any(|q| q.min == p.min && q.schedule == p.schedule && q.level == p.level)
+            {
+                mins.push(p.min);
+            }
+        }
+
+        mins.sort_unstable();
+        mins.dedup();
+
+        for min in mins {
+            found(IssueKind::DuplicateMin(min));
+        }
+
+        for p in tiers {
+            if p.price.is_sign_negative() && !p.price.is_zero() {
+                found(IssueKind::NegativePrice {
+                    min: p.min,
+                    price: p.price,
+                });
+            }
+        }
+
+        if entry.unit.is_weight() {
+            for p in tiers.iter().filter(|p| p.min > 0) {
+                found(IssueKind::TierOnWeighedItem(p.min));
+            }
+        } else if let Some(base) = base {
+            for p in tiers.iter().filter(|p| p.min > 1) {
+                let singles = base.checked_mul(Decimal::from(p.min as u64));
+
+                if let Some(singles) = singles.filter(|singles| p.price > *singles) {
+                    found(IssueKind::BulkCostsMore {
+                        min: p.min,
+                        price: p.price,
+                        singles,
+                    });
+                }
+            }
+        }
+    }
+
+    issues.sort_by(|a, b| (a.severity(), &a.item).cmp(&(b.severity(), &b.item)));
+
+    issues.dedup();
+
+    CatalogReport { issues }
+}
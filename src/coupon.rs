@@ -0,0 +1,388 @@
+//! Coupons a customer hands over at the lane, either set up by the store and typed in by code or
+//! read from a GS1 DataBar coupon barcode.
+//!
+//! ```
+//!     use scanner_terminal::coupon::{self, Requirement};
+//!     use scanner_terminal::Discount;
+//!
+//!     use rust_decimal_macros::dec;
+//!
+//!     // 75 cents off when buying 2 of company 0036000's products
+//!     let databar = coupon::parse("(8110)10036000123456275120000").unwrap();
+//!
+//!     assert_eq!(databar.company_prefix, "0036000");
+//!     assert_eq!(databar.discount().unwrap(), Discount::Amount(dec!(0.75)));
+//!     assert_eq!(databar.requirement().unwrap(), Requirement::Units(2));
+//! ```
+
+use crate::{Catalog, Discount, Sku, TerminalError};
+
+use rust_decimal::Decimal;
+
+use std::error::Error;
+use std::fmt;
+
+/// What has to be in the basket before a coupon can be used.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Requirement {
+    /// At least this many units of the coupon's items. Weighed items and barcode priced lines
+    /// count as one unit each.
+    Units(usize),
+    /// At least this much spent on the coupon's items, at their `min: 0` prices.
+    Spend(Decimal),
+}
+
+/// A coupon, worth `discount` off the coupon's items once `requirement` is met.
+///
+/// ```
+/// # #[macro_use] extern crate scanner_terminal; #[macro_use] extern crate rust_decimal_macros; fn main() -> Result<(), scanner_terminal::TerminalError> {
+///     use scanner_terminal::coupon::Coupon;
+///     use scanner_terminal::{Discount, Terminal, TerminalError, Price};
+///
+///     let mut terminal = setup_pricing!('A' => [{ price: 4 }]; 'B' => [{ price: 3 }])?;
+///
+///     terminal.add_coupon(Coupon::new("SAVE1", "$1 off A", vec!['A'], Discount::Amount(dec!(1))))?;
+///
+///     assert_eq!(terminal.redeem("SAVE1"), Err(TerminalError::CouponNotEligible("SAVE1".to_string())));
+///
+///     terminal.scan('A')?;
+///     terminal.scan('B')?;
+///     terminal.redeem("SAVE1")?;
+///
+///     assert_eq!(terminal.redeem("SAVE1"), Err(TerminalError::CouponLimitReached("SAVE1".to_string())));
+///     assert_eq!(terminal.total()?, dec!(6));
+///
+///     // without an A the coupon goes as well
+///     terminal.void('A')?;
+///
+///     assert_eq!(terminal.total()?, dec!(3));
+///     assert!(terminal.receipt()?.adjustments().is_empty());
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Coupon {
+    /// What the cashier types in, or the data of the scanned barcode.
+    pub code: String,
+    /// What the customer sees on the receipt.
+    pub label: String,
+    /// The items the coupon can be used on.
+    pub items: Vec<Sku>,
+    pub requirement: Requirement,
+    pub discount: Discount,
+    /// How many times the coupon can be used in one transaction, each use needing its own
+    /// `requirement` to be met.
+    pub limit: usize,
+}
+
+impl Coupon {
+    /// A coupon that can be used once per transaction on one unit of any of `items`.
+    pub fn new<C, L, I, K>(code: C, label: L, items: I, discount: Discount) -> Self
+    where
+        C: Into<String>,
+        L: Into<String>,
+        I: IntoIterator<Item = K>,
+        K: Into<Sku>,
+    {
+        Coupon {
+            code: code.into(),
+            label: label.into(),
+            items: items.into_iter().map(Into::into).collect(),
+            requirement: Requirement::Units(1),
+            discount,
+            limit: 1,
+        }
+    }
+
+    pub fn requirement(mut self, requirement: Requirement) -> Self {
+        self.requirement = requirement;
+        self
+    }
+
+    pub fn limit(mut self, limit: usize) -> Self {
+        self.limit = limit;
+        self
+    }
+
+    /// Checks the coupon only names items from `catalog` and can actually be used.
+    pub(crate) fn validate(&self, catalog: &Catalog) -> Result<(), TerminalError> {
+        let invalid = |reason: String| TerminalError::InvalidPromotion {
+            name: self.code.clone(),
+            reason,
+        };
+
+        if self.items.is_empty() || self.limit == 0 {
+            return Err(invalid("needs at least one item and use".to_string()));
+        }
+
+        if let Some(item) = self.items.iter().find(|item| !catalog.contains_key(item)) {
+            return Err(invalid(format!("unknown item {}", item)));
+        }
+
+        match self.requirement {
+            Requirement::Units(0) => {
+                return Err(invalid("needs at least one unit".to_string()));
+            }
+            Requirement::Spend(spend) if spend.is_sign_negative() && !spend.is_zero() => {
+                return Err(invalid(format!("negative spend {}", spend)));
+            }
+            _ => {}
+        }
+
+        match self.discount {
+            Discount::Amount(amount) if amount <= Decimal::ZERO => {
+                Err(invalid(format!("{} off is out of range", amount)))
+            }
+            Discount::Percent(percent)
+                if percent <= Decimal::ZERO || percent > Decimal::ONE_HUNDRED =>
+            {
+                Err(invalid(format!("{}% off is out of range", percent)))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Why coupon barcode data couldn't be read.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CouponError {
+    /// The data doesn't start with the coupon application identifier (8110).
+    NotACoupon,
+    InvalidCharacter(char),
+    /// The data ended in the middle of a field.
+    Truncated,
+    /// A length indicator was outside the range allowed for its field.
+    InvalidLength {
+        field: &'static str,
+        len: u8,
+    },
+    /// The coupon uses a feature the terminal can't apply.
+    Unsupported(&'static str),
+}
+
+impl fmt::Display for CouponError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CouponError::NotACoupon => write!(f, "not a GS1 coupon"),
+            CouponError::InvalidCharacter(c) => write!(f, "invalid character {:?}", c),
+            CouponError::Truncated => write!(f, "coupon data ends too soon"),
+            CouponError::InvalidLength { field, len } => {
+                write!(f, "invalid length {} for {}", len, field)
+            }
+            CouponError::Unsupported(feature) => write!(f, "unsupported {}", feature),
+        }
+    }
+}
+
+impl Error for CouponError {}
+
+/// The fields of a GS1 DataBar coupon (application identifier 8110), see `parse()`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DataBar {
+    /// The GS1 company prefix of the products the coupon is for, 6 to 12 digits.
+    pub company_prefix: String,
+    pub offer_code: String,
+    pub save_value: u32,
+    /// 0 for cents off, 5 for percent off.
+    pub save_value_code: u8,
+    pub requirement: u32,
+    /// 0 for units, 1 for cents spent.
+    pub requirement_code: u8,
+    pub family_code: String,
+    /// YYMMDD, read but not checked by the terminal.
+    pub starts: Option<String>,
+    /// YYMMDD, read but not checked by the terminal.
+    pub expires: Option<String>,
+    pub serial_number: Option<String>,
+    pub retailer_id: Option<String>,
+}
+
+impl DataBar {
+    pub fn discount(&self) -> Result<Discount, CouponError> {
+        match self.save_value_code {
+            0 => Ok(Discount::Amount(Decimal::new(self.save_value.into(), 2))),
+            5 => Ok(Discount::Percent(self.save_value.into())),
+            _ => Err(CouponError::Unsupported("save value code")),
+        }
+    }
+
+    pub fn requirement(&self) -> Result<Requirement, CouponError> {
+        match self.requirement_code {
+            0 => Ok(Requirement::Units(self.requirement as usize)),
+            1 => Ok(Requirement::Spend(Decimal::new(self.requirement.into(), 2))),
+            _ => Err(CouponError::Unsupported("purchase requirement code")),
+        }
+    }
+
+    /// A coupon for every catalog item whose GTIN carries the coupon's company prefix. Family
+    /// codes aren't kept in the catalog, so the coupon can be used on any of the company's items.
+    pub fn coupon(&self, code: &str, catalog: &Catalog) -> Result<Coupon, CouponError> {
+        let mut items: Vec<Sku> = catalog
+            .keys()
+            .filter(|sku| {
+                let digits = sku.as_str();
+
+                digits.len() == 14
+                    && digits.bytes().all(|b| b.is_ascii_digit())
+                    && digits[1..].starts_with(&self.company_prefix)
+            })
+            .cloned()
+            .collect();
+
+        items.sort();
+
+        Ok(Coupon {
+            code: code.to_string(),
+            label: format!("Coupon {}", self.offer_code),
+            items,
+            requirement: self.requirement()?,
+            discount: self.discount()?,
+            limit: 1,
+        })
+    }
+}
+
+/// Reads the data of a GS1 DataBar coupon, with or without the scanner's `]e0` symbology
+/// identifier and with the 8110 application identifier in brackets or not. Coupons with a second
+/// or third purchase requirement are rejected as unsupported.
+pub fn parse(data: &str) -> Result<DataBar, CouponError> {
+    let data = data.strip_prefix("]e0").unwrap_or(data);
+
+    let data = data
+        .strip_prefix("(8110)")
+        .or_else(|| data.strip_prefix("8110"))
+        .ok_or(CouponError::NotACoupon)?;
+
+    if let Some(c) = data.chars().find(|c| !c.is_ascii_digit()) {
+        return Err(CouponError::InvalidCharacter(c));
+    }
+
+    let mut fields = Fields(data);
+
+    let prefix_len = fields.indicator("company prefix", 0..=6)? + 6;
+    let company_prefix = fields.take(prefix_len as usize)?.to_string();
+    let offer_code = fields.take(6)?.to_string();
+
+    let save_len = fields.indicator("save value", 1..=5)?;
+    let save_value = fields.number(save_len as usize)?;
+
+    let requirement_len = fields.indicator("purchase requirement", 1..=5)?;
+    let requirement = fields.number(requirement_len as usize)?;
+    let requirement_code = fields.number(1)? as u8;
+    let family_code = fields.take(3)?.to_string();
+
+    let mut databar = DataBar {
+        company_prefix,
+        offer_code,
+        save_value,
+        save_value_code: 0,
+        requirement,
+        requirement_code,
+        family_code,
+        starts: None,
+        expires: None,
+        serial_number: None,
+        retailer_id: None,
+    };
+
+    while !fields.0.is_empty() {
+        match fields.number(1)? {
+            1 | 2 => return Err(CouponError::Unsupported("additional purchase requirements")),
+            3 => databar.expires = Some(fields.take(6)?.to_string()),
+            4 => databar.starts = Some(fields.take(6)?.to_string()),
+            5 => {
+                let len = fields.indicator("serial number", 0..=9)? + 6;
+
+                databar.serial_number = Some(fields.take(len as usize)?.to_string());
+            }
+            6 => {
+                let len = fields.indicator("retailer ID", 1..=7)? + 6;
+
+                databar.retailer_id = Some(fields.take(len as usize)?.to_string());
+            }
+            9 => {
+                databar.save_value_code = fields.number(1)? as u8;
+
+                // what the value applies to, the store coupon flag and the don't multiply flag
+                fields.take(3)?;
+            }
+            _ => return Err(CouponError::Unsupported("optional field")),
+        }
+    }
+
+    Ok(databar)
+}
+
+/// The part of the coupon data still to be read.
+struct Fields<'a>(&'a str);
+
+impl<'a> Fields<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a str, CouponError> {
+        if self.0.len() < len {
+            return Err(CouponError::Truncated);
+        }
+
+        let (field, rest) = self.0.split_at(len);
+
+        self.0 = rest;
+
+        Ok(field)
+    }
+
+    fn number(&mut self, len: usize) -> Result<u32, CouponError> {
+        self.take(len)?.parse().map_err(|_| CouponError::Truncated)
+    }
+
+    /// A one digit length indicator, which has to be in `range`.
+    fn indicator(
+        &mut self,
+        field: &'static str,
+        range: std::ops::RangeInclusive<u8>,
+    ) -> Result<u8, CouponError> {
+        let len = self.number(1)? as u8;
+
+        if !range.contains(&len) {
+            return Err(CouponError::InvalidLength { field, len });
+        }
+
+        Ok(len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_reads_optional_fields() {
+        // 20% off a purchase of $5.00, expiring 31 December 2027, with a serial number
+        let databar = parse("]e081101003600012345622035001000327123151123456795000").unwrap();
+
+        assert_eq!(databar.offer_code, "123456");
+        assert_eq!(databar.expires.as_deref(), Some("271231"));
+        assert_eq!(databar.serial_number.as_deref(), Some("1234567"));
+        assert_eq!(databar.discount(), Ok(Discount::Percent(20.into())));
+        assert_eq!(
+            databar.requirement(),
+            Ok(Requirement::Spend(Decimal::new(500, 2)))
+        );
+    }
+
+    #[test]
+    fn it_rejects_bad_data() {
+        assert_eq!(parse("0036000291452"), Err(CouponError::NotACoupon));
+        assert_eq!(parse("8110100360001234"), Err(CouponError::Truncated));
+        assert_eq!(
+            parse("81107"),
+            Err(CouponError::InvalidLength {
+                field: "company prefix",
+                len: 7
+            })
+        );
+        assert_eq!(parse("8110x"), Err(CouponError::InvalidCharacter('x')));
+        assert_eq!(
+            parse("811010036000123456275120000100"),
+            Err(CouponError::Unsupported("additional purchase requirements"))
+        );
+    }
+}
@@ -0,0 +1,137 @@
+use crate::barcode::BarcodeError;
+use crate::coupon::CouponError;
+use crate::{CatalogReport, Sku, Unit};
+
+use rust_decimal::Decimal;
+
+use std::error::Error;
+use std::fmt;
+
+/// Everything that can go wrong while setting up or running a `Terminal`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TerminalError {
+    /// The item has no entry in the terminal's pricing.
+    UnknownItem(Sku),
+    /// The pricing given to the terminal has errors, the report lists all of them.
+    InvalidCatalog(CatalogReport),
+    /// A promotion can't be used with this terminal's catalog.
+    InvalidPromotion { name: String, reason: String },
+    /// The item was voided but it isn't in the terminal.
+    NotInBasket(Sku),
+    /// The item is sold by `unit`, so it was counted when it should have been weighed or the
+    /// other way around.
+    WrongUnit { item: Sku, unit: Unit },
+    /// Weights have to be above zero.
+    InvalidWeight(Decimal),
+    /// Quantities can't be more than `MAX_QUANTITY`.
+    InvalidQuantity(usize),
+    /// Prices given at the terminal can't be negative.
+    InvalidPrice(Decimal),
+    /// There is nothing left to undo.
+    NothingToUndo,
+    /// There is nothing left to redo.
+    NothingToRedo,
+    /// A saved journal couldn't be read back.
+    InvalidJournal { line: usize, reason: String },
+    /// A scanned barcode couldn't be read, including when its check digit is wrong.
+    InvalidBarcode(BarcodeError),
+    /// Scanned coupon data couldn't be read.
+    InvalidCoupon(CouponError),
+    /// No coupon has been set up with this code.
+    UnknownCoupon(String),
+    /// What is in the basket doesn't meet the coupon's requirement.
+    CouponNotEligible(String),
+    /// The coupon has been used as many times as one transaction allows.
+    CouponLimitReached(String),
+    /// A member was taken off a transaction that didn't have one, or points were redeemed
+    /// without a member.
+    NoMember,
+    /// Points were redeemed at a terminal without a loyalty program.
+    NoLoyaltyProgram,
+    /// The member doesn't have the points being redeemed.
+    NotEnoughPoints { member: String, balance: i64 },
+    /// A member's points ledger couldn't be read or written.
+    Ledger { member: String, reason: String },
+    /// The transaction is already in the member's ledger, or was already returned.
+    TransactionInLedger(String),
+    /// A return was made for a transaction that isn't in the member's ledger.
+    UnknownTransaction(String),
+    /// A tax exemption was given without the number of its certificate.
+    MissingCertificate,
+    /// Tax was charged again on a transaction that wasn't exempt from it.
+    NotExempt,
+    /// The rounding can't be applied, the reason says why.
+    InvalidRounding(String),
+    /// Tax rates can't be negative.
+    InvalidTaxRate { jurisdiction: String, rate: Decimal },
+    /// A count or an amount got too large to be represented.
+    Overflow,
+}
+
+impl fmt::Display for TerminalError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TerminalError::UnknownItem(item) => write!(f, "unknown item {}", item),
+            TerminalError::InvalidCatalog(reason) => write!(f, "invalid catalog: {}", reason),
+            TerminalError::InvalidPromotion { name, reason } => {
+                write!(f, "invalid promotion {}: {}", name, reason)
+            }
+            TerminalError::NotInBasket(item) => write!(f, "item {} hasn't been scanned", item),
+            TerminalError::WrongUnit { item, unit } => {
+                write!(f, "item {} is sold by {}", item, unit)
+            }
+            TerminalError::InvalidWeight(weight) => write!(f, "invalid weight {}", weight),
+            TerminalError::InvalidQuantity(quantity) => write!(f, "invalid quantity {}", quantity),
+            TerminalError::InvalidPrice(price) => write!(f, "invalid price {}", price),
+            TerminalError::NothingToUndo => write!(f, "nothing to undo"),
+            TerminalError::NothingToRedo => write!(f, "nothing to redo"),
+            TerminalError::InvalidJournal { line, reason } => {
+                write!(f, "invalid journal at line {}: {}", line, reason)
+            }
+            TerminalError::InvalidBarcode(e) => write!(f, "invalid barcode: {}", e),
+            TerminalError::InvalidCoupon(e) => write!(f, "invalid coupon: {}", e),
+            TerminalError::UnknownCoupon(code) => write!(f, "unknown coupon {}", code),
+            TerminalError::CouponNotEligible(code) => {
+                write!(f, "coupon {} doesn't apply to this basket", code)
+            }
+            TerminalError::CouponLimitReached(code) => {
+                write!(f, "coupon {} can't be used again", code)
+            }
+            TerminalError::NoMember => write!(f, "no member on this transaction"),
+            TerminalError::NoLoyaltyProgram => write!(f, "no loyalty program"),
+            TerminalError::NotEnoughPoints { member, balance } => {
+                write!(f, "member {} only has {} points", member, balance)
+            }
+            TerminalError::Ledger { member, reason } => {
+                write!(f, "points ledger for member {}: {}", member, reason)
+            }
+            TerminalError::TransactionInLedger(transaction) => {
+                write!(f, "transaction {} is already in the ledger", transaction)
+            }
+            TerminalError::UnknownTransaction(transaction) => {
+                write!(f, "transaction {} isn't in the ledger", transaction)
+            }
+            TerminalError::MissingCertificate => write!(f, "no exemption certificate given"),
+            TerminalError::NotExempt => write!(f, "transaction isn't exempt from tax"),
+            TerminalError::InvalidRounding(reason) => write!(f, "invalid rounding: {}", reason),
+            TerminalError::InvalidTaxRate { jurisdiction, rate } => {
+                write!(f, "invalid tax rate {}% for {}", rate, jurisdiction)
+            }
+            TerminalError::Overflow => write!(f, "amount overflowed"),
+        }
+    }
+}
+
+impl Error for TerminalError {}
+
+impl From<BarcodeError> for TerminalError {
+    fn from(e: BarcodeError) -> Self {
+        TerminalError::InvalidBarcode(e)
+    }
+}
+
+impl From<CouponError> for TerminalError {
+    fn from(e: CouponError) -> Self {
+        TerminalError::InvalidCoupon(e)
+    }
+}
@@ -0,0 +1,400 @@
+use crate::loyalty::Redemption;
+use crate::member::{Level, Member};
+use crate::rounding::Tender;
+use crate::tax::{Category, Exemption};
+use crate::{Sku, TerminalError};
+
+use rust_decimal::Decimal;
+
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Something done at the terminal. Everything that changes what has been scanned is recorded as
+/// one of these, so the basket can always be rebuilt from the journal.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Operation {
+    /// One unit of a counted item.
+    Scan(Sku),
+    /// A weight of a weighed item, in the unit the item is sold by.
+    ScanWeight {
+        item: Sku,
+        weight: Decimal,
+    },
+    /// A line priced from its barcode.
+    ScanPriced {
+        item: Sku,
+        price: Decimal,
+    },
+    Void(Sku),
+    SetQuantity {
+        item: Sku,
+        quantity: usize,
+    },
+    /// Charges `price` per unit of the item instead of its tiers.
+    OverridePrice {
+        item: Sku,
+        price: Decimal,
+    },
+    Clear,
+    /// A coupon set up at the terminal, by its code.
+    Redeem(String),
+    /// A coupon read from its barcode.
+    ScanCoupon(String),
+    /// Prices the transaction for a loyalty member.
+    AttachMember(Member),
+    DetachMember,
+    /// Spends some of the member's loyalty points.
+    RedeemPoints {
+        points: u64,
+        redemption: Redemption,
+    },
+    /// Exempts the transaction from tax.
+    ExemptTax(Exemption),
+    ChargeTax,
+    /// How the customer is paying.
+    Tender(Tender),
+    /// Takes back the last operation that is still in effect.
+    Undo,
+    /// Puts back the last operation taken back by `Undo`.
+    Redo,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    /// Starts at 1 and goes up by one for every entry.
+    pub sequence: u64,
+    pub timestamp: SystemTime,
+    pub operation: Operation,
+}
+
+/// Every operation done at a terminal in the order it happened. Entries are only ever added,
+/// undoing something adds an `Undo` entry rather than removing the entry it undid.
+///
+/// A journal is saved as text with one tab separated entry per line, `to_string()` writes it out
+/// and `parse()` reads it back.
+///
+/// ```
+/// # #[macro_use] extern crate scanner_terminal; #[macro_use] extern crate rust_decimal_macros; fn main() -> Result<(), scanner_terminal::TerminalError> {
+///     use scanner_terminal::journal::Operation;
+///     use scanner_terminal::{Terminal, Price};
+///
+///     let mut terminal = setup_pricing!('A' => [{ price: 2 }]; 'B' => [{ price: 12 }])?;
+///
+///     terminal.scan('A')?;
+///     terminal.scan('B')?;
+///     terminal.undo()?;
+///
+///     assert_eq!(terminal.total()?, dec!(2));
+///
+///     let journal = terminal.journal();
+///
+///     assert_eq!(journal.entries().len(), 3);
+///     assert_eq!(journal.entries()[2].sequence, 3);
+///     assert_eq!(journal.entries()[2].operation, Operation::Undo);
+///     assert_eq!(journal.effective(), vec![&Operation::Scan('A'.into())]);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Journal {
+    entries: Vec<Entry>,
+}
+
+impl Journal {
+    pub fn new() -> Self {
+        Journal::default()
+    }
+
+    pub fn entries(&self) -> &[Entry] {
+        &self.entries
+    }
+
+    pub(crate) fn record(&mut self, operation: Operation, timestamp: SystemTime) {
+        let sequence = self.entries.len() as u64 + 1;
+
+        self.entries.push(Entry {
+            sequence,
+            timestamp,
+            operation,
+        });
+    }
+
+    /// The operations still in effect once every undo and redo has been worked out, in the order
+    /// they have to be applied.
+    pub fn effective(&self) -> Vec<&Operation> {
+        self.effective_entries()
+            .into_iter()
+            .map(|e| &e.operation)
+            .collect()
+    }
+
+    /// The entries of the operations in `effective()`, with when each one was done.
+    pub(crate) fn effective_entries(&self) -> Vec<&Entry> {
+        self.stacks().0
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.stacks().0.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.stacks().1.is_empty()
+    }
+
+    /// The operations in effect and the ones that have been undone, most recent last.
+    fn stacks(&self) -> (Vec<&Entry>, Vec<&Entry>) {
+        let mut done = vec![];
+        let mut undone = vec![];
+
+        for entry in &self.entries {
+            match &entry.operation {
+                Operation::Undo => undone.extend(done.pop()),
+                Operation::Redo => done.extend(undone.pop()),
+                _ => {
+                    done.push(entry);
+                    // a new operation means there's nothing left to redo
+                    undone.clear();
+                }
+            }
+        }
+
+        (done, undone)
+    }
+}
+
+impl Journal {
+    /// Reads a journal written out with `to_string()`. Entries have to be numbered from 1 with no
+    /// gaps, so a journal with lines missing is rejected.
+    ///
+    /// ```
+    /// # #[macro_use] extern crate scanner_terminal; #[macro_use] extern crate rust_decimal_macros; fn main() -> Result<(), scanner_terminal::TerminalError> {
+    ///     use scanner_terminal::journal::Journal;
+    ///     use scanner_terminal::{Terminal, Price};
+    ///
+    ///     let mut terminal = setup_pricing!('A' => [{ price: 2 }]; "BAN 001" => [{ price: 0.25 }])?;
+    ///
+    ///     terminal.scan('A')?;
+    ///     terminal.set_quantity("BAN 001", 6)?;
+    ///     terminal.override_price('A', dec!(1.75))?;
+    ///
+    ///     let saved = terminal.journal().to_string();
+    ///
+    ///     assert_eq!(&Journal::parse(&saved)?, terminal.journal());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn parse(text: &str) -> Result<Self, TerminalError> {
+        let mut journal = Journal::new();
+
+        for (i, line) in text.lines().enumerate().filter(|(_, l)| !l.is_empty()) {
+            let invalid = |reason: &str| TerminalError::InvalidJournal {
+                line: i + 1,
+                reason: reason.to_string(),
+            };
+
+            let fields: Vec<String> = line.split('\t').map(unescape).collect();
+
+            if fields.len() < 3 {
+                return Err(invalid("missing fields"));
+            }
+
+            let sequence: u64 = fields[0]
+                .parse()
+                .map_err(|_| invalid("bad sequence number"))?;
+
+            if sequence != journal.entries.len() as u64 + 1 {
+                return Err(invalid("out of sequence"));
+            }
+
+            let timestamp = parse_timestamp(&fields[1]).ok_or_else(|| invalid("bad timestamp"))?;
+
+            let args = &fields[3..];
+
+            let arg = |n: usize| args.get(n).ok_or_else(|| invalid("missing argument"));
+
+            let decimal = |n: usize| {
+                arg(n).and_then(|a| Decimal::from_str(a).map_err(|_| invalid("bad amount")))
+            };
+
+            let operation = match fields[2].as_str() {
+                "scan" => Operation::Scan(Sku::new(arg(0)?.as_str())),
+                "weight" => Operation::ScanWeight {
+                    item: Sku::new(arg(0)?.as_str()),
+                    weight: decimal(1)?,
+                },
+                "priced" => Operation::ScanPriced {
+                    item: Sku::new(arg(0)?.as_str()),
+                    price: decimal(1)?,
+                },
+                "void" => Operation::Void(Sku::new(arg(0)?.as_str())),
+                "quantity" => Operation::SetQuantity {
+                    item: Sku::new(arg(0)?.as_str()),
+                    quantity: arg(1)?.parse().map_err(|_| invalid("bad quantity"))?,
+                },
+                "override" => Operation::OverridePrice {
+                    item: Sku::new(arg(0)?.as_str()),
+                    price: decimal(1)?,
+                },
+                "clear" => Operation::Clear,
+                "redeem" => Operation::Redeem(arg(0)?.clone()),
+                "coupon" => Operation::ScanCoupon(arg(0)?.clone()),
+                "member" => Operation::AttachMember(Member::new(
+                    arg(0)?.as_str(),
+                    Level::parse(arg(1)?).ok_or_else(|| invalid("bad member level"))?,
+                )),
+                "nomember" => Operation::DetachMember,
+                "points" => Operation::RedeemPoints {
+                    points: arg(0)?.parse().map_err(|_| invalid("bad points"))?,
+                    redemption: Redemption::parse(arg(1)?)
+                        .ok_or_else(|| invalid("bad redemption"))?,
+                },
+                "exempt" => Operation::ExemptTax(Exemption {
+                    certificate: arg(0)?.clone(),
+                    reason: arg(1)?.clone(),
+                    categories: arg(2)?
+                        .split(',')
+                        .filter(|c| !c.is_empty())
+                        .map(|c| Category::parse(c).ok_or_else(|| invalid("bad tax category")))
+                        .collect::<Result<_, _>>()?,
+                }),
+                "tax" => Operation::ChargeTax,
+                "tender" => {
+                    Operation::Tender(Tender::parse(arg(0)?).ok_or_else(|| invalid("bad tender"))?)
+                }
+                "undo" => Operation::Undo,
+                "redo" => Operation::Redo,
+                _ => return Err(invalid("unknown operation")),
+            };
+
+            journal.entries.push(Entry {
+                sequence,
+                timestamp,
+                operation,
+            });
+        }
+
+        Ok(journal)
+    }
+}
+
+impl fmt::Display for Journal {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for entry in &self.entries {
+            write!(
+                f,
+                "{}\t{}\t",
+                entry.sequence,
+                format_timestamp(entry.timestamp)
+            )?;
+
+            match &entry.operation {
+                Operation::Scan(item) => write!(f, "scan\t{}", escape(item.as_str())),
+                Operation::ScanWeight { item, weight } => {
+                    write!(f, "weight\t{}\t{}", escape(item.as_str()), weight)
+                }
+                Operation::ScanPriced { item, price } => {
+                    write!(f, "priced\t{}\t{}", escape(item.as_str()), price)
+                }
+                Operation::Void(item) => write!(f, "void\t{}", escape(item.as_str())),
+                Operation::SetQuantity { item, quantity } => {
+                    write!(f, "quantity\t{}\t{}", escape(item.as_str()), quantity)
+                }
+                Operation::OverridePrice { item, price } => {
+                    write!(f, "override\t{}\t{}", escape(item.as_str()), price)
+                }
+                Operation::Clear => write!(f, "clear"),
+                Operation::Redeem(code) => write!(f, "redeem\t{}", escape(code)),
+                Operation::ScanCoupon(data) => write!(f, "coupon\t{}", escape(data)),
+                Operation::AttachMember(member) => {
+                    write!(f, "member\t{}\t{}", escape(&member.id), member.level)
+                }
+                Operation::DetachMember => write!(f, "nomember"),
+                Operation::RedeemPoints { points, redemption } => {
+                    write!(f, "points\t{}\t{}", points, redemption)
+                }
+                Operation::ExemptTax(exemption) => write!(
+                    f,
+                    "exempt\t{}\t{}\t{}",
+                    escape(&exemption.certificate),
+                    escape(&exemption.reason),
+                    exemption
+                        .categories
+                        .iter()
+                        .map(|c| c.to_string())
+                        .collect::<Vec<_>>()
+                        .join(",")
+                ),
+                Operation::ChargeTax => write!(f, "tax"),
+                Operation::Tender(tender) => write!(f, "tender\t{}", tender),
+                Operation::Undo => write!(f, "undo"),
+                Operation::Redo => write!(f, "redo"),
+            }?;
+
+            writeln!(f)?;
+        }
+
+        Ok(())
+    }
+}
+
+pub(crate) fn format_timestamp(timestamp: SystemTime) -> String {
+    let since_epoch = timestamp.duration_since(UNIX_EPOCH).unwrap_or_default();
+
+    format!(
+        "{}.{:09}",
+        since_epoch.as_secs(),
+        since_epoch.subsec_nanos()
+    )
+}
+
+/// Reads a timestamp written by `format_timestamp()`, `None` if it isn't one or is out of range.
+pub(crate) fn parse_timestamp(field: &str) -> Option<SystemTime> {
+    let digits = |part: &str| !part.is_empty() && part.bytes().all(|b| b.is_ascii_digit());
+
+    let mut parts = field.splitn(2, '.');
+
+    let secs = parts.next().filter(|s| digits(s))?;
+    let nanos = parts.next().unwrap_or("000000000");
+
+    // always written out as nanoseconds, so anything else is a typo rather than a fraction
+    if nanos.len() != 9 || !digits(nanos) {
+        return None;
+    }
+
+    UNIX_EPOCH
+        .checked_add(Duration::from_secs(secs.parse().ok()?))?
+        .checked_add(Duration::from_nanos(nanos.parse().ok()?))
+}
+
+/// Keeps tabs and line breaks in item and coupon codes from breaking up the saved fields. A
+/// carriage return is escaped too, since reading the lines back drops one at the end of a line.
+pub(crate) fn escape(field: &str) -> String {
+    field
+        .replace('\\', "\\\\")
+        .replace('\t', "\\t")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r")
+}
+
+pub(crate) fn unescape(field: &str) -> String {
+    let mut out = String::with_capacity(field.len());
+    let mut chars = field.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+
+        match chars.next() {
+            Some('t') => out.push('\t'),
+            Some('n') => out.push('\n'),
+            Some('r') => out.push('\r'),
+            Some(other) => out.push(other),
+            None => out.push('\\'),
+        }
+    }
+
+    out
+}
@@ -1,18 +1,91 @@
 use rust_decimal::Decimal;
 
-#[macro_use]
-extern crate rust_decimal_macros;
+pub mod barcode;
+mod basket;
+mod catalog;
+pub mod coupon;
+mod error;
+pub mod journal;
+pub mod loyalty;
+pub mod member;
+mod pricing;
+mod promotion;
+mod receipt;
+pub mod render;
+pub mod replay;
+pub mod rounding;
+pub mod schedule;
+mod sku;
+pub mod tax;
+
+use barcode::{Embedded, EmbeddedCodes};
+use basket::Basket;
+use catalog::lookup;
+use coupon::Coupon;
+use journal::{Journal, Operation};
+use loyalty::{Ledger, Redemption, Rules};
+use member::{Level, Member};
+use replay::SavedTransaction;
+use rounding::{Rounding, Scope, Tender};
+use schedule::{Clock, Schedule, SystemClock};
+use tax::{Exemption, TaxTable};
+
+pub use catalog::{
+    validate, Catalog, CatalogIssue, CatalogReport, IssueKind, Item, Severity, Unit,
+};
+pub use error::TerminalError;
+pub use promotion::{Conflict, Discount, Promotion, Reason, Rule};
+pub use receipt::{Adjustment, DealUse, Line, Pricing, Receipt, TierUse};
+pub use sku::Sku;
 
 use rust_decimal_macros::*;
 
 use std::collections::HashMap;
 
-use std::cmp::Ordering;
+use std::cmp::{Ordering, Reverse};
+use std::time::SystemTime;
+
+/// The most units of one counted item a terminal holds, well past any real basket.
+pub const MAX_QUANTITY: usize = 100_000;
 
 #[derive(Debug, Eq, PartialEq, Clone)]
 pub struct Price {
     pub min: usize,
     pub price: Decimal,
+    /// When the price is in effect, `None` for a regular price that always is.
+    pub schedule: Option<Schedule>,
+    /// Only charged to members at this level or above, `None` for everyone.
+    pub level: Option<Level>,
+}
+
+impl Price {
+    pub fn new(min: usize, price: Decimal) -> Self {
+        Price {
+            min,
+            price,
+            schedule: None,
+            level: None,
+        }
+    }
+
+    /// Only charges this price while `schedule` is in effect. A scheduled price replaces the
+    /// regular price for the same `min` for as long as it is, see `Terminal::set_clock()`.
+    pub fn during(mut self, schedule: Schedule) -> Self {
+        self.schedule = Some(schedule);
+        self
+    }
+
+    /// Only charges this price to members at `level` or above, in place of the regular price for
+    /// the same `min`, see `Terminal::attach_member()`.
+    pub fn members(mut self, level: Level) -> Self {
+        self.level = Some(level);
+        self
+    }
+
+    /// Whether the price is charged to everyone, all the time.
+    pub(crate) fn is_regular(&self) -> bool {
+        self.schedule.is_none() && self.level.is_none()
+    }
 }
 
 impl Ord for Price {
@@ -38,7 +111,7 @@ impl PartialOrd for Price {
 /// tiers taken into consideration.
 ///
 /// ```
-/// # #[macro_use] extern crate scanner_terminal; #[macro_use] extern crate rust_decimal_macros; fn main() {
+/// # #[macro_use] extern crate scanner_terminal; #[macro_use] extern crate rust_decimal_macros; fn main() -> Result<(), scanner_terminal::TerminalError> {
 ///     use scanner_terminal::{Terminal, Price};
 ///
 ///     use std::collections::HashMap;
@@ -46,203 +119,2290 @@ impl PartialOrd for Price {
 ///     let mut prices = HashMap::new();
 ///
 ///     // start rough equivalent of setup_pricing!('A' => [{ price: 2 }, { min: 4, price: 7 }]; 'B' => [{ price: 12 }]; 'C' => [{ price: 1.25 }, { min: 6, price: 6 }]; 'D' => [{ price: 0.15 }]);
-///     prices.insert('A', vec![Price{ min: 0, price: dec!(2) }, Price{ min: 4, price: dec!(7) }]);
-///     prices.insert('B', vec![Price{ min: 0, price: dec!(12) }]);
-///     prices.insert('C', vec![Price{ min: 0, price: dec!(1.25) }, Price{ min: 6, price: dec!(6) }]);
-///     prices.insert('D', vec![Price{ min: 0, price: dec!(0.15) }]);
+///     prices.insert('A', vec![Price::new(0, dec!(2)), Price::new(4, dec!(7))]);
+///     prices.insert('B', vec![Price::new(0, dec!(12))]);
+///     prices.insert('C', vec![Price::new(0, dec!(1.25)), Price::new(6, dec!(6))]);
+///     prices.insert('D', vec![Price::new(0, dec!(0.15))]);
 ///
 ///
-///     let mut terminal = Terminal::new(prices);
+///     let mut terminal = Terminal::new(prices)?;
 ///     // end rough equivalent of setup_pricing!('A' => [{ price: 2 }, { min: 4, price: 7 }]; 'B' => [{ price: 12 }]; 'C' => [{ price: 1.25 }, { min: 6, price: 6 }]; 'D' => [{ price: 0.15 }]);
 ///
-///     terminal.scan('A');
-///     terminal.scan('B');
-///     terminal.scan('C');
-///     terminal.scan('D');
+///     terminal.scan('A')?;
+///     terminal.scan('B')?;
+///     terminal.scan('C')?;
+///     terminal.scan('D')?;
 ///
-///     assert_eq!(terminal.total(), dec!(15.40));
+///     assert_eq!(terminal.total()?, dec!(15.40));
+/// # Ok(())
 /// # }
 /// ```
-
 pub struct Terminal {
-    prices: HashMap<char, Vec<Price>>,
-    items: HashMap<char, usize>,
+    catalog: Catalog,
+    basket: Basket,
+    journal: Journal,
+    embedded: EmbeddedCodes,
+    promotions: Vec<Promotion>,
+    coupons: Vec<Coupon>,
+    report: CatalogReport,
+    catalog_version: Option<String>,
+    clock: Box<dyn Clock>,
+    utc_offset: i32,
+    loyalty: Option<(Rules, Ledger)>,
+    tax: TaxTable,
+    rounding: Rounding,
 }
 
 impl Terminal {
-    pub fn new(prices: HashMap<char, Vec<Price>>) -> Self {
-        Terminal {
-            prices: prices.iter().fold(HashMap::new(), |mut acc, (k, v)| {
-                let mut nv = v.to_vec();
+    /// Creates a terminal for the given pricing. The pricing is checked with `validate()` first
+    /// and any errors are returned together, warnings are kept and can be read back with
+    /// `catalog_report()`.
+    ///
+    /// ```
+    /// # #[macro_use] extern crate scanner_terminal; #[macro_use] extern crate rust_decimal_macros; fn main() -> Result<(), scanner_terminal::TerminalError> {
+    ///     use scanner_terminal::{Terminal, TerminalError, Price};
+    ///
+    ///     match setup_pricing!('A' => [{ price: 2 }, { min: 4, price: -7 }]) {
+    ///         Err(TerminalError::InvalidCatalog(report)) => assert_eq!(report.errors().count(), 1),
+    ///         _ => panic!("a negative price should be rejected"),
+    ///     }
+    ///
+    ///     let terminal = setup_pricing!('A' => [{ price: 2 }, { min: 4, price: 9 }])?;
+    ///
+    ///     assert_eq!(terminal.catalog_report().warnings().count(), 1);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn new<K, V>(prices: HashMap<K, V>) -> Result<Self, TerminalError>
+    where
+        K: Into<Sku>,
+        V: Into<Item>,
+    {
+        let catalog: Catalog = prices
+            .into_iter()
+            .map(|(k, v)| (k.into(), v.into()))
+            .collect();
+
+        let report = validate(&catalog);
+
+        if !report.is_valid() {
+            return Err(TerminalError::InvalidCatalog(report));
+        }
+
+        Ok(Terminal {
+            catalog,
+            basket: Basket::default(),
+            journal: Journal::new(),
+            embedded: EmbeddedCodes::new(),
+            promotions: vec![],
+            coupons: vec![],
+            report,
+            catalog_version: None,
+            clock: Box::new(SystemClock),
+            utc_offset: 0,
+            loyalty: None,
+            tax: TaxTable::new(),
+            rounding: Rounding::new(),
+        })
+    }
+
+    /// Names the version of the catalog this terminal was set up with, so it is kept with saved
+    /// transactions.
+    pub fn set_catalog_version<S: Into<String>>(&mut self, version: S) {
+        self.catalog_version = Some(version.into());
+    }
+
+    pub fn catalog_version(&self) -> Option<&str> {
+        self.catalog_version.as_deref()
+    }
+
+    /// The warnings found when the terminal's pricing was validated.
+    pub fn catalog_report(&self) -> &CatalogReport {
+        &self.report
+    }
+
+    /// Sets where the terminal gets the time from. The whole basket is priced with the prices and
+    /// promotions in effect when it is priced, and operations are journaled with this time.
+    ///
+    /// ```
+    /// # #[macro_use] extern crate scanner_terminal; #[macro_use] extern crate rust_decimal_macros; fn main() -> Result<(), scanner_terminal::TerminalError> {
+    ///     use scanner_terminal::schedule::{FixedClock, Schedule, Weekday, Window};
+    ///     use scanner_terminal::{Item, Price, Terminal};
+    ///
+    ///     use std::collections::HashMap;
+    ///     use std::time::{Duration, UNIX_EPOCH};
+    ///
+    ///     let happy_hour = Schedule::new().window(Window::new(&Weekday::WEEKDAYS, (16, 0), (18, 0)));
+    ///
+    ///     let mut prices = HashMap::new();
+    ///
+    ///     prices.insert('A', Item::new(vec![Price::new(0, dec!(5)), Price::new(0, dec!(3)).during(happy_hour)]));
+    ///
+    ///     let mut terminal = Terminal::new(prices)?;
+    ///
+    ///     // Friday 2 October 2026, 17:55 UTC
+    ///     let clock = FixedClock::new(UNIX_EPOCH + Duration::from_secs(1_790_963_700));
+    ///
+    ///     terminal.set_clock(clock.clone());
+    ///     terminal.set_quantity('A', 2)?;
+    ///
+    ///     assert_eq!(terminal.total()?, dec!(6));
+    ///
+    ///     clock.advance(Duration::from_secs(5 * 60));
+    ///
+    ///     assert_eq!(terminal.total()?, dec!(10));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn set_clock<C: Clock + 'static>(&mut self, clock: C) {
+        self.clock = Box::new(clock);
+    }
+
+    /// Sets how many minutes east of UTC the store is, for the days and times of day in
+    /// schedules. Defaults to 0.
+    pub fn set_utc_offset(&mut self, minutes: i32) {
+        self.utc_offset = minutes;
+    }
+
+    /// The time the terminal's clock reads now.
+    pub fn now(&self) -> SystemTime {
+        self.clock.now()
+    }
+
+    /// Adds one of `item` to the terminal. Items missing from the pricing are rejected and leave
+    /// the terminal untouched.
+    ///
+    /// ```
+    /// # #[macro_use] extern crate scanner_terminal; #[macro_use] extern crate rust_decimal_macros; fn main() -> Result<(), scanner_terminal::TerminalError> {
+    ///     use scanner_terminal::{Terminal, TerminalError, Price};
+    ///
+    ///     let mut terminal = setup_pricing!('A' => [{ price: 2 }])?;
+    ///
+    ///     assert_eq!(terminal.scan('Z'), Err(TerminalError::UnknownItem('Z'.into())));
+    ///
+    ///     terminal.scan('A')?;
+    ///
+    ///     assert_eq!(terminal.total()?, dec!(2));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn scan<I: Into<Sku>>(&mut self, item: I) -> Result<(), TerminalError> {
+        self.record(Operation::Scan(item.into()))
+    }
+
+    /// Adds `weight` of an item sold by weight, as read from a scale in `unit`. The weight is
+    /// converted to the unit the item is priced by, and weighing the same item again adds to it.
+    ///
+    /// ```
+    /// # #[macro_use] extern crate scanner_terminal; #[macro_use] extern crate rust_decimal_macros; fn main() -> Result<(), scanner_terminal::TerminalError> {
+    ///     use scanner_terminal::{Item, Price, Terminal, TerminalError, Unit};
+    ///
+    ///     use std::collections::HashMap;
+    ///
+    ///     let mut prices = HashMap::new();
+    ///
+    ///     prices.insert('A', Item::new(vec![Price::new(0, dec!(2))]));
+    ///     prices.insert('B', Item::weighed(Unit::Lb, dec!(1.99)));
+    ///
+    ///     let mut terminal = Terminal::new(prices)?;
+    ///
+    ///     terminal.scan('A')?;
+    ///     terminal.scan_weight('B', dec!(1.5), Unit::Lb)?;
+    ///     terminal.scan_weight('B', dec!(8), Unit::Oz)?;
+    ///
+    ///     assert_eq!(terminal.scan('B'), Err(TerminalError::WrongUnit { item: 'B'.into(), unit: Unit::Lb }));
+    ///
+    ///     // 2 lb at $1.99 plus one A
+    ///     assert_eq!(terminal.total()?, dec!(5.98));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn scan_weight<I: Into<Sku>>(
+        &mut self,
+        item: I,
+        weight: Decimal,
+        unit: Unit,
+    ) -> Result<(), TerminalError> {
+        let item = item.into();
+
+        let sold_by = self.item(&item)?.unit;
+
+        let weight = unit
+            .convert(weight, sold_by)
+            .ok_or_else(|| TerminalError::WrongUnit {
+                item: item.clone(),
+                unit: sold_by,
+            })?;
+
+        self.record(Operation::ScanWeight { item, weight })
+    }
+
+    /// Scans the digits sent by a barcode scanner. The code's check digit is validated and it is
+    /// normalized to a GTIN-14, which is the `Sku` the item needs in the catalog.
+    ///
+    /// ```
+    /// # #[macro_use] extern crate scanner_terminal; #[macro_use] extern crate rust_decimal_macros; fn main() -> Result<(), scanner_terminal::TerminalError> {
+    ///     use scanner_terminal::barcode::BarcodeError;
+    ///     use scanner_terminal::{Terminal, TerminalError, Price};
+    ///
+    ///     let mut terminal = setup_pricing!("00036000291452" => [{ price: 3.49 }])?;
+    ///
+    ///     // the same item as a UPC-A and as an EAN-13
+    ///     terminal.scan_barcode("036000291452")?;
+    ///     terminal.scan_barcode("0036000291452")?;
+    ///
+    ///     assert_eq!(
+    ///         terminal.scan_barcode("036000291453"),
+    ///         Err(TerminalError::InvalidBarcode(BarcodeError::BadCheckDigit { expected: 2, found: 3 }))
+    ///     );
+    ///
+    ///     assert_eq!(terminal.total()?, dec!(6.98));
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// Codes with one of the store's variable measure prefixes (see `set_embedded_codes()`) are
+    /// looked up by their item reference. A price in the code is charged as it is, a weight is
+    /// added to the item like `scan_weight()` and priced with the rest of the basket. Weight codes
+    /// are only accepted for items sold by weight.
+    ///
+    /// ```
+    /// # #[macro_use] extern crate scanner_terminal; #[macro_use] extern crate rust_decimal_macros; fn main() -> Result<(), scanner_terminal::TerminalError> {
+    ///     use scanner_terminal::barcode::{EmbeddedCodes, EmbeddedRule};
+    ///     use scanner_terminal::{Item, Price, Terminal, Unit};
+    ///
+    ///     use std::collections::HashMap;
+    ///
+    ///     let mut prices = HashMap::new();
+    ///
+    ///     // deli ham keyed by its price zeroed code, and apples at $2.20 per kg
+    ///     prices.insert("00201234000006", Item::new(vec![Price::new(0, dec!(0))]));
+    ///     prices.insert("02101234000007", Item::weighed(Unit::Kg, dec!(2.20)));
+    ///
+    ///     let mut terminal = Terminal::new(prices)?;
+    ///
+    ///     terminal.set_embedded_codes(EmbeddedCodes::new().rule(2, EmbeddedRule::upc_price()).rule(21, EmbeddedRule::weight(5, 3, Unit::Kg)))?;
+    ///
+    ///     // $3.49 of ham
+    ///     terminal.scan_barcode("201234003496")?;
+    ///     // 1.25 kg of apples
+    ///     terminal.scan_barcode("2101234012505")?;
+    ///
+    ///     assert_eq!(terminal.total()?, dec!(6.24));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn scan_barcode(&mut self, code: &str) -> Result<(), TerminalError> {
+        let gtin = barcode::parse(code)?;
 
-                nv.sort();
+        let embedded = match self.embedded.decode(&gtin)? {
+            Some(embedded) => embedded,
+            None => return self.scan(gtin),
+        };
 
-                acc.entry(*k).or_insert(nv);
+        let item = Sku::from(embedded.item);
 
-                acc
+        match embedded.kind {
+            Embedded::Price => self.record(Operation::ScanPriced {
+                item,
+                price: embedded.value,
             }),
-            items: HashMap::new(),
+            Embedded::Weight(unit) => self.scan_weight(item, embedded.value, unit),
+        }
+    }
+
+    /// Sets the variable measure prefixes this store prints price and weight labels with.
+    pub fn set_embedded_codes(&mut self, codes: EmbeddedCodes) -> Result<(), TerminalError> {
+        codes.validate()?;
+
+        self.embedded = codes;
+
+        Ok(())
+    }
+
+    /// Adds a promotion to the terminal. Promotions are only used where they make the total
+    /// cheaper, see `Promotion` for examples.
+    ///
+    /// Each unit goes into at most one item promotion, whichever makes the total cheapest, with
+    /// ties going to the promotion with the higher `priority` and then to the one added first.
+    /// Basket discounts are applied in the same order. Of the promotions sharing an exclusivity
+    /// group only one is used: the highest priority one that saves anything, then the one that
+    /// saves the most. A promotion that isn't stackable keeps the units it discounted from
+    /// basket discounts, and a basket discount that isn't stackable skips units other
+    /// promotions discounted. The receipt's `conflicts()` say which promotion won each time.
+    ///
+    /// ```
+    /// # #[macro_use] extern crate scanner_terminal; #[macro_use] extern crate rust_decimal_macros; fn main() -> Result<(), scanner_terminal::TerminalError> {
+    ///     use scanner_terminal::{Discount, Promotion, Reason, Terminal, Price};
+    ///
+    ///     let mut terminal = setup_pricing!('A' => [{ price: 10 }]; 'B' => [{ price: 20 }])?;
+    ///
+    ///     terminal.add_promotion(Promotion::spend("$5 off", dec!(20), Discount::Amount(dec!(5)), Vec::<char>::new()).exclusive("weekly"))?;
+    ///     terminal.add_promotion(Promotion::spend("20% off", dec!(20), Discount::Percent(dec!(20)), Vec::<char>::new()).exclusive("weekly"))?;
+    ///     terminal.add_promotion(Promotion::cheapest_free("2 for 1", vec!['A'], 2).stackable(false))?;
+    ///
+    ///     terminal.set_quantity('A', 2)?;
+    ///     terminal.scan('B')?;
+    ///
+    ///     // one A is free, and neither of the A can be discounted again
+    ///     let receipt = terminal.receipt()?;
+    ///
+    ///     assert_eq!(receipt.total(), dec!(25));
+    ///     assert_eq!(receipt.conflicts()[0].promotion, "20% off");
+    ///     assert_eq!(receipt.conflicts()[0].reason, Reason::Savings { group: "weekly".to_string() });
+    ///     assert_eq!(receipt.conflicts()[1].winner, "2 for 1");
+    ///     assert_eq!(receipt.conflicts()[1].reason, Reason::NotStackable);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn add_promotion(&mut self, promotion: Promotion) -> Result<(), TerminalError> {
+        promotion.validate(&self.catalog)?;
+
+        if self.promotions.iter().any(|p| p.name == promotion.name) {
+            return Err(TerminalError::InvalidPromotion {
+                name: promotion.name,
+                reason: "another promotion has the same name".to_string(),
+            });
         }
+
+        self.promotions.push(promotion);
+
+        Ok(())
+    }
+
+    /// Sets up a coupon that can be redeemed by its code, see `Coupon` for an example.
+    pub fn add_coupon(&mut self, coupon: Coupon) -> Result<(), TerminalError> {
+        coupon.validate(&self.catalog)?;
+
+        self.coupons.retain(|c| c.code != coupon.code);
+        self.coupons.push(coupon);
+
+        Ok(())
+    }
+
+    /// Uses the coupon set up with `code` on this transaction. The basket has to meet the
+    /// coupon's requirement, and the coupon is taken out again as soon as it doesn't.
+    pub fn redeem(&mut self, code: &str) -> Result<(), TerminalError> {
+        self.record(Operation::Redeem(code.to_string()))
+    }
+
+    /// Uses a coupon read from a GS1 DataBar coupon barcode, see `coupon::parse()`. It can be used
+    /// on any catalog item keyed by a GTIN with the coupon's company prefix.
+    ///
+    /// ```
+    /// # #[macro_use] extern crate scanner_terminal; #[macro_use] extern crate rust_decimal_macros; fn main() -> Result<(), scanner_terminal::TerminalError> {
+    ///     use scanner_terminal::{Terminal, TerminalError, Price};
+    ///
+    ///     let mut terminal = setup_pricing!("00036000291452" => [{ price: 3.49 }])?;
+    ///
+    ///     // 75 cents off two
+    ///     let coupon = "]e0811010036000123456275120000";
+    ///
+    ///     terminal.scan_barcode("036000291452")?;
+    ///
+    ///     assert_eq!(terminal.scan_coupon(coupon), Err(TerminalError::CouponNotEligible(coupon.to_string())));
+    ///
+    ///     terminal.scan_barcode("036000291452")?;
+    ///     terminal.scan_coupon(coupon)?;
+    ///
+    ///     assert_eq!(terminal.total()?, dec!(6.23));
+    ///     assert_eq!(terminal.receipt()?.adjustments()[0].label, "Coupon 123456");
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn scan_coupon(&mut self, data: &str) -> Result<(), TerminalError> {
+        self.record(Operation::ScanCoupon(data.to_string()))
+    }
+
+    /// Prices the transaction for `member`, replacing any member already on it. Everything in the
+    /// basket is repriced with the prices for their level, see `member` for an example.
+    pub fn attach_member(&mut self, member: Member) -> Result<(), TerminalError> {
+        self.record(Operation::AttachMember(member))
+    }
+
+    /// Takes the member back off the transaction, so it is priced at regular prices again.
+    pub fn detach_member(&mut self) -> Result<(), TerminalError> {
+        self.record(Operation::DetachMember)
+    }
+
+    pub fn member(&self) -> Option<&Member> {
+        self.basket.member.as_ref()
     }
 
-    pub fn scan(&mut self, item: char)  {
-        if self.prices.get(&item).is_none() {
-            panic!("invalid item {}", item);
+    /// Sets how members earn and spend points, and where their balances are kept. See `loyalty`
+    /// for an example.
+    pub fn set_loyalty(&mut self, rules: Rules, ledger: Ledger) {
+        self.loyalty = Some((rules, ledger));
+    }
+
+    /// Spends `points` of the member's balance on this transaction. The points are only taken
+    /// from the ledger when the transaction is committed, and only as many as the total needs.
+    pub fn redeem_points(
+        &mut self,
+        points: u64,
+        redemption: Redemption,
+    ) -> Result<(), TerminalError> {
+        let (_, ledger) = self
+            .loyalty
+            .as_ref()
+            .ok_or(TerminalError::NoLoyaltyProgram)?;
+
+        let member = self.basket.member.as_ref().ok_or(TerminalError::NoMember)?;
+
+        let balance = ledger.balance(&member.id)?;
+
+        let wanted = self
+            .basket
+            .redeemed(Redemption::Discount)?
+            .checked_add(self.basket.redeemed(Redemption::Tender)?)
+            .and_then(|p| p.checked_add(points))
+            .ok_or(TerminalError::Overflow)?;
+
+        if !loyalty::covers(balance, wanted) {
+            return Err(TerminalError::NotEnoughPoints {
+                member: member.id.clone(),
+                balance,
+            });
         }
 
-        let e = self.items.entry(item).or_insert(0);
+        self.record(Operation::RedeemPoints { points, redemption })
+    }
+
+    /// Finishes the transaction as `transaction`, recording the points the member earned and
+    /// redeemed in the ledger. Nothing is recorded without a member or a loyalty program.
+    pub fn commit(&self, transaction: &str) -> Result<Receipt, TerminalError> {
+        let receipt = self.receipt()?;
 
-        *e += 1;
+        if let (Some(member), Some((_, ledger))) = (&self.basket.member, &self.loyalty) {
+            ledger.commit(
+                &member.id,
+                transaction,
+                receipt.points_earned,
+                receipt.points_redeemed,
+            )?;
+        }
+
+        Ok(receipt)
     }
 
+    /// Takes one scan of `item` back out of the terminal: one unit of a counted item, all of a
+    /// weighed item or the last barcode priced line for it. Voiding an item that isn't in the
+    /// terminal is an error.
+    ///
+    /// ```
+    /// # #[macro_use] extern crate scanner_terminal; #[macro_use] extern crate rust_decimal_macros; fn main() -> Result<(), scanner_terminal::TerminalError> {
+    ///     use scanner_terminal::{Terminal, TerminalError, Price};
+    ///
+    ///     let mut terminal = setup_pricing!('A' => [{ price: 2 }, { min: 4, price: 7 }]; 'B' => [{ price: 12 }])?;
+    ///
+    ///     for _ in 0..4 {
+    ///         terminal.scan('A')?;
+    ///     }
+    ///
+    ///     assert_eq!(terminal.total()?, dec!(7));
+    ///
+    ///     // a double scan, the 4 pack no longer applies
+    ///     terminal.void('A')?;
     ///
-    /// If you provide more than a price at min: 0, the lib will make as many sets as possible.
+    ///     assert_eq!(terminal.total()?, dec!(6));
+    ///     assert_eq!(terminal.void('B'), Err(TerminalError::NotInBasket('B'.into())));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn void<I: Into<Sku>>(&mut self, item: I) -> Result<(), TerminalError> {
+        self.record(Operation::Void(item.into()))
+    }
+
+    /// Sets how many of a counted item are in the terminal, a quantity of 0 removes it. Quantities
+    /// above `MAX_QUANTITY` are rejected.
     ///
     /// ```
-    /// # #[macro_use] extern crate scanner_terminal; #[macro_use] extern crate rust_decimal_macros; fn main() {
+    /// # #[macro_use] extern crate scanner_terminal; #[macro_use] extern crate rust_decimal_macros; fn main() -> Result<(), scanner_terminal::TerminalError> {
     ///     use scanner_terminal::{Terminal, Price};
     ///
-    ///     let mut terminal = setup_pricing!('C' => [{ price: 1.25 }, { min: 6, price: 6 }]);
+    ///     let mut terminal = setup_pricing!('C' => [{ price: 1.25 }, { min: 6, price: 6 }])?;
     ///
-    ///     // These first 6 will be used for the 6 pack and will total $6
-    ///     terminal.scan('C');
-    ///     terminal.scan('C');
-    ///     terminal.scan('C');
-    ///     terminal.scan('C');
-    ///     terminal.scan('C');
-    ///     terminal.scan('C');
+    ///     terminal.scan('C')?;
+    ///     terminal.set_quantity('C', 7)?;
     ///
-    ///     // This last one is back to normal
-    ///     terminal.scan('C');
+    ///     assert_eq!(terminal.total()?, dec!(7.25));
     ///
-    ///     assert_eq!(terminal.total(), dec!(7.25));
+    ///     terminal.set_quantity('C', 5)?;
+    ///
+    ///     assert_eq!(terminal.total()?, dec!(6.25));
+    /// # Ok(())
     /// # }
+    /// ```
+    pub fn set_quantity<I: Into<Sku>>(
+        &mut self,
+        item: I,
+        quantity: usize,
+    ) -> Result<(), TerminalError> {
+        self.record(Operation::SetQuantity {
+            item: item.into(),
+            quantity,
+        })
+    }
 
-    pub fn total(&self) -> Decimal {
-        self.items.iter().fold(dec!(0), |mut acc, (item, count)| {
-            acc += match self.prices.get(item) {
-                Some(prices) => {
-                    let mut item_total = dec!(0);
+    /// Charges `price` for each unit of `item` (or per unit of weight) instead of its tiers, for
+    /// as long as the item is in the terminal.
+    ///
+    /// ```
+    /// # #[macro_use] extern crate scanner_terminal; #[macro_use] extern crate rust_decimal_macros; fn main() -> Result<(), scanner_terminal::TerminalError> {
+    ///     use scanner_terminal::{Terminal, Price};
+    ///
+    ///     let mut terminal = setup_pricing!('A' => [{ price: 2 }, { min: 4, price: 7 }])?;
+    ///
+    ///     terminal.set_quantity('A', 4)?;
+    ///     terminal.override_price('A', dec!(1.5))?;
+    ///
+    ///     assert_eq!(terminal.total()?, dec!(6));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn override_price<I: Into<Sku>>(
+        &mut self,
+        item: I,
+        price: Decimal,
+    ) -> Result<(), TerminalError> {
+        self.record(Operation::OverridePrice {
+            item: item.into(),
+            price,
+        })
+    }
 
-                    let mut c = *count;
+    /// Sets the sales tax charged on top of the total, or the VAT included in the prices, see
+    /// `tax` for examples. Without a tax table no tax is charged.
+    pub fn set_tax_table(&mut self, table: TaxTable) -> Result<(), TerminalError> {
+        table.validate()?;
 
-                    for p in prices {
-                        if c == 0 {
-                            break;
-                        }
+        self.tax = table;
 
-                        if p.min == 0 {
-                            item_total += p.price * Decimal::new(c as i64, 0);
-                        } else if c >= p.min {
-                            let x = c / p.min;
+        Ok(())
+    }
 
-                            item_total += p.price * Decimal::new(x as i64, 0);
+    /// Exempts the transaction from tax, or from the tax on some categories, see `Exemption`
+    /// for an example. Replaces any exemption already on it.
+    pub fn exempt_tax(&mut self, exemption: Exemption) -> Result<(), TerminalError> {
+        if exemption.certificate.trim().is_empty() {
+            return Err(TerminalError::MissingCertificate);
+        }
 
-                            c -= x * p.min;
-                        }
-                    }
+        self.record(Operation::ExemptTax(exemption))
+    }
 
-                    item_total
-                }
-                None => panic!(format!("bad item name {}", item)),
-            };
+    /// Takes the exemption back off the transaction, so it is charged tax again.
+    pub fn charge_tax(&mut self) -> Result<(), TerminalError> {
+        self.record(Operation::ChargeTax)
+    }
 
-            acc
-        })
+    pub fn exemption(&self) -> Option<&Exemption> {
+        self.basket.exemption.as_ref()
     }
-}
 
-///
-/// setup_pricing!() can be called to set up the a terminal directly. Arguments are provided as an
-/// array or {} dictionaries, which can specify the min value that this can apply (default for min
-/// is 0) and the price for that amount.
-///
-/// ```
-/// # #[macro_use] extern crate scanner_terminal; #[macro_use] extern crate rust_decimal_macros; fn main() {
-///     use scanner_terminal::{Terminal, Price};
-///
-///     let mut terminal = setup_pricing!('A' => [{ price: 2 }, { min: 4, price: 7 }]; 'B' => [{ price: 12 }]; 'C' => [{ price: 1.25 }, { min: 6, price: 6 }]; 'D' => [{ price: 0.15 }]);
-///
-///     // As items are scanned the number of items scanned is tracked
-///     terminal.scan('A');
-///     terminal.scan('B');
-///     terminal.scan('C');
-///     terminal.scan('D');
-///     terminal.scan('A');
-///     terminal.scan('B');
-///     terminal.scan('A');
-///     terminal.scan('A');
-///
-///     // The total gives checks price tiers
-///     assert_eq!(terminal.total(), dec!(32.40));
-/// # }
-///
-///
-///
+    /// Sets how amounts are rounded, see `rounding` for an example. Without a policy the total
+    /// is rounded half up to cents and cash isn't rounded any further.
+    pub fn set_rounding(&mut self, rounding: Rounding) -> Result<(), TerminalError> {
+        rounding.validate()?;
 
-#[macro_export]
-macro_rules! setup_pricing(
-    { $($key:literal => $($value:tt), + ); + } => {
-        {
-            let mut m = ::std::collections::HashMap::new();
+        self.rounding = rounding;
 
-            $(
-                let mut v = vec![];
+        Ok(())
+    }
 
-                $(
-                    for iv in parse_price!($value) {
-                        v.push(iv)
-                    }
-                )*;
+    /// Records how the customer is paying. Paying cash rounds what is due to the cash
+    /// increment, when the terminal's rounding has one.
+    pub fn tender(&mut self, tender: Tender) -> Result<(), TerminalError> {
+        self.record(Operation::Tender(tender))
+    }
 
-                m.insert($key, v);
-            )+
+    /// The total with tax, see `Receipt::grand_total()`.
+    pub fn grand_total(&self) -> Result<Decimal, TerminalError> {
+        Ok(self.receipt()?.grand_total())
+    }
+
+    /// Removes everything that has been scanned.
+    pub fn clear(&mut self) {
+        self.journal.record(Operation::Clear, self.now());
+        self.basket = Basket::default();
+    }
 
-            Terminal::new(m)
+    /// Takes back the last operation still in effect, whether that was a scan, a void, a quantity
+    /// change, a price override or a clear.
+    ///
+    /// ```
+    /// # #[macro_use] extern crate scanner_terminal; #[macro_use] extern crate rust_decimal_macros; fn main() -> Result<(), scanner_terminal::TerminalError> {
+    ///     use scanner_terminal::{Terminal, TerminalError, Price};
+    ///
+    ///     let mut terminal = setup_pricing!('A' => [{ price: 2 }]; 'B' => [{ price: 12 }])?;
+    ///
+    ///     terminal.scan('A')?;
+    ///     terminal.scan('B')?;
+    ///     terminal.clear();
+    ///
+    ///     terminal.undo()?;
+    ///     assert_eq!(terminal.total()?, dec!(14));
+    ///
+    ///     terminal.undo()?;
+    ///     assert_eq!(terminal.total()?, dec!(2));
+    ///
+    ///     terminal.redo()?;
+    ///     assert_eq!(terminal.total()?, dec!(14));
+    ///
+    ///     // scanning something new drops the clear that could still have been redone
+    ///     terminal.scan('A')?;
+    ///     assert_eq!(terminal.redo(), Err(TerminalError::NothingToRedo));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn undo(&mut self) -> Result<(), TerminalError> {
+        if !self.journal.can_undo() {
+            return Err(TerminalError::NothingToUndo);
         }
-     };
-);
 
-#[macro_export]
-macro_rules! parse_price(
-    ($price:literal) => {
-        {
-            vec![Price{ min: 0, price: dec!($price) }];
+        self.record_and_rebuild(Operation::Undo)
+    }
+
+    /// Puts back the last operation taken back by `undo()`. Anything done after an undo other than
+    /// another undo or redo means there is nothing left to redo.
+    pub fn redo(&mut self) -> Result<(), TerminalError> {
+        if !self.journal.can_redo() {
+            return Err(TerminalError::NothingToRedo);
         }
-     };
-    ([{ price: $price:literal }$(,)? $({ min: $bulk_quantity:literal, price: $bulk_price:literal }), *]) => {
-        {
-            let mut v = vec![];
 
-            v.push(Price{ min: 0, price: dec!($price) });
+        self.record_and_rebuild(Operation::Redo)
+    }
 
-            $(
-              v.push(Price{ min: $bulk_quantity, price: dec!($bulk_price) });
-             )*
+    /// Everything that has been done at this terminal, in order.
+    pub fn journal(&self) -> &Journal {
+        &self.journal
+    }
 
-                v
-        }
-     };
-);
+    /// Replaces this terminal's journal and rebuilds what has been scanned from it alone. Each
+    /// operation is checked against the prices in effect when it was recorded, the way it was
+    /// the first time.
+    pub fn restore(&mut self, journal: Journal) -> Result<(), TerminalError> {
+        self.basket = Basket::replay(&self.coupons, journal.effective_entries(), |time, level| {
+            self.priced_catalog(time, level)
+        })?;
+        self.journal = journal;
 
-#[cfg(test)]
-mod tests {
-    use super::Price;
+        Ok(())
+    }
 
-    #[test]
-    fn it_parses() {
-        assert_eq!(
-            parse_price!([{ price: 2 }, { min: 4, price: 7 }]),
+    /// Everything needed to reproduce this transaction later with `replay::replay()`.
+    pub fn save(&self) -> Result<SavedTransaction, TerminalError> {
+        // priced and recorded at the same instant, so a replay prices it the same way
+        let now = self.now();
+        let receipt = self.receipt_at(now)?;
+
+        Ok(SavedTransaction {
+            catalog_version: self.catalog_version.clone(),
+            total: receipt.total(),
+            grand_total: receipt.grand_total(),
+            amount_due: receipt.amount_due(),
+            priced_at: now,
+            journal: self.journal.clone(),
+        })
+    }
+
+    fn record(&mut self, operation: Operation) -> Result<(), TerminalError> {
+        // journaled at the time it was checked at, so a replay checks it the same way
+        let now = self.now();
+
+        self.basket.apply(
+            &self.priced_catalog(now, self.level()),
+            &self.coupons,
+            &operation,
+        )?;
+        self.journal.record(operation, now);
+
+        Ok(())
+    }
+
+    fn record_and_rebuild(&mut self, operation: Operation) -> Result<(), TerminalError> {
+        let now = self.now();
+        let mut journal = self.journal.clone();
+
+        journal.record(operation, now);
+
+        self.restore(journal)
+    }
+
+    ///
+    /// If you provide more than a price at min: 0, the lib will pick whichever combination of
+    /// sets and single items is cheapest for the customer.
+    ///
+    /// ```
+    /// # #[macro_use] extern crate scanner_terminal; #[macro_use] extern crate rust_decimal_macros; fn main() -> Result<(), scanner_terminal::TerminalError> {
+    ///     use scanner_terminal::{Terminal, Price};
+    ///
+    ///     let mut terminal = setup_pricing!('C' => [{ price: 1.25 }, { min: 6, price: 6 }])?;
+    ///
+    ///     // These first 6 will be used for the 6 pack and will total $6
+    ///     terminal.scan('C')?;
+    ///     terminal.scan('C')?;
+    ///     terminal.scan('C')?;
+    ///     terminal.scan('C')?;
+    ///     terminal.scan('C')?;
+    ///     terminal.scan('C')?;
+    ///
+    ///     // This last one is back to normal
+    ///     terminal.scan('C')?;
+    ///
+    ///     assert_eq!(terminal.total()?, dec!(7.25));
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// Sets are not simply taken largest or priciest first, so overlapping tiers still come out
+    /// at the lowest total.
+    ///
+    /// ```
+    /// # #[macro_use] extern crate scanner_terminal; #[macro_use] extern crate rust_decimal_macros; fn main() -> Result<(), scanner_terminal::TerminalError> {
+    ///     use scanner_terminal::{Terminal, Price};
+    ///
+    ///     let mut terminal = setup_pricing!('C' => [{ price: 1 }, { min: 4, price: 3 }, { min: 5, price: 3.9 }])?;
+    ///
+    ///     for _ in 0..8 {
+    ///         terminal.scan('C')?;
+    ///     }
+    ///
+    ///     // two 4 packs beat a 5 pack and three singles
+    ///     assert_eq!(terminal.total()?, dec!(6));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn total(&self) -> Result<Decimal, TerminalError> {
+        Ok(self.receipt()?.total())
+    }
+
+    /// Breaks the total down by item, showing which tiers were used and what they saved.
+    ///
+    /// ```
+    /// # #[macro_use] extern crate scanner_terminal; #[macro_use] extern crate rust_decimal_macros; fn main() -> Result<(), scanner_terminal::TerminalError> {
+    ///     use scanner_terminal::{Terminal, Price, Pricing, TierUse};
+    ///
+    ///     let mut terminal = setup_pricing!('A' => [{ price: 2 }, { min: 4, price: 7 }]; 'B' => [{ price: 12 }])?;
+    ///
+    ///     for _ in 0..5 {
+    ///         terminal.scan('A')?;
+    ///     }
+    ///
+    ///     terminal.scan('B')?;
+    ///
+    ///     let receipt = terminal.receipt()?;
+    ///     let a = &receipt.lines()[0];
+    ///
+    ///     assert_eq!(a.quantity, dec!(5));
+    ///     assert_eq!(a.pricing, Pricing::Tiers(vec![
+    ///         TierUse { price: Price::new(0, dec!(2)), times: 1, subtotal: dec!(2) },
+    ///         TierUse { price: Price::new(4, dec!(7)), times: 1, subtotal: dec!(7) },
+    ///     ]));
+    ///     assert_eq!(a.subtotal, dec!(9));
+    ///     assert_eq!(a.savings, dec!(1));
+    ///
+    ///     assert_eq!(receipt.total(), dec!(21));
+    ///     assert_eq!(receipt.total(), terminal.total()?);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn receipt(&self) -> Result<Receipt, TerminalError> {
+        self.receipt_at(self.now())
+    }
+
+    /// The receipt as it would be priced at `now`.
+    fn receipt_at(&self, now: SystemTime) -> Result<Receipt, TerminalError> {
+        let mut receipt = self.receipt_for(now, self.level())?;
+
+        receipt.taxes = self.tax.taxes(
+            &self.catalog,
+            &receipt,
+            self.basket.exemption.as_ref(),
+            &self.rounding,
+        )?;
+        receipt.exemption = self.basket.exemption.clone();
+
+        receipt.grand_total = checked_sum(
+            receipt
+                .taxes
+                .iter()
+                .map(|t| if t.included { -t.waived } else { t.amount })
+                .chain(Some(receipt.total)),
+        )?;
+        receipt.amount_due = receipt.grand_total;
+
+        if let Some(member) = &self.basket.member {
+            receipt.member_savings = self
+                .receipt_for(now, None)?
+                .total()
+                .checked_sub(receipt.total())
+                .ok_or(TerminalError::Overflow)?;
+            receipt.member = Some(member.clone());
+
+            if let Some((rules, _)) = &self.loyalty {
+                self.settle_points(&mut receipt, rules)?;
+            }
+        }
+
+        receipt.tender = self.basket.tender;
+
+        if receipt.tender == Some(Tender::Cash) {
+            let due = receipt.amount_due;
+            let rounded = self.rounding.round_cash(due)?;
+
+            receipt.cash_rounding = rounded.checked_sub(due).ok_or(TerminalError::Overflow)?;
+            receipt.amount_due = rounded;
+        }
+
+        Ok(receipt)
+    }
+
+    /// Works out the points used as tender, the points used altogether and the points earned.
+    fn settle_points(&self, receipt: &mut Receipt, rules: &Rules) -> Result<(), TerminalError> {
+        let requested = self.basket.redeemed(Redemption::Discount)?;
+
+        // the points discount is applied last, and only when points were redeemed for it
+        let discount = match receipt.adjustments.last() {
+            Some(a) if requested > 0 && a.label == loyalty::LABEL => {
+                rules.points_for(-a.amount)?.min(requested)
+            }
+            _ => 0,
+        };
+
+        let tender = self.basket.redeemed(Redemption::Tender)?;
+
+        receipt.points_tendered = rules
+            .value(tender)?
+            .min(receipt.grand_total().max(Decimal::ZERO));
+
+        receipt.amount_due = receipt
+            .grand_total
+            .checked_sub(receipt.points_tendered)
+            .ok_or(TerminalError::Overflow)?;
+
+        receipt.points_redeemed = rules
+            .points_for(receipt.points_tendered)?
+            .min(tender)
+            .checked_add(discount)
+            .ok_or(TerminalError::Overflow)?;
+
+        receipt.points_earned = rules.earned(receipt)?;
+
+        Ok(())
+    }
+
+    /// The receipt at `time` for a member at `level`, with the promotions in effect then.
+    fn receipt_for(&self, now: SystemTime, level: Option<Level>) -> Result<Receipt, TerminalError> {
+        let catalog = self.priced_catalog(now, level);
+
+        let mut promotions: Vec<&Promotion> = self
+            .promotions
+            .iter()
+            .filter(|p| {
+                p.schedule
+                    .as_ref()
+                    .is_none_or(|s| s.contains(now, self.utc_offset))
+            })
+            .collect();
+
+        // a stable sort, so promotions with the same priority stay in the order they were added
+        promotions.sort_by_key(|p| Reverse(p.priority));
+
+        let (promotions, mut conflicts) = pricing::exclusive(promotions, |promotions| {
+            Ok(self.priced(&catalog, promotions)?.total())
+        })?;
+
+        let mut receipt = self.priced(&catalog, &promotions)?;
+
+        conflicts.append(&mut receipt.conflicts);
+        receipt.conflicts = conflicts;
+
+        Ok(receipt)
+    }
+
+    /// The receipt priced from `catalog` with only `promotions` on offer, in the order given.
+    fn priced(
+        &self,
+        catalog: &Catalog,
+        promotions: &[&Promotion],
+    ) -> Result<Receipt, TerminalError> {
+        let mut lines = vec![];
+
+        let mut counts = HashMap::new();
+
+        for (item, count) in &self.basket.items {
+            let unit_price = match self.basket.overrides.get(item) {
+                Some(price) => *price,
+                None => {
+                    counts.insert(item.clone(), *count);
+
+                    continue;
+                }
+            };
+
+            let quantity = Decimal::from(*count as u64);
+
+            let singles = base_price(item, lookup(catalog, item)?)?
+                .checked_mul(quantity)
+                .ok_or(TerminalError::Overflow)?;
+
+            let subtotal = unit_price
+                .checked_mul(quantity)
+                .ok_or(TerminalError::Overflow)?;
+
+            lines.push(Line {
+                item: item.clone(),
+                quantity,
+                unit: Unit::Each,
+                pricing: Pricing::Override { unit_price },
+                deals: vec![],
+                subtotal,
+                savings: singles
+                    .checked_sub(subtotal)
+                    .ok_or(TerminalError::Overflow)?,
+                rounding: Decimal::ZERO,
+            });
+        }
+
+        let (counted, mut adjustments) =
+            pricing::counted_lines(catalog, &counts, promotions, &self.rounding)?;
+
+        lines.extend(counted);
+
+        for (item, weight) in &self.basket.weights {
+            let entry = lookup(catalog, item)?;
+
+            let unit_price = match self.basket.overrides.get(item) {
+                Some(price) => *price,
+                None => base_price(item, entry)?,
+            };
+
+            let subtotal = self.rounding.round(
+                unit_price
+                    .checked_mul(*weight)
+                    .ok_or(TerminalError::Overflow)?,
+            );
+
+            lines.push(Line {
+                item: item.clone(),
+                quantity: *weight,
+                unit: entry.unit,
+                pricing: Pricing::Weight { unit_price },
+                deals: vec![],
+                subtotal,
+                savings: Decimal::ZERO,
+                rounding: Decimal::ZERO,
+            });
+        }
+
+        lines.sort_by(|a, b| a.item.cmp(&b.item));
+
+        for (item, price) in &self.basket.priced {
+            lines.push(Line {
+                item: item.clone(),
+                quantity: Decimal::ONE,
+                unit: Unit::Each,
+                pricing: Pricing::Barcode,
+                deals: vec![],
+                subtotal: *price,
+                savings: Decimal::ZERO,
+                rounding: Decimal::ZERO,
+            });
+        }
+
+        if self.rounding.scope == Scope::PerLine {
+            for line in &mut lines {
+                let rounded = self.rounding.round(line.subtotal);
+
+                line.rounding = rounded
+                    .checked_sub(line.subtotal)
+                    .ok_or(TerminalError::Overflow)?;
+                line.subtotal = rounded;
+            }
+        }
+
+        let points = match &self.loyalty {
+            Some((rules, _)) => match self.basket.redeemed(Redemption::Discount)? {
+                0 => None,
+                points => Some(rules.value(points)?),
+            },
+            None => None,
+        };
+
+        let (basket, conflicts) = pricing::basket_adjustments(
+            &lines,
+            &adjustments,
+            &self.basket.coupons,
+            promotions,
+            points,
+            &self.rounding,
+        )?;
+
+        adjustments.extend(basket);
+
+        let subtotal = checked_sum(lines.iter().map(|l| l.subtotal))?;
+        let adjusted = checked_sum(adjustments.iter().map(|a| a.amount))?;
+
+        let savings = checked_sum(lines.iter().map(|l| l.savings))?
+            .checked_sub(adjusted)
+            .ok_or(TerminalError::Overflow)?;
+
+        let total = subtotal
+            .checked_add(adjusted)
+            .ok_or(TerminalError::Overflow)?;
+
+        // whatever was rounded per line, the total has to come to whole minor units
+        let rounding = self
+            .rounding
+            .round(total)
+            .checked_sub(total)
+            .ok_or(TerminalError::Overflow)?;
+
+        let total = total.checked_add(rounding).ok_or(TerminalError::Overflow)?;
+
+        Ok(Receipt {
+            lines,
+            adjustments,
+            conflicts,
+            member: None,
+            member_savings: Decimal::ZERO,
+            points_tendered: Decimal::ZERO,
+            points_redeemed: 0,
+            points_earned: 0,
+            taxes: vec![],
+            exemption: None,
+            rounding,
+            tender: None,
+            cash_rounding: Decimal::ZERO,
+            policy: self.rounding,
+            subtotal,
+            savings,
+            total,
+            // nothing on top of the total until tax and tender are worked out
+            grand_total: total,
+            amount_due: total,
+        })
+    }
+
+    fn level(&self) -> Option<Level> {
+        self.basket.member.as_ref().map(|m| m.level)
+    }
+
+    fn item(&self, item: &Sku) -> Result<&Item, TerminalError> {
+        lookup(&self.catalog, item)
+    }
+
+    /// The catalog with only the prices in effect at `time` for a member at `level`.
+    fn priced_catalog(&self, time: SystemTime, level: Option<Level>) -> Catalog {
+        self.catalog
+            .iter()
+            .map(|(sku, item)| (sku.clone(), item.at(time, self.utc_offset, level)))
+            .collect()
+    }
+}
+
+fn checked_sum<I: IntoIterator<Item = Decimal>>(amounts: I) -> Result<Decimal, TerminalError> {
+    amounts
+        .into_iter()
+        .try_fold(Decimal::ZERO, |acc, amount| acc.checked_add(amount))
+        .ok_or(TerminalError::Overflow)
+}
+
+fn base_price(sku: &Sku, item: &Item) -> Result<Decimal, TerminalError> {
+    item.base_price().ok_or_else(|| {
+        TerminalError::InvalidCatalog(CatalogReport::missing_base_price(sku.clone()))
+    })
+}
+
+/// The cheapest combination of price tiers for every count of units up to some limit, worked out
+/// in one go so each count can be read back without searching again. A price with `min: 0` is
+/// charged per unit, any other price buys a set of `min` units.
+pub(crate) struct Cheapest<'a> {
+    pub(crate) prices: &'a [Price],
+    /// best[n] is the cheapest cost for n units and the tier that was used last to get there.
+    best: Vec<Option<(Decimal, usize)>>,
+}
+
+impl<'a> Cheapest<'a> {
+    pub(crate) fn new(prices: &'a [Price], count: usize) -> Result<Self, TerminalError> {
+        let size = count.checked_add(1).ok_or(TerminalError::Overflow)?;
+
+        let mut best: Vec<Option<(Decimal, usize)>> = vec![None; size];
+
+        best[0] = Some((dec!(0), 0));
+
+        for n in 1..=count {
+            for (i, p) in prices.iter().enumerate() {
+                let size = p.min.max(1);
+
+                if size > n {
+                    continue;
+                }
+
+                if let Some((prev, _)) = best[n - size] {
+                    let cost = prev.checked_add(p.price).ok_or(TerminalError::Overflow)?;
+
+                    match best[n] {
+                        Some((current, _)) if current <= cost => {}
+                        _ => best[n] = Some((cost, i)),
+                    }
+                }
+            }
+        }
+
+        Ok(Cheapest { prices, best })
+    }
+
+    /// The cheapest cost of `count` units, `None` if the tiers can't make up exactly that many.
+    pub(crate) fn cost(&self, count: usize) -> Option<Decimal> {
+        self.best
+            .get(count)
+            .copied()
+            .flatten()
+            .map(|(cost, _)| cost)
+    }
+
+    /// The cheapest cost of `count` units with the number of times each tier was used (indexed
+    /// the same as the prices), `None` if the tiers can't make up exactly that many.
+    pub(crate) fn tiers(&self, count: usize) -> Option<(Decimal, Vec<usize>)> {
+        let cost = self.cost(count)?;
+
+        let mut used = vec![0; self.prices.len()];
+
+        let mut n = count;
+
+        while let Some((_, i)) = self.best[n].filter(|_| n > 0) {
+            used[i] += 1;
+
+            n -= self.prices[i].min.max(1);
+        }
+
+        Some((cost, used))
+    }
+}
+
+///
+/// setup_pricing!() can be called to set up the a terminal directly. Arguments are provided as an
+/// array or {} dictionaries, which can specify the min value that this can apply (default for min
+/// is 0) and the price for that amount.
+///
+/// ```
+/// # #[macro_use] extern crate scanner_terminal; #[macro_use] extern crate rust_decimal_macros; fn main() -> Result<(), scanner_terminal::TerminalError> {
+///     use scanner_terminal::{Terminal, Price};
+///
+///     let mut terminal = setup_pricing!('A' => [{ price: 2 }, { min: 4, price: 7 }]; 'B' => [{ price: 12 }]; 'C' => [{ price: 1.25 }, { min: 6, price: 6 }]; 'D' => [{ price: 0.15 }])?;
+///
+///     // As items are scanned the number of items scanned is tracked
+///     terminal.scan('A')?;
+///     terminal.scan('B')?;
+///     terminal.scan('C')?;
+///     terminal.scan('D')?;
+///     terminal.scan('A')?;
+///     terminal.scan('B')?;
+///     terminal.scan('A')?;
+///     terminal.scan('A')?;
+///
+///     // The total gives checks price tiers
+///     assert_eq!(terminal.total()?, dec!(32.40));
+/// # Ok(())
+/// # }
+/// ```
+///
+/// Keys can be any literal that can be written out as a `Sku`, so string codes and numeric item
+/// codes work as well.
+///
+/// ```
+/// # #[macro_use] extern crate scanner_terminal; #[macro_use] extern crate rust_decimal_macros; fn main() -> Result<(), scanner_terminal::TerminalError> {
+///     use scanner_terminal::{Terminal, Price};
+///
+///     let mut terminal = setup_pricing!("BAN-001" => [{ price: 0.25 }]; 4011 => [{ price: 0.59 }, { min: 3, price: 1.5 }])?;
+///
+///     terminal.scan("BAN-001")?;
+///     terminal.scan(4011_u64)?;
+///     terminal.scan(4011_u64)?;
+///     terminal.scan(4011_u64)?;
+///
+///     assert_eq!(terminal.total()?, dec!(1.75));
+/// # Ok(())
+/// # }
+/// ```
+#[macro_export]
+macro_rules! setup_pricing(
+    { $($key:literal => $($value:tt), + ); + } => {
+        {
+            let mut m = ::std::collections::HashMap::new();
+
+            $(
+                let mut v = vec![];
+
+                $(
+                    for iv in parse_price!($value) {
+                        v.push(iv)
+                    }
+                )*;
+
+                m.insert($crate::Sku::new($key.to_string()), v);
+            )+
+
+            $crate::Terminal::new(m)
+        }
+     };
+);
+
+#[macro_export]
+macro_rules! parse_price(
+    ($price:literal) => {
+        {
+            vec![Price::new(0, dec!($price))];
+        }
+     };
+    ([{ price: $price:literal }$(,)? $({ min: $bulk_quantity:literal, price: $bulk_price:literal }), *]) => {
+        {
             vec![
-                Price {
-                    min: 0,
-                    price: dec!(2)
+                Price::new(0, dec!($price))
+                $(, Price::new($bulk_quantity, dec!($bulk_price)))*
+            ]
+        }
+     };
+);
+
+#[cfg(test)]
+mod tests {
+    use super::barcode::{BarcodeError, EmbeddedCodes, EmbeddedRule};
+    use super::coupon::{Coupon, Requirement};
+    use super::journal::{Journal, Operation};
+    use super::loyalty::{Ledger, Redemption, Rules};
+    use super::member::{Level, Member};
+    use super::render::{Renderer, Width};
+    use super::replay::{self, SavedTransaction};
+    use super::schedule::{Clock, FixedClock, Schedule, Weekday, Window};
+    use super::{
+        Cheapest, Conflict, Discount, IssueKind, Item, Price, Promotion, Reason, Terminal,
+        TerminalError, Unit, MAX_QUANTITY,
+    };
+
+    use std::collections::HashMap;
+
+    use rust_decimal::Decimal;
+    use rust_decimal_macros::dec;
+
+    use std::sync::Mutex;
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    #[test]
+    fn it_parses() {
+        assert_eq!(
+            parse_price!([{ price: 2 }, { min: 4, price: 7 }]),
+            vec![Price::new(0, dec!(2)), Price::new(4, dec!(7))]
+        );
+    }
+
+    /// Tries every combination of tiers that adds up to exactly `count` units.
+    fn brute_force(prices: &[Price], count: usize) -> Option<Decimal> {
+        if count == 0 {
+            return Some(dec!(0));
+        }
+
+        prices
+            .iter()
+            .filter(|p| p.min.max(1) <= count)
+            .filter_map(|p| brute_force(prices, count - p.min.max(1)).map(|rest| rest + p.price))
+            .min()
+    }
+
+    /// Small deterministic generator so the layouts are the same on every run.
+    fn layouts() -> Vec<Vec<Price>> {
+        let mut seed: u64 = 0x5eed;
+
+        let mut next = move |bound: u64| {
+            seed = seed.wrapping_mul(6_364_136_223_846_793_005).wrapping_add(1);
+
+            (seed >> 33) % bound
+        };
+
+        (0..40)
+            .map(|i| {
+                // every fourth layout has no single item price at all
+                let mut prices = if i % 4 == 3 {
+                    vec![]
+                } else {
+                    vec![Price::new(0, Decimal::new(50 + next(200) as i64, 2))]
+                };
+
+                for _ in 0..1 + next(3) {
+                    prices.push(Price::new(
+                        2 + next(6) as usize,
+                        Decimal::new(100 + next(900) as i64, 2),
+                    ));
+                }
+
+                prices
+            })
+            .collect()
+    }
+
+    #[test]
+    fn it_matches_brute_force() {
+        for prices in layouts() {
+            for count in 0..=14 {
+                let found = Cheapest::new(&prices, count).unwrap().tiers(count);
+
+                assert_eq!(
+                    found.as_ref().map(|(cost, _)| *cost),
+                    brute_force(&prices, count),
+                    "{:?} with {} items",
+                    prices,
+                    count
+                );
+
+                if let Some((cost, used)) = found {
+                    let units: usize = used
+                        .iter()
+                        .zip(&prices)
+                        .map(|(n, p)| n * p.min.max(1))
+                        .sum();
+
+                    let priced: Decimal = used
+                        .iter()
+                        .zip(&prices)
+                        .map(|(n, p)| p.price * Decimal::from(*n as u64))
+                        .sum();
+
+                    assert_eq!(units, count);
+                    assert_eq!(priced, cost);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn it_beats_greedy() -> Result<(), TerminalError> {
+        let mut terminal =
+            setup_pricing!('C' => [{ price: 1.25 }, { min: 4, price: 5 }, { min: 6, price: 6 }])?;
+
+        for _ in 0..10 {
+            terminal.scan('C')?;
+        }
+
+        assert_eq!(terminal.total()?, dec!(11));
+
+        let mut terminal =
+            setup_pricing!('A' => [{ price: 1 }, { min: 3, price: 2.5 }, { min: 5, price: 4.5 }])?;
+
+        for _ in 0..6 {
+            terminal.scan('A')?;
+        }
+
+        assert_eq!(terminal.total()?, dec!(5));
+
+        Ok(())
+    }
+
+    #[test]
+    fn it_rejects_items_without_a_single_price() {
+        let mut prices = HashMap::new();
+
+        prices.insert('A', vec![Price::new(4, dec!(7))]);
+
+        assert!(matches!(
+            Terminal::new(prices),
+            Err(TerminalError::InvalidCatalog(_))
+        ));
+    }
+
+    #[test]
+    fn it_reports_overflow() -> Result<(), TerminalError> {
+        let mut prices = HashMap::new();
+
+        prices.insert('A', vec![Price::new(0, Decimal::MAX)]);
+
+        let mut terminal = Terminal::new(prices)?;
+
+        terminal.scan('A')?;
+
+        assert_eq!(terminal.total()?, Decimal::MAX);
+
+        terminal.scan('A')?;
+
+        assert_eq!(terminal.total(), Err(TerminalError::Overflow));
+
+        // the override saves as much as a Decimal holds, so the free B can't be added to it
+        let mut prices = HashMap::new();
+
+        prices.insert('A', vec![Price::new(0, Decimal::MAX)]);
+        prices.insert('B', vec![Price::new(0, dec!(10))]);
+
+        let mut terminal = Terminal::new(prices)?;
+
+        terminal.add_promotion(Promotion::cheapest_free("2 for 1", vec!['B'], 2))?;
+        terminal.scan('A')?;
+        terminal.override_price('A', Decimal::ZERO)?;
+        terminal.scan('B')?;
+
+        assert_eq!(terminal.receipt()?.savings(), Decimal::MAX);
+
+        terminal.scan('B')?;
+
+        assert_eq!(terminal.receipt(), Err(TerminalError::Overflow));
+
+        Ok(())
+    }
+
+    #[test]
+    fn it_rejects_quantities_no_basket_could_hold() -> Result<(), TerminalError> {
+        let mut terminal = setup_pricing!('A' => [{ price: 1 }, { min: 3, price: 2.5 }])?;
+
+        terminal.scan('A')?;
+
+        assert_eq!(
+            terminal.set_quantity('A', usize::MAX),
+            Err(TerminalError::InvalidQuantity(usize::MAX))
+        );
+        assert_eq!(terminal.total()?, dec!(1));
+
+        terminal.set_quantity('A', MAX_QUANTITY)?;
+
+        assert_eq!(
+            terminal.scan('A'),
+            Err(TerminalError::InvalidQuantity(MAX_QUANTITY + 1))
+        );
+        assert_eq!(terminal.total()?, dec!(83333.5));
+
+        Ok(())
+    }
+
+    #[test]
+    fn it_rounds_weighed_items_to_cents() -> Result<(), TerminalError> {
+        let mut prices = HashMap::new();
+
+        prices.insert('A', Item::weighed(Unit::Kg, dec!(1.50)));
+        prices.insert('B', Item::weighed(Unit::Kg, dec!(2.10)));
+
+        let mut terminal = Terminal::new(prices)?;
+
+        // 0.4995 rounds up and 0.525 goes away from zero instead of to the even cent
+        terminal.scan_weight('A', dec!(0.333), Unit::Kg)?;
+        terminal.scan_weight('B', dec!(0.25), Unit::Kg)?;
+
+        assert_eq!(terminal.total()?, dec!(1.03));
+
+        assert_eq!(
+            terminal.scan_weight('A', dec!(0), Unit::Kg),
+            Err(TerminalError::InvalidWeight(dec!(0)))
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn it_rejects_tiers_on_weighed_items() {
+        let mut prices = HashMap::new();
+
+        let mut item = Item::weighed(Unit::Lb, dec!(3));
+
+        item.prices.push(Price::new(2, dec!(5)));
+
+        prices.insert('A', item);
+
+        match Terminal::new(prices) {
+            Err(TerminalError::InvalidCatalog(report)) => {
+                assert_eq!(report.issues()[0].kind, IssueKind::TierOnWeighedItem(2))
+            }
+            _ => panic!("tiers on a weighed item should be rejected"),
+        }
+    }
+
+    #[test]
+    fn it_reprices_after_the_basket_shrinks() -> Result<(), TerminalError> {
+        let mut terminal =
+            setup_pricing!('A' => [{ price: 1 }, { min: 3, price: 2.5 }, { min: 5, price: 4.2 }])?;
+
+        terminal.set_quantity('A', 8)?;
+
+        // a 5 pack and a 3 pack
+        assert_eq!(terminal.total()?, dec!(6.7));
+
+        terminal.void('A')?;
+
+        // the 5 pack is dropped for two 3 packs and a single
+        assert_eq!(terminal.total()?, dec!(6));
+
+        terminal.set_quantity('A', 6)?;
+
+        // two 3 packs beat a 5 pack and a single
+        assert_eq!(terminal.total()?, dec!(5));
+
+        terminal.set_quantity('A', 0)?;
+
+        assert_eq!(
+            terminal.void('A'),
+            Err(TerminalError::NotInBasket('A'.into()))
+        );
+
+        terminal.scan('A')?;
+        terminal.clear();
+
+        assert_eq!(terminal.total()?, dec!(0));
+        assert!(terminal.receipt()?.lines().is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn it_rebuilds_the_basket_from_the_journal() -> Result<(), TerminalError> {
+        let mut terminal =
+            setup_pricing!('A' => [{ price: 2 }, { min: 4, price: 7 }]; 'B' => [{ price: 12 }])?;
+
+        terminal.set_quantity('A', 5)?;
+        terminal.scan('B')?;
+        terminal.override_price('B', dec!(10))?;
+        terminal.void('A')?;
+        terminal.scan('B')?;
+        terminal.undo()?;
+        terminal.undo()?;
+        terminal.redo()?;
+
+        // failed operations aren't journaled
+        assert!(terminal.void('C').is_err());
+
+        let sequences: Vec<u64> = terminal
+            .journal()
+            .entries()
+            .iter()
+            .map(|e| e.sequence)
+            .collect();
+
+        assert_eq!(sequences, (1..=8).collect::<Vec<_>>());
+
+        let mut rebuilt =
+            setup_pricing!('A' => [{ price: 2 }, { min: 4, price: 7 }]; 'B' => [{ price: 12 }])?;
+
+        rebuilt.restore(terminal.journal().clone())?;
+
+        // 4 A for 7 and one B at the overridden price
+        assert_eq!(rebuilt.total()?, dec!(17));
+        assert_eq!(rebuilt.receipt()?, terminal.receipt()?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn it_reads_back_codes_with_tabs_and_line_breaks() -> Result<(), TerminalError> {
+        let mut terminal = setup_pricing!("A\tB" => [{ price: 2 }]; "C\r" => [{ price: 3 }]; "D\r\n\\" => [{ price: 4 }])?;
+
+        terminal.scan("A\tB")?;
+        terminal.scan("C\r")?;
+        terminal.set_quantity("D\r\n\\", 2)?;
+        terminal.void("C\r")?;
+        terminal.scan("C\r")?;
+
+        let journal = Journal::parse(&terminal.journal().to_string())?;
+
+        assert_eq!(&journal, terminal.journal());
+
+        terminal.restore(journal)?;
+
+        assert_eq!(terminal.total()?, dec!(13));
+
+        Ok(())
+    }
+
+    #[test]
+    fn it_drops_coupons_that_stop_qualifying() -> Result<(), TerminalError> {
+        let mut terminal = setup_pricing!('A' => [{ price: 2 }]; 'B' => [{ price: 12 }])?;
+
+        terminal.add_coupon(
+            Coupon::new("A50", "50c off A", vec!['A'], Discount::Amount(dec!(0.5))).limit(2),
+        )?;
+
+        terminal.set_quantity('A', 2)?;
+        terminal.scan('B')?;
+        terminal.redeem("A50")?;
+        terminal.redeem("A50")?;
+
+        assert_eq!(terminal.total()?, dec!(15));
+
+        // the second use needs a second A
+        terminal.void('A')?;
+
+        assert_eq!(terminal.total()?, dec!(13.5));
+
+        // scanning it again doesn't bring the coupon back, undoing the void does
+        terminal.scan('A')?;
+
+        assert_eq!(terminal.total()?, dec!(15.5));
+
+        terminal.undo()?;
+        terminal.undo()?;
+
+        assert_eq!(terminal.total()?, dec!(15));
+
+        let mut rebuilt = setup_pricing!('A' => [{ price: 2 }]; 'B' => [{ price: 12 }])?;
+
+        rebuilt.add_coupon(
+            Coupon::new("A50", "50c off A", vec!['A'], Discount::Amount(dec!(0.5))).limit(2),
+        )?;
+        rebuilt.restore(Journal::parse(&terminal.journal().to_string())?)?;
+
+        assert_eq!(rebuilt.receipt()?, terminal.receipt()?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn it_leaves_the_basket_alone_when_a_change_fails() -> Result<(), TerminalError> {
+        let mut prices = HashMap::new();
+
+        prices.insert('A', vec![Price::new(0, Decimal::MAX / Decimal::from(4))]);
+
+        let mut terminal = Terminal::new(prices)?;
+
+        terminal.add_coupon(
+            Coupon::new("A50", "50c off A", vec!['A'], Discount::Amount(dec!(0.5)))
+                .requirement(Requirement::Spend(dec!(1))),
+        )?;
+
+        terminal.scan('A')?;
+        terminal.redeem("A50")?;
+
+        let before = terminal.receipt()?;
+
+        // checking the coupon's spend overflows, so neither the quantity nor the coupon change
+        assert_eq!(terminal.set_quantity('A', 5), Err(TerminalError::Overflow));
+        assert_eq!(terminal.receipt()?, before);
+        assert_eq!(terminal.receipt()?.adjustments().len(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn it_breaks_ties_between_promotions_by_priority() -> Result<(), TerminalError> {
+        let mut terminal = setup_pricing!('A' => [{ price: 4 }]; 'B' => [{ price: 4 }])?;
+
+        terminal.add_promotion(Promotion::mix_and_match("Pair", vec!['A', 'B'], 2, dec!(6)))?;
+        terminal.add_promotion(
+            Promotion::mix_and_match("Two for 6", vec!['A', 'B'], 2, dec!(6)).priority(1),
+        )?;
+
+        terminal.scan('A')?;
+        terminal.scan('B')?;
+
+        let receipt = terminal.receipt()?;
+
+        assert_eq!(receipt.total(), dec!(6));
+        assert!(receipt
+            .lines()
+            .iter()
+            .all(|l| l.deals[0].promotion == "Two for 6"));
+        assert!(receipt.conflicts().is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn it_picks_exclusive_promotions_by_priority_before_savings() -> Result<(), TerminalError> {
+        let mut terminal =
+            setup_pricing!('A' => [{ price: 5 }, { min: 3, price: 12 }]; 'B' => [{ price: 5 }])?;
+
+        terminal.add_promotion(
+            Promotion::spend(
+                "10% off",
+                dec!(0),
+                Discount::Percent(dec!(10)),
+                Vec::<char>::new(),
+            )
+            .exclusive("weekly"),
+        )?;
+        terminal.add_promotion(
+            Promotion::mix_and_match("A and B for 9", vec!['A', 'B'], 2, dec!(9))
+                .priority(1)
+                .exclusive("weekly"),
+        )?;
+        // never saves anything, so it doesn't count as losing
+        terminal.add_promotion(
+            Promotion::mix_and_match("B for 6", vec!['B'], 1, dec!(6))
+                .priority(2)
+                .exclusive("weekly"),
+        )?;
+
+        terminal.set_quantity('A', 4)?;
+        terminal.scan('B')?;
+
+        // 3 A on their tier and A with B for 9 comes to 21, where 10% off would have been 19.80
+        let receipt = terminal.receipt()?;
+
+        assert_eq!(receipt.total(), dec!(21));
+        assert_eq!(
+            receipt.conflicts(),
+            &[Conflict {
+                promotion: "10% off".to_string(),
+                winner: "A and B for 9".to_string(),
+                reason: Reason::Priority {
+                    group: "weekly".to_string()
                 },
-                Price {
-                    min: 4,
-                    price: dec!(7)
+            }]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn it_keeps_unstackable_units_out_of_basket_discounts() -> Result<(), TerminalError> {
+        let mut terminal = setup_pricing!('A' => [{ price: 6 }]; 'B' => [{ price: 6 }]; 'C' => [{ price: 2.5 }, { min: 2, price: 4 }])?;
+
+        terminal.add_promotion(
+            Promotion::mix_and_match("Any 2 for 10", vec!['A', 'B'], 2, dec!(10)).stackable(false),
+        )?;
+        terminal.add_promotion(Promotion::spend(
+            "$2 off $8",
+            dec!(8),
+            Discount::Amount(dec!(2)),
+            Vec::<char>::new(),
+        ))?;
+
+        terminal.scan('A')?;
+        terminal.scan('B')?;
+        terminal.set_quantity('C', 2)?;
+
+        // only the two C on their tier count, $4 isn't enough
+        let receipt = terminal.receipt()?;
+
+        assert_eq!(receipt.total(), dec!(14));
+        assert_eq!(receipt.conflicts()[0].reason, Reason::NotStackable);
+
+        terminal.set_quantity('C', 4)?;
+
+        // $8 of C gets the discount, and all of it goes on C
+        let receipt = terminal.receipt()?;
+
+        assert_eq!(receipt.total(), dec!(16));
+        assert_eq!(
+            receipt.adjustments()[0].shares,
+            vec![('C'.into(), dec!(-2))]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn it_skips_discounted_units_for_unstackable_basket_discounts() -> Result<(), TerminalError> {
+        let mut terminal = setup_pricing!('A' => [{ price: 4 }]; 'B' => [{ price: 10 }])?;
+
+        terminal.add_promotion(Promotion::buy_get(
+            "BOGO",
+            vec!['A'],
+            1,
+            vec!['A'],
+            1,
+            dec!(100),
+        ))?;
+        terminal.add_promotion(
+            Promotion::spend(
+                "10% off",
+                dec!(0),
+                Discount::Percent(dec!(10)),
+                Vec::<char>::new(),
+            )
+            .stackable(false),
+        )?;
+
+        terminal.set_quantity('A', 3)?;
+        terminal.scan('B')?;
+
+        // two A for 4, then 10% off the third A and B
+        let receipt = terminal.receipt()?;
+
+        assert_eq!(receipt.total(), dec!(16.6));
+        assert_eq!(
+            receipt.adjustments()[1].shares,
+            vec![('A'.into(), dec!(-0.4)), ('B'.into(), dec!(-1))]
+        );
+        assert_eq!(receipt.conflicts()[0].winner, "BOGO");
+
+        Ok(())
+    }
+
+    #[test]
+    fn it_reprices_when_a_window_closes_mid_transaction() -> Result<(), TerminalError> {
+        let happy_hour = Schedule::new().window(Window::new(&Weekday::WEEKDAYS, (16, 0), (18, 0)));
+
+        let mut terminal = setup_pricing!('A' => [{ price: 5 }]; 'B' => [{ price: 4 }])?;
+
+        terminal.add_promotion(
+            Promotion::mix_and_match("Happy hour pair", vec!['A', 'B'], 2, dec!(6))
+                .during(happy_hour),
+        )?;
+
+        // Friday 2 October 2026, 17:58 in a store two hours east of UTC
+        let clock = FixedClock::new(UNIX_EPOCH + Duration::from_secs(1_790_956_680));
+
+        terminal.set_clock(clock.clone());
+        terminal.set_utc_offset(120);
+
+        terminal.scan('A')?;
+        terminal.scan('B')?;
+
+        assert_eq!(terminal.total()?, dec!(6));
+
+        let during = terminal.save()?;
+
+        clock.advance(Duration::from_secs(2 * 60));
+        terminal.scan('A')?;
+
+        // the pair was rung up in time but the basket is priced when it's totalled
+        assert_eq!(terminal.total()?, dec!(14));
+        assert_eq!(
+            terminal.journal().entries()[2].timestamp,
+            UNIX_EPOCH + Duration::from_secs(1_790_956_800)
+        );
+
+        // a replay is priced at the time that was saved, not when it is replayed
+        let mut replayed = setup_pricing!('A' => [{ price: 5 }]; 'B' => [{ price: 4 }])?;
+
+        replayed.add_promotion(
+            Promotion::mix_and_match("Happy hour pair", vec!['A', 'B'], 2, dec!(6))
+                .during(Schedule::new().window(Window::new(&Weekday::WEEKDAYS, (16, 0), (18, 0)))),
+        )?;
+        replayed.set_utc_offset(120);
+
+        let replayed = replay::replay(replayed, &SavedTransaction::parse(&during.to_string())?)?;
+
+        assert!(replayed.matches());
+
+        Ok(())
+    }
+
+    /// A clock that moves on a second every time it is read.
+    struct Ticking(Mutex<SystemTime>);
+
+    impl Clock for Ticking {
+        fn now(&self) -> SystemTime {
+            let mut time = self.0.lock().unwrap();
+            let now = *time;
+
+            *time += Duration::from_secs(1);
+
+            now
+        }
+    }
+
+    #[test]
+    fn it_saves_the_instant_a_transaction_was_priced_at() -> Result<(), TerminalError> {
+        let happy_hour = || {
+            Promotion::mix_and_match("Happy hour pair", vec!['A', 'B'], 2, dec!(6))
+                .during(Schedule::new().window(Window::new(&Weekday::WEEKDAYS, (16, 0), (18, 0))))
+        };
+
+        let mut terminal = setup_pricing!('A' => [{ price: 5 }]; 'B' => [{ price: 4 }])?;
+
+        terminal.add_promotion(happy_hour())?;
+        terminal.set_utc_offset(120);
+        terminal.set_clock(FixedClock::new(
+            UNIX_EPOCH + Duration::from_secs(1_790_956_680),
+        ));
+
+        terminal.scan('A')?;
+        terminal.scan('B')?;
+
+        // Friday 2 October 2026, a second before happy hour ends
+        let closing = UNIX_EPOCH + Duration::from_secs(1_790_956_799);
+
+        terminal.set_clock(Ticking(Mutex::new(closing)));
+
+        let saved = terminal.save()?;
+
+        assert_eq!(saved.priced_at, closing);
+        assert_eq!(saved.total, dec!(6));
+
+        let mut replayed = setup_pricing!('A' => [{ price: 5 }]; 'B' => [{ price: 4 }])?;
+
+        replayed.add_promotion(happy_hour())?;
+        replayed.set_utc_offset(120);
+
+        assert!(replay::replay(replayed, &saved)?.matches());
+
+        Ok(())
+    }
+
+    #[test]
+    fn it_journals_an_operation_at_the_time_it_was_checked() -> Result<(), TerminalError> {
+        let mut terminal = setup_pricing!('A' => [{ price: 5 }])?;
+        let opened = UNIX_EPOCH + Duration::from_secs(1_790_956_680);
+
+        terminal.set_clock(Ticking(Mutex::new(opened)));
+        terminal.scan('A')?;
+        terminal.void('A')?;
+
+        let times: Vec<SystemTime> = terminal
+            .journal()
+            .entries()
+            .iter()
+            .map(|e| e.timestamp)
+            .collect();
+
+        assert_eq!(times, vec![opened, opened + Duration::from_secs(1)]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn it_moves_a_terminal_with_its_clock_to_another_thread() -> Result<(), TerminalError> {
+        let mut terminal = setup_pricing!('A' => [{ price: 5 }])?;
+        let clock = FixedClock::new(UNIX_EPOCH + Duration::from_secs(1_790_956_680));
+
+        terminal.set_clock(clock.clone());
+
+        let terminal = std::thread::spawn(move || -> Result<Terminal, TerminalError> {
+            terminal.scan('A')?;
+
+            Ok(terminal)
+        })
+        .join()
+        .expect("the terminal's thread panicked")?;
+
+        clock.advance(Duration::from_secs(60));
+
+        assert_eq!(terminal.total()?, dec!(5));
+        assert_eq!(
+            terminal.journal().entries()[0].timestamp,
+            UNIX_EPOCH + Duration::from_secs(1_790_956_680)
+        );
+        assert_eq!(
+            terminal.now(),
+            UNIX_EPOCH + Duration::from_secs(1_790_956_740)
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn it_prices_weight_barcodes_when_the_basket_is_totalled() -> Result<(), TerminalError> {
+        let starts = UNIX_EPOCH + Duration::from_secs(1_790_000_000);
+        let weekly_ad = Schedule::new().starts(starts);
+
+        let mut prices = HashMap::new();
+
+        prices.insert(
+            "02101234000007",
+            Item {
+                prices: vec![
+                    Price::new(0, dec!(2.20)),
+                    Price::new(0, dec!(1.80)).during(weekly_ad),
+                ],
+                ..Item::weighed(Unit::Kg, dec!(2.20))
+            },
+        );
+
+        let mut terminal = Terminal::new(prices)?;
+
+        let clock = FixedClock::new(starts - Duration::from_secs(1));
+
+        terminal.set_clock(clock.clone());
+        terminal.set_embedded_codes(
+            EmbeddedCodes::new().rule(21, EmbeddedRule::weight(5, 3, Unit::Kg)),
+        )?;
+        terminal.scan_barcode("2101234012505")?;
+
+        assert_eq!(
+            terminal.journal().entries()[0].operation,
+            Operation::ScanWeight {
+                item: "02101234000007".into(),
+                weight: dec!(1.250)
+            }
+        );
+        assert_eq!(terminal.total()?, dec!(2.75));
+
+        // the weight is kept, so the apples go on sale with the rest of the basket
+        clock.set(starts);
+
+        assert_eq!(terminal.total()?, dec!(2.25));
+
+        Ok(())
+    }
+
+    #[test]
+    fn it_converts_barcode_weights_to_the_unit_items_are_sold_by() -> Result<(), TerminalError> {
+        let mut prices = HashMap::new();
+
+        prices.insert("02201234000004", Item::weighed(Unit::Kg, dec!(10)));
+        prices.insert("02205678000002", Item::new(vec![Price::new(0, dec!(3))]));
+
+        let mut terminal = Terminal::new(prices)?;
+
+        // these scales print pounds
+        terminal.set_embedded_codes(
+            EmbeddedCodes::new().rule(22, EmbeddedRule::weight(5, 3, Unit::Lb)),
+        )?;
+
+        // 1 lb is 0.45359237 kg
+        terminal.scan_barcode("2201234010003")?;
+
+        assert_eq!(terminal.total()?, dec!(4.54));
+
+        // a counted item can't be sold by the weight on a label
+        assert_eq!(
+            terminal.scan_barcode("2205678002501"),
+            Err(TerminalError::WrongUnit {
+                item: "02205678000002".into(),
+                unit: Unit::Each
+            })
+        );
+        assert_eq!(
+            terminal.set_embedded_codes(EmbeddedCodes::new().rule(22, EmbeddedRule::price(5, 29))),
+            Err(TerminalError::InvalidBarcode(
+                BarcodeError::TooManyDecimals(29)
+            ))
+        );
+        assert_eq!(
+            terminal.set_embedded_codes(
+                EmbeddedCodes::new().rule(22, EmbeddedRule::price(9, 2).with_check_digit())
+            ),
+            Err(TerminalError::InvalidBarcode(
+                BarcodeError::NoRoomForValue {
+                    item_digits: 9,
+                    check_digit: true
                 }
-            ]
+            ))
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn it_replays_each_operation_at_the_prices_it_was_done_at() -> Result<(), TerminalError> {
+        let starts = UNIX_EPOCH + Duration::from_secs(1_790_000_000);
+
+        let mut prices = HashMap::new();
+
+        prices.insert(
+            'A',
+            Item::new(vec![
+                Price::new(0, dec!(5)),
+                Price::new(0, dec!(4)).during(Schedule::new().starts(starts)),
+            ]),
+        );
+        prices.insert('B', Item::new(vec![Price::new(0, dec!(1))]));
+
+        let mut terminal = Terminal::new(prices)?;
+
+        terminal.add_coupon(
+            Coupon::new(
+                "A10",
+                "$1 off $10 of A",
+                vec!['A'],
+                Discount::Amount(dec!(1)),
+            )
+            .requirement(Requirement::Spend(dec!(10))),
+        )?;
+
+        let clock = FixedClock::new(starts - Duration::from_secs(60));
+
+        terminal.set_clock(clock.clone());
+        terminal.set_quantity('A', 2)?;
+        terminal.redeem("A10")?;
+
+        // the coupon was taken when two A came to $10, the sale that starts later doesn't
+        // undo it
+        clock.set(starts);
+        terminal.scan('B')?;
+        terminal.undo()?;
+
+        assert_eq!(terminal.total()?, dec!(7));
+
+        let before = terminal.receipt()?;
+
+        terminal.restore(Journal::parse(&terminal.journal().to_string())?)?;
+
+        assert_eq!(terminal.receipt()?, before);
+
+        Ok(())
+    }
+
+    #[test]
+    fn it_uses_scheduled_prices_between_their_dates() -> Result<(), TerminalError> {
+        let starts = UNIX_EPOCH + Duration::from_secs(1_790_000_000);
+        let ends = starts + Duration::from_secs(7 * 24 * 60 * 60);
+
+        let weekly_ad = Schedule::new().starts(starts).ends(ends);
+
+        let mut prices = HashMap::new();
+
+        prices.insert(
+            'A',
+            Item::new(vec![
+                Price::new(0, dec!(2)),
+                Price::new(4, dec!(7)),
+                Price::new(0, dec!(1.5)).during(weekly_ad.clone()),
+                Price::new(3, dec!(4)).during(weekly_ad.clone()),
+            ]),
+        );
+
+        let mut terminal = Terminal::new(prices)?;
+
+        let clock = FixedClock::new(starts - Duration::from_secs(1));
+
+        terminal.set_clock(clock.clone());
+        terminal.set_quantity('A', 4)?;
+
+        assert_eq!(terminal.total()?, dec!(7));
+
+        // the ad's 3 for 4 and a single at the ad price
+        clock.set(starts);
+
+        assert_eq!(terminal.total()?, dec!(5.5));
+
+        clock.set(ends);
+
+        assert_eq!(terminal.total()?, dec!(7));
+
+        // a scheduled price can't stand in for the regular one
+        let mut prices = HashMap::new();
+
+        prices.insert('A', vec![Price::new(0, dec!(2)).during(weekly_ad)]);
+
+        match Terminal::new(prices) {
+            Err(TerminalError::InvalidCatalog(report)) => {
+                assert_eq!(report.issues()[0].kind, IssueKind::MissingBasePrice)
+            }
+            _ => panic!("an item needs a regular price"),
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn it_reprices_the_basket_for_a_member() -> Result<(), TerminalError> {
+        let mut prices = HashMap::new();
+
+        prices.insert(
+            'A',
+            Item::new(vec![
+                Price::new(0, dec!(3)),
+                Price::new(0, dec!(2.5)).members(Level::Silver),
+                Price::new(0, dec!(2.25)).members(Level::Gold),
+            ]),
+        );
+        prices.insert(
+            'B',
+            Item::new(vec![
+                Price::new(0, dec!(4)),
+                Price::new(3, dec!(10)).members(Level::Gold),
+            ]),
+        );
+
+        let mut terminal = Terminal::new(prices)?;
+
+        terminal.set_quantity('A', 2)?;
+        terminal.set_quantity('B', 3)?;
+
+        assert_eq!(terminal.total()?, dec!(18));
+        assert_eq!(terminal.receipt()?.member_savings(), dec!(0));
+
+        terminal.attach_member(Member::new("1001", Level::Silver))?;
+
+        assert_eq!(terminal.total()?, dec!(17));
+
+        // replacing the member reprices everything again
+        terminal.attach_member(Member::new("1002", Level::Gold))?;
+
+        let receipt = terminal.receipt()?;
+
+        assert_eq!(receipt.total(), dec!(14.5));
+        assert_eq!(receipt.member_savings(), dec!(3.5));
+        assert_eq!(receipt.member().map(|m| m.id.as_str()), Some("1002"));
+
+        let text = Renderer::new(Width::Narrow).render_text(&receipt);
+
+        assert!(text.contains("Member (gold)               1002"));
+        assert!(text.contains("MEMBER SAVINGS              3.50"));
+
+        let mut rebuilt = Terminal::new(HashMap::<char, Item>::new())?;
+
+        rebuilt.catalog = terminal.catalog.clone();
+        rebuilt.restore(Journal::parse(&terminal.journal().to_string())?)?;
+
+        assert_eq!(rebuilt.receipt()?, receipt);
+
+        terminal.detach_member()?;
+
+        assert_eq!(terminal.total()?, dec!(18));
+        assert_eq!(terminal.detach_member(), Err(TerminalError::NoMember));
+
+        Ok(())
+    }
+
+    #[test]
+    fn it_pays_with_points_up_to_the_total() -> Result<(), TerminalError> {
+        let dir = std::env::temp_dir().join(format!("points-tender-{}", std::process::id()));
+
+        std::fs::remove_dir_all(&dir).ok();
+
+        let ledger = Ledger::new(&dir);
+        let rules = Rules::new(dec!(10), dec!(0.01)).bonus('A', 50);
+
+        let mut terminal = setup_pricing!('A' => [{ price: 2.5 }]; 'B' => [{ price: 20 }])?;
+
+        terminal.set_loyalty(rules.clone(), ledger.clone());
+        terminal.attach_member(Member::new("1001", Level::Gold))?;
+        terminal.set_quantity('A', 2)?;
+        terminal.scan('B')?;
+
+        // 250 points for $25 and 50 for each A
+        assert_eq!(terminal.commit("T1")?.points_earned(), 350);
+
+        let mut terminal = setup_pricing!('A' => [{ price: 2.5 }]; 'B' => [{ price: 20 }])?;
+
+        terminal.set_loyalty(rules, ledger.clone());
+
+        assert_eq!(
+            terminal.redeem_points(100, Redemption::Tender),
+            Err(TerminalError::NoMember)
+        );
+
+        terminal.attach_member(Member::new("1001", Level::Gold))?;
+        terminal.scan('A')?;
+
+        assert_eq!(
+            terminal.redeem_points(400, Redemption::Tender),
+            Err(TerminalError::NotEnoughPoints {
+                member: "1001".to_string(),
+                balance: 350
+            })
+        );
+
+        terminal.redeem_points(300, Redemption::Tender)?;
+
+        // $3 of points pays for the $2.50, and only the bonus is earned
+        let receipt = terminal.receipt()?;
+
+        assert_eq!(receipt.total(), dec!(2.5));
+        assert_eq!(receipt.points_tendered(), dec!(2.5));
+        assert_eq!(receipt.amount_due(), dec!(0));
+        assert_eq!(receipt.points_redeemed(), 250);
+        assert_eq!(receipt.points_earned(), 50);
+
+        let text = Renderer::new(Width::Narrow).render_text(&receipt);
+
+        assert!(text.contains("Paid with points           -2.50"));
+        assert!(text.contains("DUE                         0.00"));
+
+        let mut rebuilt = setup_pricing!('A' => [{ price: 2.5 }]; 'B' => [{ price: 20 }])?;
+
+        rebuilt.set_loyalty(
+            Rules::new(dec!(10), dec!(0.01)).bonus('A', 50),
+            ledger.clone(),
         );
+        rebuilt.restore(Journal::parse(&terminal.journal().to_string())?)?;
+
+        assert_eq!(rebuilt.receipt()?, receipt);
+
+        terminal.commit("T2")?;
+
+        assert_eq!(ledger.balance("1001")?, 150);
+
+        std::fs::remove_dir_all(&dir).ok();
+
+        Ok(())
     }
 }
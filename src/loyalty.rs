@@ -0,0 +1,635 @@
+//! Points members earn on what they spend and can spend again, kept in a ledger with one file
+//! per member.
+//!
+//! ```
+//! # #[macro_use] extern crate scanner_terminal; #[macro_use] extern crate rust_decimal_macros; fn main() -> Result<(), scanner_terminal::TerminalError> {
+//!     use scanner_terminal::loyalty::{Ledger, Redemption, Rules};
+//!     use scanner_terminal::member::{Level, Member};
+//!     use scanner_terminal::{Terminal, Price};
+//!
+//!     let dir = std::env::temp_dir().join(format!("loyalty-doc-{}", std::process::id()));
+//!     let ledger = Ledger::new(&dir);
+//!
+//!     // 1 point per dollar and 10 times the points on A, 100 points are worth $1
+//!     let rules = Rules::new(dec!(1), dec!(0.01)).multiplier('A', dec!(10));
+//!
+//!     let mut terminal = setup_pricing!('A' => [{ price: 2.5 }]; 'B' => [{ price: 20 }])?;
+//!
+//!     terminal.set_loyalty(rules.clone(), ledger.clone());
+//!     terminal.attach_member(Member::new("1001", Level::Silver))?;
+//!     terminal.set_quantity('A', 4)?;
+//!     terminal.scan('B')?;
+//!
+//!     // 100 points for the A and 20 for B
+//!     assert_eq!(terminal.commit("T1")?.points_earned(), 120);
+//!     assert_eq!(ledger.balance("1001")?, 120);
+//!
+//!     let mut terminal = setup_pricing!('A' => [{ price: 2.5 }]; 'B' => [{ price: 20 }])?;
+//!
+//!     terminal.set_loyalty(rules, ledger.clone());
+//!     terminal.attach_member(Member::new("1001", Level::Silver))?;
+//!     terminal.scan('B')?;
+//!     terminal.redeem_points(100, Redemption::Discount)?;
+//!
+//!     // $1 off, and points are earned on the $19 paid
+//!     let receipt = terminal.commit("T2")?;
+//!
+//!     assert_eq!(receipt.total(), dec!(19));
+//!     assert_eq!(receipt.points_earned(), 19);
+//!     assert_eq!(ledger.balance("1001")?, 39);
+//!
+//!     // B was brought back
+//!     ledger.reverse("1001", "T2")?;
+//!
+//!     assert_eq!(ledger.balance("1001")?, 120);
+//! #   std::fs::remove_dir_all(&dir).ok();
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::journal::{escape, unescape};
+use crate::{Receipt, Sku, TerminalError, Unit};
+
+use rust_decimal::Decimal;
+
+use std::convert::TryFrom;
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// The label of points redeemed as a discount.
+pub(crate) const LABEL: &str = "Points";
+
+/// How long to wait for another terminal to finish with a member's points.
+const LOCK_WAIT: Duration = Duration::from_secs(5);
+
+/// Numbers the files written by this process so no two writes share one.
+static WRITES: AtomicU64 = AtomicU64::new(0);
+
+/// How points are earned on a transaction and what they are worth when they are spent.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rules {
+    /// Points for every dollar paid, after discounts and not counting what was paid with points.
+    pub per_dollar: Decimal,
+    /// What one point is worth when it is redeemed.
+    pub point_value: Decimal,
+    /// Items that earn this many times the usual points.
+    pub multipliers: Vec<(Sku, Decimal)>,
+    /// Items that earn this many points for every unit bought on top of the usual points.
+    /// Weighed items and barcode priced lines count as one unit each.
+    pub bonuses: Vec<(Sku, u64)>,
+}
+
+impl Rules {
+    pub fn new(per_dollar: Decimal, point_value: Decimal) -> Self {
+        Rules {
+            per_dollar,
+            point_value,
+            multipliers: vec![],
+            bonuses: vec![],
+        }
+    }
+
+    pub fn multiplier<I: Into<Sku>>(mut self, item: I, multiplier: Decimal) -> Self {
+        self.multipliers.push((item.into(), multiplier));
+        self
+    }
+
+    pub fn bonus<I: Into<Sku>>(mut self, item: I, points: u64) -> Self {
+        self.bonuses.push((item.into(), points));
+        self
+    }
+
+    /// The points a priced receipt earns, rounded down to whole points.
+    pub(crate) fn earned(&self, receipt: &Receipt) -> Result<u64, TerminalError> {
+        let total = receipt.grand_total();
+
+        if total <= Decimal::ZERO {
+            return Ok(0);
+        }
+
+        // only what wasn't paid with points earns points
+        let paid = receipt
+            .amount_due()
+            .checked_div(total)
+            .ok_or(TerminalError::Overflow)?;
+
+        let mut points = Decimal::ZERO;
+
+        for (item, amount) in receipt.net_amounts()? {
+            let multiplier = self
+                .multipliers
+                .iter()
+                .find(|(i, _)| *i == item)
+                .map_or(Decimal::ONE, |(_, m)| *m);
+
+            points = amount
+                .max(Decimal::ZERO)
+                .checked_mul(self.per_dollar)
+                .and_then(|p| p.checked_mul(multiplier))
+                .and_then(|p| p.checked_mul(paid))
+                .and_then(|p| points.checked_add(p))
+                .ok_or(TerminalError::Overflow)?;
+        }
+
+        let mut bonus: u64 = 0;
+
+        for line in receipt.lines() {
+            let per_unit = match self.bonuses.iter().find(|(item, _)| *item == line.item) {
+                Some((_, per_unit)) => *per_unit,
+                None => continue,
+            };
+
+            let units = match line.unit {
+                Unit::Each => u64::try_from(line.quantity).map_err(|_| TerminalError::Overflow)?,
+                _ => 1,
+            };
+
+            bonus = per_unit
+                .checked_mul(units)
+                .and_then(|b| bonus.checked_add(b))
+                .ok_or(TerminalError::Overflow)?;
+        }
+
+        u64::try_from(points.floor())
+            .ok()
+            .and_then(|p| p.checked_add(bonus))
+            .ok_or(TerminalError::Overflow)
+    }
+
+    /// What `points` are worth.
+    pub(crate) fn value(&self, points: u64) -> Result<Decimal, TerminalError> {
+        Decimal::from(points)
+            .checked_mul(self.point_value)
+            .ok_or(TerminalError::Overflow)
+    }
+
+    /// The fewest points that are worth at least `amount`.
+    pub(crate) fn points_for(&self, amount: Decimal) -> Result<u64, TerminalError> {
+        if self.point_value <= Decimal::ZERO {
+            return Ok(0);
+        }
+
+        amount
+            .checked_div(self.point_value)
+            .and_then(|p| u64::try_from(p.ceil()).ok())
+            .ok_or(TerminalError::Overflow)
+    }
+}
+
+/// How redeemed points are used.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Redemption {
+    /// Taken off the total like a coupon, after every other discount.
+    Discount,
+    /// Pays for part of the total, which is left as it is.
+    Tender,
+}
+
+impl fmt::Display for Redemption {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Redemption::Discount => write!(f, "discount"),
+            Redemption::Tender => write!(f, "tender"),
+        }
+    }
+}
+
+impl Redemption {
+    pub(crate) fn parse(name: &str) -> Option<Redemption> {
+        match name {
+            "discount" => Some(Redemption::Discount),
+            "tender" => Some(Redemption::Tender),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EntryKind {
+    Sale,
+    /// Takes back the points of the sale with the same transaction.
+    Return,
+}
+
+/// What one transaction did to a member's points.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct LedgerEntry {
+    pub kind: EntryKind,
+    pub transaction: String,
+    pub earned: u64,
+    pub redeemed: u64,
+}
+
+impl LedgerEntry {
+    /// How the entry changes the member's balance, `Overflow` if that doesn't fit in an `i64`.
+    pub fn points(&self) -> Result<i64, TerminalError> {
+        let points = i64::try_from(self.earned)
+            .ok()
+            .zip(i64::try_from(self.redeemed).ok())
+            .and_then(|(earned, redeemed)| earned.checked_sub(redeemed));
+
+        match self.kind {
+            EntryKind::Sale => points,
+            EntryKind::Return => points.and_then(i64::checked_neg),
+        }
+        .ok_or(TerminalError::Overflow)
+    }
+}
+
+/// Points balances kept in a directory, one text file per member with one line per entry.
+/// Files are rewritten in full and moved into place, so an entry is either all there or not at
+/// all. A member's file is locked while it is read and rewritten, so terminals sharing the
+/// directory don't write over each other's entries.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Ledger {
+    dir: PathBuf,
+}
+
+impl Ledger {
+    /// A ledger kept in `dir`, which is created when the first entry is written.
+    pub fn new<P: AsRef<Path>>(dir: P) -> Self {
+        Ledger {
+            dir: dir.as_ref().to_path_buf(),
+        }
+    }
+
+    /// Every entry for `member`, oldest first.
+    pub fn entries(&self, member: &str) -> Result<Vec<LedgerEntry>, TerminalError> {
+        let text = match fs::read_to_string(self.path(member)) {
+            Ok(text) => text,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(vec![]),
+            Err(e) => return Err(failed(member, e)),
+        };
+
+        text.lines()
+            .enumerate()
+            .map(|(i, line)| {
+                parse_entry(line).ok_or_else(|| TerminalError::Ledger {
+                    member: member.to_string(),
+                    reason: format!("bad entry at line {}", i + 1),
+                })
+            })
+            .collect()
+    }
+
+    pub fn balance(&self, member: &str) -> Result<i64, TerminalError> {
+        balance(&self.entries(member)?)
+    }
+
+    /// Records the points of a finished sale. The member has to have the points redeemed, and
+    /// each transaction can only be recorded once.
+    pub(crate) fn commit(
+        &self,
+        member: &str,
+        transaction: &str,
+        earned: u64,
+        redeemed: u64,
+    ) -> Result<(), TerminalError> {
+        let _lock = self.lock(member)?;
+
+        let mut entries = self.entries(member)?;
+
+        if entries.iter().any(|e| e.transaction == transaction) {
+            return Err(TerminalError::TransactionInLedger(transaction.to_string()));
+        }
+
+        let balance = balance(&entries)?;
+
+        if !covers(balance, redeemed) {
+            return Err(TerminalError::NotEnoughPoints {
+                member: member.to_string(),
+                balance,
+            });
+        }
+
+        entries.push(LedgerEntry {
+            kind: EntryKind::Sale,
+            transaction: transaction.to_string(),
+            earned,
+            redeemed,
+        });
+
+        self.write(member, &entries)
+    }
+
+    /// Takes back the points a sale earned and gives back the points it redeemed, for when the
+    /// transaction is returned. The balance can go below zero if the earned points were spent.
+    pub fn reverse(&self, member: &str, transaction: &str) -> Result<(), TerminalError> {
+        let _lock = self.lock(member)?;
+
+        let mut entries = self.entries(member)?;
+
+        let unknown = || TerminalError::UnknownTransaction(transaction.to_string());
+
+        let sale = entries
+            .iter()
+            .find(|e| e.kind == EntryKind::Sale && e.transaction == transaction)
+            .ok_or_else(unknown)?;
+
+        if entries
+            .iter()
+            .any(|e| e.kind == EntryKind::Return && e.transaction == transaction)
+        {
+            return Err(TerminalError::TransactionInLedger(transaction.to_string()));
+        }
+
+        let reversal = LedgerEntry {
+            kind: EntryKind::Return,
+            ..sale.clone()
+        };
+
+        entries.push(reversal);
+
+        self.write(member, &entries)
+    }
+
+    fn write(&self, member: &str, entries: &[LedgerEntry]) -> Result<(), TerminalError> {
+        let path = self.path(member);
+        let partial = path.with_extension(format!(
+            "{}-{}.partial",
+            process::id(),
+            WRITES.fetch_add(1, Ordering::Relaxed)
+        ));
+
+        let mut text = String::new();
+
+        for e in entries {
+            let kind = match e.kind {
+                EntryKind::Sale => "sale",
+                EntryKind::Return => "return",
+            };
+
+            text.push_str(&format!(
+                "{}\t{}\t{}\t{}\n",
+                kind,
+                escape(&e.transaction),
+                e.earned,
+                e.redeemed
+            ));
+        }
+
+        let written = fs::create_dir_all(&self.dir)
+            .and_then(|_| fs::File::create(&partial))
+            .and_then(|mut file| {
+                file.write_all(text.as_bytes())?;
+                file.sync_all()
+            })
+            .and_then(|_| fs::rename(&partial, &path))
+            .and_then(|_| sync_dir(&self.dir));
+
+        if written.is_err() {
+            fs::remove_file(&partial).ok();
+        }
+
+        written.map_err(|e| failed(member, e))
+    }
+
+    /// Creates the member's lock file, waiting up to `LOCK_WAIT` for whoever holds it. The lock
+    /// is let go when the returned guard is dropped.
+    fn lock(&self, member: &str) -> Result<Lock, TerminalError> {
+        fs::create_dir_all(&self.dir).map_err(|e| failed(member, e))?;
+
+        let path = self.path(member).with_extension("lock");
+        let started = Instant::now();
+
+        loop {
+            match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(_) => return Ok(Lock(path)),
+                Err(e) if e.kind() != ErrorKind::AlreadyExists => return Err(failed(member, e)),
+                Err(_) if started.elapsed() >= LOCK_WAIT => {
+                    return Err(TerminalError::Ledger {
+                        member: member.to_string(),
+                        reason: format!(
+                            "locked by another terminal, {} can be removed if none is running",
+                            path.display()
+                        ),
+                    })
+                }
+                Err(_) => thread::sleep(Duration::from_millis(10)),
+            }
+        }
+    }
+
+    /// Member numbers are written out so that any of them makes a safe file name.
+    fn path(&self, member: &str) -> PathBuf {
+        let name: String = member
+            .bytes()
+            .map(|b| match b {
+                b'0'..=b'9' | b'A'..=b'Z' | b'a'..=b'z' | b'-' | b'_' => (b as char).to_string(),
+                _ => format!("%{:02X}", b),
+            })
+            .collect();
+
+        self.dir.join(format!("{}.points", name))
+    }
+}
+
+fn parse_entry(line: &str) -> Option<LedgerEntry> {
+    let fields: Vec<String> = line.split('\t').map(unescape).collect();
+
+    if fields.len() != 4 {
+        return None;
+    }
+
+    let kind = match fields[0].as_str() {
+        "sale" => EntryKind::Sale,
+        "return" => EntryKind::Return,
+        _ => return None,
+    };
+
+    Some(LedgerEntry {
+        kind,
+        transaction: fields[1].clone(),
+        earned: fields[2].parse().ok()?,
+        redeemed: fields[3].parse().ok()?,
+    })
+}
+
+/// Holds a member's lock file, removing it when dropped.
+struct Lock(PathBuf);
+
+impl Drop for Lock {
+    fn drop(&mut self) {
+        fs::remove_file(&self.0).ok();
+    }
+}
+
+/// Makes a rename in `dir` last through a crash, which needs the directory itself synced.
+#[cfg(unix)]
+fn sync_dir(dir: &Path) -> std::io::Result<()> {
+    fs::File::open(dir)?.sync_all()
+}
+
+#[cfg(not(unix))]
+fn sync_dir(_dir: &Path) -> std::io::Result<()> {
+    Ok(())
+}
+
+fn balance(entries: &[LedgerEntry]) -> Result<i64, TerminalError> {
+    entries.iter().try_fold(0_i64, |total, e| {
+        total
+            .checked_add(e.points()?)
+            .ok_or(TerminalError::Overflow)
+    })
+}
+
+/// Whether a balance has `points` to redeem, which it never does when they don't fit in an `i64`.
+pub(crate) fn covers(balance: i64, points: u64) -> bool {
+    i64::try_from(points).is_ok_and(|points| balance >= points)
+}
+
+fn failed(member: &str, e: std::io::Error) -> TerminalError {
+    TerminalError::Ledger {
+        member: member.to_string(),
+        reason: e.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parse_price, setup_pricing, Price};
+
+    use rust_decimal_macros::dec;
+
+    fn ledger(name: &str) -> Ledger {
+        let dir = std::env::temp_dir().join(format!("ledger-{}-{}", name, std::process::id()));
+
+        fs::remove_dir_all(&dir).ok();
+
+        Ledger::new(dir)
+    }
+
+    #[test]
+    fn it_keeps_one_file_per_member() -> Result<(), TerminalError> {
+        let ledger = ledger("files");
+
+        ledger.commit("4417 0020", "T\t1", 50, 0)?;
+        ledger.commit("4417/0021", "T2", 10, 0)?;
+
+        assert_eq!(
+            ledger.commit("4417 0020", "T\t1", 50, 0),
+            Err(TerminalError::TransactionInLedger("T\t1".to_string()))
+        );
+        assert_eq!(
+            ledger.commit("4417/0021", "T3", 0, 20),
+            Err(TerminalError::NotEnoughPoints {
+                member: "4417/0021".to_string(),
+                balance: 10
+            })
+        );
+
+        ledger.commit("4417 0020", "T3\r", 5, 40)?;
+        ledger.reverse("4417 0020", "T\t1")?;
+
+        // the points earned by T1 were spent in T3
+        assert_eq!(ledger.balance("4417 0020")?, -35);
+        assert_eq!(ledger.balance("4417/0021")?, 10);
+        assert_eq!(ledger.balance("nobody")?, 0);
+        assert!(ledger
+            .entries("4417 0020")?
+            .iter()
+            .any(|e| e.transaction == "T3\r"));
+        assert_eq!(
+            ledger.reverse("4417 0020", "T\t1"),
+            Err(TerminalError::TransactionInLedger("T\t1".to_string()))
+        );
+        assert_eq!(
+            ledger.reverse("4417 0020", "T9"),
+            Err(TerminalError::UnknownTransaction("T9".to_string()))
+        );
+
+        assert!(ledger.dir.join("4417%200020.points").exists());
+        // nothing is left behind but the members' files
+        let left: Vec<PathBuf> = fs::read_dir(&ledger.dir)
+            .and_then(|dir| dir.map(|e| e.map(|e| e.path())).collect())
+            .map_err(|e| failed("4417 0020", e))?;
+
+        assert!(left
+            .iter()
+            .all(|path| path.extension() == Some("points".as_ref())));
+
+        fs::remove_dir_all(&ledger.dir).ok();
+
+        Ok(())
+    }
+
+    #[test]
+    fn it_keeps_every_entry_when_terminals_commit_at_once() -> Result<(), TerminalError> {
+        let ledger = ledger("concurrent");
+
+        let terminals: Vec<_> = (0..8)
+            .map(|t| {
+                let ledger = ledger.clone();
+
+                thread::spawn(move || -> Result<(), TerminalError> {
+                    for n in 0..10 {
+                        ledger.commit("1001", &format!("T{}-{}", t, n), 1, 0)?;
+                    }
+
+                    Ok(())
+                })
+            })
+            .collect();
+
+        for terminal in terminals {
+            terminal.join().expect("a terminal's thread panicked")?;
+        }
+
+        assert_eq!(ledger.entries("1001")?.len(), 80);
+        assert_eq!(ledger.balance("1001")?, 80);
+
+        fs::remove_dir_all(&ledger.dir).ok();
+
+        Ok(())
+    }
+
+    #[test]
+    fn it_checks_balances_that_dont_fit_in_an_i64() -> Result<(), TerminalError> {
+        let ledger = ledger("overflow");
+
+        ledger.commit("1001", "T1", 10, 0)?;
+
+        assert_eq!(
+            ledger.commit("1001", "T2", 0, u64::MAX),
+            Err(TerminalError::NotEnoughPoints {
+                member: "1001".to_string(),
+                balance: 10
+            })
+        );
+
+        ledger.commit("1002", "T1", u64::MAX, 0)?;
+
+        assert_eq!(ledger.balance("1002"), Err(TerminalError::Overflow));
+
+        fs::remove_dir_all(&ledger.dir).ok();
+
+        Ok(())
+    }
+
+    #[test]
+    fn it_rounds_points_down_and_adds_bonuses() -> Result<(), TerminalError> {
+        let rules = Rules::new(dec!(1), dec!(0.01))
+            .multiplier('A', dec!(2))
+            .bonus('B', 25);
+
+        assert_eq!(rules.points_for(dec!(1.005))?, 101);
+        assert_eq!(rules.value(250)?, dec!(2.5));
+
+        let mut terminal = setup_pricing!('A' => [{ price: 1.49 }]; 'B' => [{ price: 0.99 }])?;
+
+        terminal.set_quantity('A', 3)?;
+        terminal.set_quantity('B', 2)?;
+
+        // 8.94 points for A and 1.98 for B, plus 50 for the two B
+        assert_eq!(rules.earned(&terminal.receipt()?)?, 60);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,81 @@
+//! Loyalty members, who can be charged their own prices.
+//!
+//! ```
+//! # #[macro_use] extern crate scanner_terminal; #[macro_use] extern crate rust_decimal_macros; fn main() -> Result<(), scanner_terminal::TerminalError> {
+//!     use scanner_terminal::member::{Level, Member};
+//!     use scanner_terminal::{Item, Price, Terminal};
+//!
+//!     use std::collections::HashMap;
+//!
+//!     let mut prices = HashMap::new();
+//!
+//!     prices.insert('A', Item::new(vec![
+//!         Price::new(0, dec!(2)),
+//!         Price::new(0, dec!(1.8)).members(Level::Silver),
+//!         Price::new(4, dec!(6)).members(Level::Gold),
+//!     ]));
+//!
+//!     let mut terminal = Terminal::new(prices)?;
+//!
+//!     terminal.set_quantity('A', 4)?;
+//!
+//!     assert_eq!(terminal.total()?, dec!(8));
+//!
+//!     // gold members get the silver price as well
+//!     terminal.attach_member(Member::new("4417 0020", Level::Gold))?;
+//!
+//!     let receipt = terminal.receipt()?;
+//!
+//!     assert_eq!(receipt.total(), dec!(6));
+//!     assert_eq!(receipt.member_savings(), dec!(2));
+//! # Ok(())
+//! # }
+//! ```
+
+use std::fmt;
+
+/// A member's level in the loyalty program. Higher levels get the prices of the levels below
+/// them too.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Level {
+    Silver,
+    Gold,
+}
+
+impl Level {
+    pub(crate) fn parse(name: &str) -> Option<Level> {
+        match name {
+            "silver" => Some(Level::Silver),
+            "gold" => Some(Level::Gold),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Level::Silver => "silver",
+            Level::Gold => "gold",
+        };
+
+        write!(f, "{}", name)
+    }
+}
+
+/// The customer a transaction is for, when they are a member.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Member {
+    /// The number on their card.
+    pub id: String,
+    pub level: Level,
+}
+
+impl Member {
+    pub fn new<S: Into<String>>(id: S, level: Level) -> Self {
+        Member {
+            id: id.into(),
+            level,
+        }
+    }
+}
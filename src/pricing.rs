@@ -0,0 +1,2187 @@
+//! Prices the counted items in a basket, working out which units go into promotions and which
+//! are left to their own price tiers so the customer pays as little as possible.
+
+use crate::catalog::lookup;
+use crate::coupon::Coupon;
+use crate::loyalty;
+use crate::promotion::{Conflict, Discount, Promotion, Reason, Rule};
+use crate::rounding::Rounding;
+use crate::{base_price, checked_sum, Cheapest};
+use crate::{
+    Adjustment, Catalog, CatalogReport, DealUse, Line, Pricing, Sku, TerminalError, TierUse, Unit,
+};
+
+use rust_decimal::Decimal;
+
+use std::collections::{BTreeMap, HashMap};
+
+/// One way of filling a promotion, as units taken from each of the items in the search.
+type Take = Vec<usize>;
+
+/// Units left of each item, and the first deal that may still be applied.
+type State = (Vec<usize>, usize);
+
+/// The deal and the option of it applied next, `None` when nothing beats the tiers.
+type Choice = Option<(usize, usize)>;
+
+/// One way of filling a promotion and what the units in it cost.
+struct Fill {
+    take: Take,
+    /// Units of each item the discount was given on, all zero for set price promotions.
+    reward: Take,
+    /// What the units in `take` cost together, after the discount.
+    price: Decimal,
+    discount: Decimal,
+}
+
+struct Deal {
+    promotion: usize,
+    fills: Vec<Fill>,
+}
+
+/// Part of what an item comes to, and the promotions that discounted it.
+struct Portion {
+    item: Sku,
+    amount: Decimal,
+    /// Indexes into the promotions.
+    touched: Vec<usize>,
+}
+
+/// A mix and match promotion priced by `pooled()`, with what each unit in it costs once prices
+/// are scaled up to whole sets of every promotion in the group.
+struct Mix {
+    promotion: usize,
+    quantity: usize,
+    rate: Decimal,
+    /// The items it can take units of.
+    reach: Vec<usize>,
+}
+
+/// How often a promotion was used in the cheapest outcome, and on which units.
+struct Used {
+    promotion: usize,
+    times: usize,
+    units: Vec<usize>,
+    reward: Vec<usize>,
+    discount: Decimal,
+}
+
+/// Groups of promotions are searched for their cheapest outcome only while the search stays
+/// below this many steps, bigger ones are priced some other way.
+const SEARCH_LIMIT: usize = 50_000;
+
+/// A single promotion, or a group of set price promotions, is priced exactly while that takes
+/// fewer steps than this.
+const EXACT_LIMIT: usize = 2_000_000;
+
+/// At most this many ways of filling a promotion are tried.
+const FILL_LIMIT: usize = 500;
+
+/// The counted items being priced, indexed the same everywhere, with what their units cost.
+struct Stock<'a> {
+    items: Vec<&'a Sku>,
+    counts: Vec<usize>,
+    /// The `min: 0` price of each item.
+    bases: Vec<Decimal>,
+    /// The cheapest tiers for every count of each item.
+    tables: Vec<Cheapest<'a>>,
+    /// How discounts are rounded.
+    rounding: &'a Rounding,
+}
+
+impl Stock<'_> {
+    /// What `n` units of item `i` come to on its own tiers.
+    fn cost(&self, i: usize, n: usize) -> Result<Decimal, TerminalError> {
+        self.tables[i].cost(n).ok_or_else(|| {
+            TerminalError::InvalidCatalog(CatalogReport::missing_base_price(self.items[i].clone()))
+        })
+    }
+
+    /// What is saved on the tiers of the items in `members` when `take` units go elsewhere.
+    fn saved(
+        &self,
+        members: &[usize],
+        remaining: &[usize],
+        take: &[usize],
+    ) -> Result<Decimal, TerminalError> {
+        members.iter().try_fold(Decimal::ZERO, |saved, i| {
+            self.cost(*i, remaining[*i])?
+                .checked_sub(self.cost(*i, remaining[*i] - take[*i])?)
+                .and_then(|s| saved.checked_add(s))
+                .ok_or(TerminalError::Overflow)
+        })
+    }
+}
+
+/// Looks for the cheapest way to fill deals from the items they share. Deals are only ever
+/// applied in index order, so the same set of deals isn't tried in every possible order.
+struct Search<'a> {
+    stock: &'a Stock<'a>,
+    /// The items the deals can take units of, the only ones searched.
+    searched: &'a [usize],
+    deals: &'a [Deal],
+    memo: HashMap<State, (Decimal, Choice)>,
+}
+
+impl<'a> Search<'a> {
+    fn best(&mut self, remaining: &[usize], from: usize) -> Result<Decimal, TerminalError> {
+        if let Some((cost, _)) = self.memo.get(&(remaining.to_vec(), from)) {
+            return Ok(*cost);
+        }
+
+        let mut best = Decimal::ZERO;
+
+        for (i, left) in self.searched.iter().zip(remaining) {
+            best = best
+                .checked_add(self.stock.cost(*i, *left)?)
+                .ok_or(TerminalError::Overflow)?;
+        }
+
+        let mut choice = None;
+
+        for d in from..self.deals.len() {
+            for (o, fill) in self.deals[d].fills.iter().enumerate() {
+                let take = || self.searched.iter().map(|i| fill.take[*i]);
+
+                if take().zip(remaining).any(|(take, left)| take > *left) {
+                    continue;
+                }
+
+                let next: Vec<usize> = remaining.iter().zip(take()).map(|(l, t)| l - t).collect();
+
+                let cost = self
+                    .best(&next, d)?
+                    .checked_add(fill.price)
+                    .ok_or(TerminalError::Overflow)?;
+
+                // ties keep the earlier choice, so results don't depend on anything but order
+                if cost < best {
+                    best = cost;
+                    choice = Some((d, o));
+                }
+            }
+        }
+
+        self.memo.insert((remaining.to_vec(), from), (best, choice));
+
+        Ok(best)
+    }
+
+    /// The deals used by the cheapest outcome, as (deal, fill) pairs.
+    fn path(&self, counts: &[usize]) -> Vec<(usize, usize)> {
+        let mut path = vec![];
+        let mut remaining = counts.to_vec();
+        let mut from = 0;
+
+        while let Some((_, Some((d, o)))) = self.memo.get(&(remaining.clone(), from)) {
+            let take = &self.deals[*d].fills[*o].take;
+
+            for (left, i) in remaining.iter_mut().zip(self.searched) {
+                *left -= take[*i];
+            }
+
+            path.push((*d, *o));
+            from = *d;
+        }
+
+        path
+    }
+}
+
+/// Lines for the counted items in `counts`, with promotions applied wherever they bring the
+/// total down, and the discounts they gave. Items with an overridden price shouldn't be in
+/// `counts`. When outcomes cost the same, the one using promotions earlier in `promotions` wins.
+///
+/// Promotions that can't take units of the same items are priced separately. A promotion that
+/// shares its items with no other is priced exactly, promotions that share items are searched
+/// for their cheapest outcome when there are few enough of them. Bigger groups of set price
+/// promotions are priced exactly too, and only bigger groups with promotions that discount
+/// particular units are filled greedily.
+pub(crate) fn counted_lines(
+    catalog: &Catalog,
+    counts: &HashMap<Sku, usize>,
+    promotions: &[&Promotion],
+    rounding: &Rounding,
+) -> Result<(Vec<Line>, Vec<Adjustment>), TerminalError> {
+    let mut items: Vec<&Sku> = counts.keys().collect();
+
+    items.sort();
+
+    let mut stock = Stock {
+        counts: items.iter().map(|item| counts[*item]).collect(),
+        items: vec![],
+        bases: vec![],
+        tables: vec![],
+        rounding,
+    };
+
+    // the cheapest tiers for every count of each item, worked out once
+    for (item, count) in items.iter().zip(&stock.counts) {
+        let entry = lookup(catalog, item)?;
+
+        stock.tables.push(Cheapest::new(&entry.prices, *count)?);
+        stock.bases.push(base_price(item, entry)?);
+    }
+
+    stock.items = items;
+
+    let reach: Vec<Vec<usize>> = promotions.iter().map(|p| reach(&p.rule, &stock)).collect();
+
+    let mut used: Vec<Used> = vec![];
+
+    // items no promotion can take units of are left to their tiers
+    for (group, members) in groups(&reach) {
+        if let [promotion] = group[..] {
+            let rule = &promotions[promotion].rule;
+
+            if exact_steps(rule, &members, &stock) <= EXACT_LIMIT {
+                used.extend(exact(promotion, rule, &members, &stock)?);
+
+                continue;
+            }
+        }
+
+        let mut deals = vec![];
+
+        for &promotion in &group {
+            let fills = fills(&promotions[promotion].rule, &stock)?;
+
+            if !fills.is_empty() {
+                deals.push(Deal { promotion, fills });
+            }
+        }
+
+        let states = members.iter().fold(1usize, |states, i| {
+            states.saturating_mul(stock.counts[*i] + 1)
+        });
+
+        let steps = deals
+            .iter()
+            .fold(states, |steps, d| steps.saturating_mul(d.fills.len()));
+
+        let complete = deals.iter().all(|d| d.fills.len() < FILL_LIMIT);
+
+        let path = if complete && steps <= SEARCH_LIMIT {
+            let start: Vec<usize> = members.iter().map(|i| stock.counts[*i]).collect();
+
+            let mut search = Search {
+                stock: &stock,
+                searched: &members,
+                deals: &deals,
+                memo: HashMap::new(),
+            };
+
+            search.best(&start, 0)?;
+            search.path(&start)
+        } else if let Some(pooled) = pooled(&group, promotions, &reach, &members, &stock)? {
+            used.extend(pooled);
+
+            continue;
+        } else {
+            greedy(&deals, &members, &stock)?
+        };
+
+        for (d, o) in path {
+            tally(&mut used, deals[d].promotion, &deals[d].fills[o])?;
+        }
+    }
+
+    let mut remaining = stock.counts.clone();
+
+    for u in &used {
+        for (left, units) in remaining.iter_mut().zip(&u.units) {
+            *left -= units;
+        }
+    }
+
+    used.sort_by_key(|u| u.promotion);
+
+    let mut deal_uses: Vec<Vec<DealUse>> = vec![vec![]; stock.items.len()];
+    let mut adjustments = vec![];
+
+    for u in used {
+        let promotion = &promotions[u.promotion];
+
+        let charged = match &promotion.rule {
+            Rule::MixAndMatch { price, .. } | Rule::Bundle { price, .. } => {
+                let amount = price
+                    .checked_mul(Decimal::from(u.times as u64))
+                    .ok_or(TerminalError::Overflow)?;
+
+                allocate(
+                    amount,
+                    &values(&stock.bases, &u.units)?,
+                    &u.units,
+                    stock.rounding,
+                )?
+            }
+            Rule::BuyGet { .. } | Rule::CheapestFree { .. } => {
+                let weights = values(&stock.bases, &u.reward)?;
+
+                let discount = -u.discount;
+
+                let shares = allocate(discount, &weights, &u.reward, stock.rounding)?;
+
+                adjustments.push(Adjustment {
+                    label: promotion.name.clone(),
+                    amount: discount,
+                    shares: stock
+                        .items
+                        .iter()
+                        .zip(&u.reward)
+                        .zip(shares)
+                        .filter(|((_, r), _)| **r > 0)
+                        .map(|((item, _), share)| ((*item).clone(), share))
+                        .collect(),
+                });
+
+                values(&stock.bases, &u.units)?
+            }
+            Rule::Spend { .. } => unreachable!("basket discounts aren't filled from units"),
+        };
+
+        for (i, (units, amount)) in u.units.iter().zip(charged).enumerate() {
+            if *units > 0 {
+                deal_uses[i].push(DealUse {
+                    promotion: promotion.name.clone(),
+                    units: *units,
+                    amount,
+                });
+            }
+        }
+    }
+
+    let mut lines = vec![];
+
+    for (i, item) in stock.items.iter().enumerate() {
+        let (tier_total, tiers) = tiers(item, &stock.tables[i], remaining[i])?;
+
+        let subtotal = checked_sum(
+            deal_uses[i]
+                .iter()
+                .map(|d| d.amount)
+                .chain(Some(tier_total)),
+        )?;
+
+        let quantity = Decimal::from(stock.counts[i] as u64);
+
+        let singles = stock.bases[i]
+            .checked_mul(quantity)
+            .ok_or(TerminalError::Overflow)?;
+
+        lines.push(Line {
+            item: (*item).clone(),
+            quantity,
+            unit: Unit::Each,
+            pricing: Pricing::Tiers(tiers),
+            deals: deal_uses[i].clone(),
+            subtotal,
+            savings: singles
+                .checked_sub(subtotal)
+                .ok_or(TerminalError::Overflow)?,
+            rounding: Decimal::ZERO,
+        });
+    }
+
+    Ok((lines, adjustments))
+}
+
+/// The items `rule` could take units of, none if it can't be filled from what is in the basket.
+fn reach(rule: &Rule, stock: &Stock) -> Vec<usize> {
+    let present = |group: &[Sku]| -> Vec<usize> {
+        (0..stock.items.len())
+            .filter(|i| stock.counts[*i] > 0 && group.contains(stock.items[*i]))
+            .collect()
+    };
+
+    match rule {
+        Rule::MixAndMatch { items, .. } | Rule::CheapestFree { items, .. } => present(items),
+        Rule::BuyGet { items, rewards, .. } => {
+            let mut reach = present(items);
+
+            reach.extend(present(rewards));
+            reach.sort_unstable();
+            reach.dedup();
+
+            reach
+        }
+        Rule::Bundle { items, .. } => {
+            let skus: Vec<Sku> = items.iter().map(|(item, _)| item.clone()).collect();
+
+            let reach = present(&skus);
+
+            if reach.len() == items.len() {
+                reach
+            } else {
+                vec![]
+            }
+        }
+        Rule::Spend { .. } => vec![],
+    }
+}
+
+/// Promotions that can take units of the same items, directly or through other promotions, in
+/// the order of the promotions, along with all the items they can take units of.
+fn groups(reach: &[Vec<usize>]) -> Vec<(Vec<usize>, Vec<usize>)> {
+    let mut groups: Vec<(Vec<usize>, Vec<usize>)> = vec![];
+
+    for (promotion, items) in reach.iter().enumerate() {
+        if items.is_empty() {
+            continue;
+        }
+
+        let (joined, apart): (Vec<_>, Vec<_>) = groups
+            .into_iter()
+            .partition(|(_, members)| members.iter().any(|i| items.contains(i)));
+
+        let mut group = (vec![promotion], items.clone());
+
+        for (promotions, members) in joined {
+            group.0.extend(promotions);
+            group.1.extend(members);
+        }
+
+        group.0.sort_unstable();
+        group.1.sort_unstable();
+        group.1.dedup();
+
+        groups = apart;
+        groups.push(group);
+    }
+
+    groups.sort_by_key(|(promotions, _)| promotions[0]);
+
+    groups
+}
+
+/// Roughly how many steps `exact()` takes to price `rule` on its own, `usize::MAX` for rules it
+/// can't price.
+fn exact_steps(rule: &Rule, members: &[usize], stock: &Stock) -> usize {
+    let total: usize = members.iter().map(|i| stock.counts[*i]).sum();
+
+    match rule {
+        Rule::MixAndMatch { .. } | Rule::CheapestFree { .. } => {
+            members.iter().fold(0usize, |steps, i| {
+                steps.saturating_add((stock.counts[*i] + 1).saturating_mul(total + 1))
+            })
+        }
+        Rule::Bundle { .. } => total.saturating_mul(members.len()),
+        Rule::BuyGet { .. } | Rule::Spend { .. } => usize::MAX,
+    }
+}
+
+/// The cheapest use of a promotion that shares its items with no other, `None` when the tiers
+/// are cheaper.
+fn exact(
+    promotion: usize,
+    rule: &Rule,
+    members: &[usize],
+    stock: &Stock,
+) -> Result<Option<Used>, TerminalError> {
+    let mut used = Used {
+        promotion,
+        times: 0,
+        units: vec![0; stock.items.len()],
+        reward: vec![0; stock.items.len()],
+        discount: Decimal::ZERO,
+    };
+
+    match rule {
+        Rule::MixAndMatch {
+            quantity, price, ..
+        } => {
+            let (times, units) = fill_group(stock, members, *quantity, *price, |_, _, _| {
+                Ok(Decimal::ZERO)
+            })?;
+
+            used.times = times;
+
+            for (i, n) in members.iter().zip(units) {
+                used.units[*i] = n;
+            }
+        }
+        Rule::CheapestFree { quantity, .. } => {
+            // with the dearest units first, every `quantity`th unit is the cheapest of its set
+            let mut members = members.to_vec();
+
+            members.sort_by(|a, b| stock.bases[*b].cmp(&stock.bases[*a]).then(b.cmp(a)));
+
+            let free = |before: usize, n: usize| (before + n) / quantity - before / quantity;
+
+            let (times, units) =
+                fill_group(stock, &members, *quantity, Decimal::ZERO, |j, before, n| {
+                    let base = stock.bases[members[j]];
+
+                    Decimal::from((n - free(before, n)) as u64)
+                        .checked_mul(base)
+                        .ok_or(TerminalError::Overflow)
+                })?;
+
+            used.times = times;
+
+            let mut before = 0;
+
+            for (i, n) in members.iter().zip(units) {
+                used.units[*i] = n;
+                used.reward[*i] = free(before, n);
+
+                before += n;
+            }
+
+            used.discount = checked_sum(values(&stock.bases, &used.reward)?)?;
+        }
+        Rule::Bundle { items, price } => {
+            let bundled: Vec<(usize, usize)> = items
+                .iter()
+                .filter_map(|(item, n)| {
+                    members
+                        .iter()
+                        .find(|i| stock.items[**i] == item)
+                        .map(|i| (*i, *n))
+                })
+                .collect();
+
+            let most = bundled
+                .iter()
+                .map(|(i, n)| stock.counts[*i] / n)
+                .min()
+                .unwrap_or(0);
+
+            let mut best: Option<(Decimal, usize)> = None;
+
+            for times in 0..=most {
+                let mut cost = price
+                    .checked_mul(Decimal::from(times as u64))
+                    .ok_or(TerminalError::Overflow)?;
+
+                for (i, n) in &bundled {
+                    cost = cost
+                        .checked_add(stock.cost(*i, stock.counts[*i] - times * n)?)
+                        .ok_or(TerminalError::Overflow)?;
+                }
+
+                if best.is_none_or(|(b, _)| cost < b) {
+                    best = Some((cost, times));
+                }
+            }
+
+            used.times = best.map_or(0, |(_, times)| times);
+
+            for (i, n) in bundled {
+                used.units[i] = used.times * n;
+            }
+        }
+        Rule::BuyGet { .. } | Rule::Spend { .. } => unreachable!("only searched for"),
+    }
+
+    Ok(Some(used).filter(|u| u.times > 0))
+}
+
+/// The cheapest number of units of each of `members` to put into a promotion filled with
+/// `quantity` units at a time, and how many times it is filled. Members are taken in the order
+/// given, `taking(j, before, n)` is what `n` units of member `j` come to in the promotion when
+/// `before` units have already gone into it, and `per_fill` is charged every time it is filled.
+/// Ties go to filling it fewer times.
+fn fill_group<F>(
+    stock: &Stock,
+    members: &[usize],
+    quantity: usize,
+    per_fill: Decimal,
+    taking: F,
+) -> Result<(usize, Vec<usize>), TerminalError>
+where
+    F: Fn(usize, usize, usize) -> Result<Decimal, TerminalError>,
+{
+    let total: usize = members.iter().map(|i| stock.counts[*i]).sum();
+
+    // best[t] is the cheapest cost of the members so far with t of their units in the promotion
+    let mut best: Vec<Option<Decimal>> = vec![None; total + 1];
+    let mut picks: Vec<Vec<usize>> = vec![];
+
+    best[0] = Some(Decimal::ZERO);
+
+    for (j, i) in members.iter().enumerate() {
+        let count = stock.counts[*i];
+
+        let mut next: Vec<Option<Decimal>> = vec![None; total + 1];
+        let mut pick = vec![0; total + 1];
+
+        for t in 0..=total {
+            for n in 0..=count.min(t) {
+                let before = match best[t - n] {
+                    Some(before) => before,
+                    None => continue,
+                };
+
+                let taken = taking(j, t - n, n)?;
+
+                let cost = before
+                    .checked_add(stock.cost(*i, count - n)?)
+                    .and_then(|c| c.checked_add(taken))
+                    .ok_or(TerminalError::Overflow)?;
+
+                if next[t].is_none_or(|current| cost < current) {
+                    next[t] = Some(cost);
+                    pick[t] = n;
+                }
+            }
+        }
+
+        best = next;
+        picks.push(pick);
+    }
+
+    let mut cheapest: Option<(Decimal, usize)> = None;
+
+    for times in 0..=total / quantity {
+        if let Some(cost) = best[times * quantity] {
+            let cost = per_fill
+                .checked_mul(Decimal::from(times as u64))
+                .and_then(|p| p.checked_add(cost))
+                .ok_or(TerminalError::Overflow)?;
+
+            if cheapest.is_none_or(|(c, _)| cost < c) {
+                cheapest = Some((cost, times));
+            }
+        }
+    }
+
+    let times = cheapest.map_or(0, |(_, times)| times);
+
+    let mut units = vec![0; members.len()];
+    let mut t = times * quantity;
+
+    for (j, pick) in picks.iter().enumerate().rev() {
+        units[j] = pick[t];
+        t -= pick[t];
+    }
+
+    Ok((times, units))
+}
+
+/// The cheapest use of a group of mix and match promotions and bundles, `None` when the group
+/// has other promotions in it or pricing it would take too many steps.
+///
+/// Every unit that goes into a mix and match costs the same, so while the items are worked
+/// through one at a time all that matters is how far each promotion is past its last whole set.
+/// Costs are scaled by the sizes of all the sets so those unit costs stay exact. Only the
+/// cheapest promotion at an item takes a whole scale's worth of its units or more, since moving
+/// that many to it costs no more and leaves every promotion as far past a whole set. Bundles are
+/// tried every number of times they could be used.
+fn pooled(
+    group: &[usize],
+    promotions: &[&Promotion],
+    reach: &[Vec<usize>],
+    members: &[usize],
+    stock: &Stock,
+) -> Result<Option<Vec<Used>>, TerminalError> {
+    let mut sets = vec![];
+    let mut bundles = vec![];
+
+    for &promotion in group {
+        match &promotions[promotion].rule {
+            Rule::MixAndMatch {
+                quantity, price, ..
+            } => sets.push((promotion, *quantity, *price)),
+            Rule::Bundle { items, price } => {
+                let bundled: Vec<(usize, usize)> = items
+                    .iter()
+                    .filter_map(|(item, n)| {
+                        members
+                            .iter()
+                            .find(|i| stock.items[**i] == item)
+                            .map(|i| (*i, *n))
+                    })
+                    .collect();
+
+                let most = bundled
+                    .iter()
+                    .map(|(i, n)| stock.counts[*i] / n)
+                    .min()
+                    .unwrap_or(0);
+
+                bundles.push((promotion, *price, bundled, most));
+            }
+            Rule::BuyGet { .. } | Rule::CheapestFree { .. } | Rule::Spend { .. } => {
+                return Ok(None)
+            }
+        }
+    }
+
+    let scale = sets.iter().try_fold(1usize, |scale, (_, quantity, _)| {
+        scale.checked_mul(quantity / gcd(scale, *quantity))
+    });
+
+    let states = sets.iter().try_fold(1usize, |states, (_, quantity, _)| {
+        states.checked_mul(*quantity)
+    });
+
+    let (scale, states) = match (scale, states) {
+        (Some(scale), Some(states)) => (scale, states),
+        _ => return Ok(None),
+    };
+
+    let steps = members.iter().fold(0usize, |steps, i| {
+        let count = stock.counts[*i];
+        let sharing = sets
+            .iter()
+            .filter(|(p, _, _)| reach[*p].contains(i))
+            .count();
+
+        let spread = (1..sharing).fold(count + 1, |spread, _| {
+            spread.saturating_mul(count.min(scale - 1) + 1)
+        });
+
+        steps.saturating_add(states.saturating_mul(spread))
+    });
+
+    let steps = bundles.iter().fold(steps, |steps, (_, _, _, most)| {
+        steps.saturating_mul(most + 1)
+    });
+
+    if steps > EXACT_LIMIT {
+        return Ok(None);
+    }
+
+    let mut mixes = vec![];
+
+    for (promotion, quantity, price) in sets {
+        mixes.push(Mix {
+            promotion,
+            quantity,
+            rate: price
+                .checked_mul(Decimal::from((scale / quantity) as u64))
+                .ok_or(TerminalError::Overflow)?,
+            reach: reach[promotion].clone(),
+        });
+    }
+
+    let mut times = vec![0; bundles.len()];
+    let mut best: Option<(Decimal, Vec<usize>, Vec<Vec<usize>>)> = None;
+
+    loop {
+        let mut counts = Some(stock.counts.clone());
+
+        for ((_, _, bundled, _), t) in bundles.iter().zip(&times) {
+            for (i, n) in bundled {
+                counts = counts.and_then(|mut counts| {
+                    counts[*i] = counts[*i].checked_sub(t * n)?;
+
+                    Some(counts)
+                });
+            }
+        }
+
+        // bundles that share items may not all fit at once
+        if let Some(counts) = counts {
+            let (mut cost, units) = mixed(&mixes, members, &counts, scale, states, stock)?;
+
+            for ((_, price, _, _), t) in bundles.iter().zip(&times) {
+                cost = price
+                    .checked_mul(Decimal::from(*t as u64))
+                    .and_then(|p| p.checked_mul(Decimal::from(scale as u64)))
+                    .and_then(|p| p.checked_add(cost))
+                    .ok_or(TerminalError::Overflow)?;
+            }
+
+            if best.as_ref().is_none_or(|(b, _, _)| cost < *b) {
+                best = Some((cost, times.clone(), units));
+            }
+        }
+
+        match (0..bundles.len()).find(|b| times[*b] < bundles[*b].3) {
+            Some(b) => {
+                times[b] += 1;
+
+                for t in &mut times[..b] {
+                    *t = 0;
+                }
+            }
+            None => break,
+        }
+    }
+
+    let (_, times, units) = best.expect("bundles are tried not being used at all");
+
+    let mut used = vec![];
+
+    for (mix, units) in mixes.iter().zip(units) {
+        let taken: usize = units.iter().sum();
+
+        if taken > 0 {
+            used.push(Used {
+                promotion: mix.promotion,
+                times: taken / mix.quantity,
+                units,
+                reward: vec![0; stock.items.len()],
+                discount: Decimal::ZERO,
+            });
+        }
+    }
+
+    for ((promotion, _, bundled, _), t) in bundles.into_iter().zip(times) {
+        if t > 0 {
+            let mut units = vec![0; stock.items.len()];
+
+            for (i, n) in bundled {
+                units[i] = t * n;
+            }
+
+            used.push(Used {
+                promotion,
+                times: t,
+                units,
+                reward: vec![0; stock.items.len()],
+                discount: Decimal::ZERO,
+            });
+        }
+    }
+
+    Ok(Some(used))
+}
+
+/// The cheapest way of putting the units in `counts` of `members` into `mixes`, as the cost
+/// scaled by `scale` and the units of each item that go into each of them. A state is how far
+/// each of `mixes` is past its last whole set, one digit for each, and there are `states` of
+/// them.
+fn mixed(
+    mixes: &[Mix],
+    members: &[usize],
+    counts: &[usize],
+    scale: usize,
+    states: usize,
+    stock: &Stock,
+) -> Result<(Decimal, Vec<Vec<usize>>), TerminalError> {
+    // the state after `n` more units go into mix `k`
+    let moved = |state: usize, k: usize, n: usize| {
+        let digit: usize = mixes[..k].iter().map(|m| m.quantity).product();
+        let quantity = mixes[k].quantity;
+        let past = state / digit % quantity;
+
+        state - past * digit + (past + n) % quantity * digit
+    };
+
+    let mut best: Vec<Option<Decimal>> = vec![None; states];
+    let mut picks: Vec<Vec<(usize, Vec<usize>)>> = vec![];
+
+    best[0] = Some(Decimal::ZERO);
+
+    for i in members {
+        let count = counts[*i];
+
+        // the cheapest first, and the earlier of those that cost the same
+        let mut taking: Vec<usize> = (0..mixes.len())
+            .filter(|k| mixes[*k].reach.contains(i))
+            .collect();
+
+        taking.sort_by_key(|k| mixes[*k].rate);
+
+        // what the rest of the units come to on their tiers when `n` go into sets
+        let mut left = vec![];
+
+        for n in 0..=count {
+            left.push(
+                stock
+                    .cost(*i, count - n)?
+                    .checked_mul(Decimal::from(scale as u64))
+                    .ok_or(TerminalError::Overflow)?,
+            );
+        }
+
+        let others = spreads(taking.len().saturating_sub(1), count.min(scale - 1), count);
+
+        let mut next: Vec<Option<Decimal>> = vec![None; states];
+        let mut pick = vec![(0, vec![]); states];
+
+        for (state, before) in best.iter().enumerate() {
+            let before = match before {
+                Some(before) => *before,
+                None => continue,
+            };
+
+            for spread in &others {
+                let mut units = vec![0; mixes.len()];
+                let mut to = state;
+                let mut cost = before;
+
+                for (k, n) in taking.iter().skip(1).zip(spread) {
+                    units[*k] = *n;
+                    to = moved(to, *k, *n);
+                    cost = mixes[*k]
+                        .rate
+                        .checked_mul(Decimal::from(*n as u64))
+                        .and_then(|c| c.checked_add(cost))
+                        .ok_or(TerminalError::Overflow)?;
+                }
+
+                let spent: usize = spread.iter().sum();
+
+                let cheapest = taking.first().copied();
+                let rest = if cheapest.is_some() { count - spent } else { 0 };
+
+                for n in 0..=rest {
+                    let (to, cost) = match cheapest {
+                        Some(k) => (
+                            moved(to, k, n),
+                            mixes[k]
+                                .rate
+                                .checked_mul(Decimal::from(n as u64))
+                                .and_then(|c| c.checked_add(cost)),
+                        ),
+                        None => (to, Some(cost)),
+                    };
+
+                    let cost = cost
+                        .and_then(|c| c.checked_add(left[spent + n]))
+                        .ok_or(TerminalError::Overflow)?;
+
+                    if next[to].is_none_or(|current| cost < current) {
+                        let mut units = units.clone();
+
+                        if let Some(k) = cheapest {
+                            units[k] = n;
+                        }
+
+                        next[to] = Some(cost);
+                        pick[to] = (state, units);
+                    }
+                }
+            }
+        }
+
+        best = next;
+        picks.push(pick);
+    }
+
+    let cost = best[0].expect("putting no units into sets is always possible");
+
+    let mut units = vec![vec![0; stock.items.len()]; mixes.len()];
+    let mut state = 0;
+
+    for (i, pick) in members.iter().zip(&picks).rev() {
+        let (before, taken) = &pick[state];
+
+        for (k, n) in taken.iter().enumerate() {
+            units[k][*i] = *n;
+        }
+
+        state = *before;
+    }
+
+    Ok((cost, units))
+}
+
+/// Every way of giving `sharing` promotions up to `most` units each, `total` at most between
+/// them, fewest units first.
+fn spreads(sharing: usize, most: usize, total: usize) -> Vec<Vec<usize>> {
+    if sharing == 0 {
+        return vec![vec![]];
+    }
+
+    (0..=most.min(total))
+        .flat_map(|n| {
+            spreads(sharing - 1, most, total - n)
+                .into_iter()
+                .map(move |mut rest| {
+                    rest.insert(0, n);
+                    rest
+                })
+        })
+        .collect()
+}
+
+fn gcd(a: usize, b: usize) -> usize {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+/// Fills deals one at a time, each time with whichever fill saves the most on what is left of
+/// `members`, until none of them save anything.
+fn greedy(
+    deals: &[Deal],
+    members: &[usize],
+    stock: &Stock,
+) -> Result<Vec<(usize, usize)>, TerminalError> {
+    let mut remaining = stock.counts.clone();
+    let mut path = vec![];
+
+    loop {
+        let mut best: Option<(Decimal, usize, usize)> = None;
+
+        for (d, deal) in deals.iter().enumerate() {
+            for (o, fill) in deal.fills.iter().enumerate() {
+                if members.iter().any(|i| fill.take[*i] > remaining[*i]) {
+                    continue;
+                }
+
+                let saving = stock
+                    .saved(members, &remaining, &fill.take)?
+                    .checked_sub(fill.price)
+                    .ok_or(TerminalError::Overflow)?;
+
+                // ties keep the earlier deal, as they do when searching
+                if saving > Decimal::ZERO && best.is_none_or(|(b, _, _)| saving > b) {
+                    best = Some((saving, d, o));
+                }
+            }
+        }
+
+        let (d, o) = match best {
+            Some((_, d, o)) => (d, o),
+            None => return Ok(path),
+        };
+
+        for (left, take) in remaining.iter_mut().zip(&deals[d].fills[o].take) {
+            *left -= take;
+        }
+
+        path.push((d, o));
+    }
+}
+
+/// Adds one more use of `fill` to what `promotion` was used for.
+fn tally(used: &mut Vec<Used>, promotion: usize, fill: &Fill) -> Result<(), TerminalError> {
+    let entry = match used.iter().position(|u| u.promotion == promotion) {
+        Some(entry) => &mut used[entry],
+        None => {
+            used.push(Used {
+                promotion,
+                times: 0,
+                units: vec![0; fill.take.len()],
+                reward: vec![0; fill.take.len()],
+                discount: Decimal::ZERO,
+            });
+
+            used.last_mut().unwrap()
+        }
+    };
+
+    entry.times += 1;
+    entry.discount = entry
+        .discount
+        .checked_add(fill.discount)
+        .ok_or(TerminalError::Overflow)?;
+
+    for (u, take) in entry.units.iter_mut().zip(&fill.take) {
+        *u += take;
+    }
+
+    for (r, reward) in entry.reward.iter_mut().zip(&fill.reward) {
+        *r += reward;
+    }
+
+    Ok(())
+}
+
+/// The ways `rule` can be filled once from the units in `stock`, at most `FILL_LIMIT` of them.
+fn fills(rule: &Rule, stock: &Stock) -> Result<Vec<Fill>, TerminalError> {
+    let (items, bases, counts) = (&stock.items[..], &stock.bases[..], &stock.counts[..]);
+
+    let positions = |group: &[Sku]| -> Vec<usize> {
+        items
+            .iter()
+            .enumerate()
+            .filter(|(_, item)| group.contains(item))
+            .map(|(i, _)| i)
+            .collect()
+    };
+
+    let takes = |group: &[Sku], quantity: usize| {
+        let mut out = vec![];
+
+        compositions(
+            &positions(group),
+            counts,
+            quantity,
+            &mut vec![0; counts.len()],
+            &mut out,
+        );
+
+        out
+    };
+
+    let discounted = |take: Take, reward: Take, discount: Decimal| {
+        let price = checked_sum(values(bases, &take)?)?
+            .checked_sub(discount)
+            .ok_or(TerminalError::Overflow)?;
+
+        Ok(Fill {
+            take,
+            reward,
+            price,
+            discount,
+        })
+    };
+
+    match rule {
+        Rule::MixAndMatch {
+            items: group,
+            quantity,
+            price,
+        } => Ok(takes(group, *quantity)
+            .into_iter()
+            .map(|take| Fill {
+                reward: vec![0; take.len()],
+                take,
+                price: *price,
+                discount: Decimal::ZERO,
+            })
+            .collect()),
+        Rule::BuyGet {
+            items: group,
+            buy,
+            rewards,
+            get,
+            percent_off,
+        } => {
+            // the smallest discount for each set of units, so overlapping items reward the
+            // cheaper units
+            let mut best: BTreeMap<Take, (Decimal, Take)> = BTreeMap::new();
+
+            let got = takes(rewards, *get);
+
+            for bought in takes(group, *buy) {
+                if best.len() >= FILL_LIMIT {
+                    break;
+                }
+
+                for reward in &got {
+                    let take: Take = bought.iter().zip(reward).map(|(b, r)| b + r).collect();
+
+                    if take.iter().zip(counts).any(|(t, c)| t > c) {
+                        continue;
+                    }
+
+                    let discount = stock.rounding.round(
+                        checked_sum(values(bases, reward)?)?
+                            .checked_mul(*percent_off)
+                            .and_then(|d| d.checked_div(Decimal::ONE_HUNDRED))
+                            .ok_or(TerminalError::Overflow)?,
+                    );
+
+                    match best.get(&take) {
+                        Some((smallest, _)) if *smallest <= discount => {}
+                        _ => {
+                            best.insert(take, (discount, reward.clone()));
+                        }
+                    }
+                }
+            }
+
+            best.into_iter()
+                .map(|(take, (discount, reward))| discounted(take, reward, discount))
+                .collect()
+        }
+        Rule::Bundle {
+            items: bundled,
+            price,
+        } => {
+            let mut take = vec![0; counts.len()];
+
+            for (item, n) in bundled {
+                match items.iter().position(|i| *i == item) {
+                    Some(i) if counts[i] >= *n => take[i] = *n,
+                    _ => return Ok(vec![]),
+                }
+            }
+
+            Ok(vec![Fill {
+                reward: vec![0; take.len()],
+                take,
+                price: *price,
+                discount: Decimal::ZERO,
+            }])
+        }
+        Rule::Spend { .. } => Ok(vec![]),
+        Rule::CheapestFree {
+            items: group,
+            quantity,
+        } => takes(group, *quantity)
+            .into_iter()
+            .map(|take| {
+                // ties go to the first item so the outcome doesn't depend on anything else
+                let free = (0..take.len())
+                    .filter(|i| take[*i] > 0)
+                    .fold(None, |cheapest: Option<usize>, i| match cheapest {
+                        Some(c) if bases[c] <= bases[i] => Some(c),
+                        _ => Some(i),
+                    })
+                    .expect("a fill takes at least one unit");
+
+                let mut reward = vec![0; take.len()];
+
+                reward[free] = 1;
+
+                discounted(take, reward, bases[free])
+            })
+            .collect(),
+    }
+}
+
+/// What `units` of each item come to at `bases`, item by item.
+fn values(bases: &[Decimal], units: &[usize]) -> Result<Vec<Decimal>, TerminalError> {
+    bases
+        .iter()
+        .zip(units)
+        .map(|(base, u)| {
+            base.checked_mul(Decimal::from(*u as u64))
+                .ok_or(TerminalError::Overflow)
+        })
+        .collect()
+}
+
+/// Keeps at most one promotion from each exclusivity group. The one kept has the highest
+/// priority of those that bring the total down, then brings it down the most, then comes first.
+/// `promotions` have to be in priority order, and `total` prices the basket with some of them.
+pub(crate) fn exclusive<F>(
+    mut promotions: Vec<&Promotion>,
+    total: F,
+) -> Result<(Vec<&Promotion>, Vec<Conflict>), TerminalError>
+where
+    F: Fn(&[&Promotion]) -> Result<Decimal, TerminalError>,
+{
+    let mut groups: Vec<String> = vec![];
+
+    for group in promotions.iter().filter_map(|p| p.group.as_ref()) {
+        if !groups.contains(group) {
+            groups.push(group.clone());
+        }
+    }
+
+    let mut conflicts = vec![];
+
+    for group in groups {
+        let in_group = |p: &Promotion| p.group.as_ref() == Some(&group);
+
+        let others: Vec<&Promotion> = promotions
+            .iter()
+            .copied()
+            .filter(|p| !in_group(p))
+            .collect();
+
+        let without = total(&others)?;
+
+        // members that bring the total down on their own, with what it comes to
+        let mut useful: Vec<(usize, Decimal)> = vec![];
+
+        for (i, member) in promotions.iter().enumerate() {
+            if !in_group(member) {
+                continue;
+            }
+
+            let with: Vec<&Promotion> = promotions
+                .iter()
+                .enumerate()
+                .filter(|(j, p)| *j == i || !in_group(p))
+                .map(|(_, p)| *p)
+                .collect();
+
+            let cost = total(&with)?;
+
+            if cost < without {
+                useful.push((i, cost));
+            }
+        }
+
+        // members come in priority order, so a later one only wins on savings at equal priority
+        let mut winner: Option<(usize, Decimal)> = None;
+
+        for (i, cost) in useful.iter().copied() {
+            match winner {
+                Some((w, _)) if promotions[w].priority > promotions[i].priority => {}
+                Some((_, best)) if best <= cost => {}
+                _ => winner = Some((i, cost)),
+            }
+        }
+
+        if let Some((w, winning_cost)) = winner {
+            for (i, cost) in useful.iter().filter(|(i, _)| *i != w) {
+                let group = group.clone();
+
+                let reason = if promotions[*i].priority < promotions[w].priority {
+                    Reason::Priority { group }
+                } else if *cost > winning_cost {
+                    Reason::Savings { group }
+                } else {
+                    Reason::Order { group }
+                };
+
+                conflicts.push(Conflict {
+                    promotion: promotions[*i].name.clone(),
+                    winner: promotions[w].name.clone(),
+                    reason,
+                });
+            }
+        }
+
+        let winner = winner.map(|(w, _)| promotions[w]);
+
+        promotions.retain(|p| !in_group(p) || Some(*p) == winner);
+    }
+
+    Ok((promotions, conflicts))
+}
+
+/// Discounts from `coupons` in the order they were redeemed, followed by discounts on the whole
+/// basket from `Rule::Spend` promotions in the order of `promotions`. They come after `lines` have
+/// been priced and the item discounts in `adjustments` given, and each one is taken off what is
+/// left of every item after the ones before it. Units discounted by a promotion that isn't
+/// stackable aren't discounted again, and a basket discount that isn't stackable only applies to
+/// units no promotion has discounted; each time that leaves a promotion out is a conflict.
+pub(crate) fn basket_adjustments(
+    lines: &[Line],
+    adjustments: &[Adjustment],
+    coupons: &[Coupon],
+    promotions: &[&Promotion],
+    points: Option<Decimal>,
+    rounding: &Rounding,
+) -> Result<(Vec<Adjustment>, Vec<Conflict>), TerminalError> {
+    let index = |name: &str| promotions.iter().position(|p| p.name == name);
+
+    // what each item comes to so far, split by the promotions that went into it
+    let mut portions: Vec<Portion> = vec![];
+
+    for line in lines {
+        let mut rest = line.subtotal;
+
+        for deal in &line.deals {
+            rest = rest
+                .checked_sub(deal.amount)
+                .ok_or(TerminalError::Overflow)?;
+
+            portions.push(Portion {
+                item: line.item.clone(),
+                amount: deal.amount,
+                touched: index(&deal.promotion).into_iter().collect(),
+            });
+        }
+
+        portions.push(Portion {
+            item: line.item.clone(),
+            amount: rest,
+            touched: vec![],
+        });
+    }
+
+    for adjustment in adjustments {
+        let promotion = index(&adjustment.label);
+
+        for (item, share) in &adjustment.shares {
+            let portion = portions
+                .iter_mut()
+                .find(|p| p.item == *item && p.touched.first() == promotion.as_ref())
+                .ok_or_else(|| TerminalError::NotInBasket(item.clone()))?;
+
+            portion.amount = portion
+                .amount
+                .checked_add(*share)
+                .ok_or(TerminalError::Overflow)?;
+        }
+    }
+
+    let mut basket = vec![];
+    let mut conflicts: Vec<Conflict> = vec![];
+
+    for coupon in coupons {
+        let eligible: Vec<usize> = (0..portions.len())
+            .filter(|i| coupon.items.contains(&portions[*i].item))
+            .collect();
+
+        basket.extend(take_off(
+            &mut portions,
+            &eligible,
+            &coupon.discount,
+            &coupon.label,
+            None,
+            rounding,
+        )?);
+    }
+
+    for (p, promotion) in promotions.iter().enumerate() {
+        let (threshold, discount, excluding) = match &promotion.rule {
+            Rule::Spend {
+                threshold,
+                discount,
+                excluding,
+            } => (threshold, discount, excluding),
+            _ => continue,
+        };
+
+        let counted: Vec<usize> = (0..portions.len())
+            .filter(|i| !excluding.contains(&portions[*i].item))
+            .collect();
+
+        if checked_sum(counted.iter().map(|i| portions[*i].amount))? < *threshold {
+            continue;
+        }
+
+        let mut eligible = vec![];
+
+        for i in counted {
+            let blocker = portions[i]
+                .touched
+                .iter()
+                .find(|q| !promotion.stackable || !promotions[**q].stackable);
+
+            match blocker {
+                None => eligible.push(i),
+                Some(q) if !portions[i].amount.is_zero() => {
+                    let conflict = Conflict {
+                        promotion: promotion.name.clone(),
+                        winner: promotions[*q].name.clone(),
+                        reason: Reason::NotStackable,
+                    };
+
+                    if !conflicts.contains(&conflict) {
+                        conflicts.push(conflict);
+                    }
+                }
+                Some(_) => {}
+            }
+        }
+
+        if checked_sum(eligible.iter().map(|i| portions[*i].amount))? < *threshold {
+            continue;
+        }
+
+        basket.extend(take_off(
+            &mut portions,
+            &eligible,
+            discount,
+            &promotion.name,
+            Some(p),
+            rounding,
+        )?);
+    }
+
+    // points redeemed as a discount come off whatever is left
+    if let Some(value) = points {
+        let everything: Vec<usize> = (0..portions.len()).collect();
+
+        basket.extend(take_off(
+            &mut portions,
+            &everything,
+            &Discount::Amount(value),
+            loyalty::LABEL,
+            None,
+            rounding,
+        )?);
+    }
+
+    Ok((basket, conflicts))
+}
+
+/// Takes `discount` off the `eligible` portions, split by what each of them comes to, and marks
+/// them as touched by `promotion`. `None` if there is nothing to take it off.
+fn take_off(
+    portions: &mut [Portion],
+    eligible: &[usize],
+    discount: &Discount,
+    label: &str,
+    promotion: Option<usize>,
+    rounding: &Rounding,
+) -> Result<Option<Adjustment>, TerminalError> {
+    let total = checked_sum(eligible.iter().map(|i| portions[*i].amount))?;
+
+    if total <= Decimal::ZERO {
+        return Ok(None);
+    }
+
+    let amount = match discount {
+        Discount::Amount(amount) => (*amount).min(total),
+        Discount::Percent(percent) => rounding.round(
+            total
+                .checked_mul(*percent)
+                .and_then(|d| d.checked_div(Decimal::ONE_HUNDRED))
+                .ok_or(TerminalError::Overflow)?,
+        ),
+    };
+
+    if amount.is_zero() {
+        return Ok(None);
+    }
+
+    let weights: Vec<Decimal> = eligible
+        .iter()
+        .map(|i| portions[*i].amount.max(Decimal::ZERO))
+        .collect();
+
+    let split = allocate(-amount, &weights, &vec![1; eligible.len()], rounding)?;
+
+    let mut shares: Vec<(Sku, Decimal)> = vec![];
+
+    for (i, share) in eligible.iter().zip(split) {
+        if share.is_zero() {
+            continue;
+        }
+
+        let portion = &mut portions[*i];
+
+        portion.amount = portion
+            .amount
+            .checked_add(share)
+            .ok_or(TerminalError::Overflow)?;
+        portion.touched.extend(promotion);
+
+        match shares.iter_mut().find(|(item, _)| *item == portion.item) {
+            Some((_, total)) => *total += share,
+            None => shares.push((portion.item.clone(), share)),
+        }
+    }
+
+    Ok(Some(Adjustment {
+        label: label.to_string(),
+        amount: -amount,
+        shares,
+    }))
+}
+
+/// The cheapest tiers for `count` units of an item and what they come to.
+fn tiers(
+    item: &Sku,
+    table: &Cheapest,
+    count: usize,
+) -> Result<(Decimal, Vec<TierUse>), TerminalError> {
+    let (total, used) = table.tiers(count).ok_or_else(|| {
+        TerminalError::InvalidCatalog(CatalogReport::missing_base_price(item.clone()))
+    })?;
+
+    let tiers = table
+        .prices
+        .iter()
+        .zip(used)
+        .filter(|(_, times)| *times > 0)
+        .map(|(price, times)| {
+            Ok(TierUse {
+                price: price.clone(),
+                times,
+                subtotal: price
+                    .price
+                    .checked_mul(Decimal::from(times as u64))
+                    .ok_or(TerminalError::Overflow)?,
+            })
+        })
+        .collect::<Result<Vec<_>, TerminalError>>()?;
+
+    Ok((total, tiers))
+}
+
+/// The ways of taking `quantity` units from the `members` positions without going over what
+/// `counts` has of each, stopping at `FILL_LIMIT` of them.
+fn compositions(
+    members: &[usize],
+    counts: &[usize],
+    quantity: usize,
+    current: &mut Vec<usize>,
+    out: &mut Vec<Take>,
+) {
+    let (first, rest) = match members.split_first() {
+        Some(split) => split,
+        None => {
+            if quantity == 0 && out.len() < FILL_LIMIT {
+                out.push(current.clone());
+            }
+
+            return;
+        }
+    };
+
+    for take in 0..=quantity.min(counts[*first]) {
+        if out.len() >= FILL_LIMIT {
+            break;
+        }
+
+        current[*first] = take;
+
+        compositions(rest, counts, quantity - take, current, out);
+    }
+
+    current[*first] = 0;
+}
+
+/// Splits `amount` between items in proportion to `weights`, each share rounded to the minor unit. Any
+/// rounding left over goes to the item with the largest weight so the shares always add back up
+/// to `amount`. When every weight is zero the split goes by `units` instead.
+pub(crate) fn allocate(
+    amount: Decimal,
+    weights: &[Decimal],
+    units: &[usize],
+    rounding: &Rounding,
+) -> Result<Vec<Decimal>, TerminalError> {
+    let weights: Vec<Decimal> = if weights.iter().all(|w| w.is_zero()) {
+        units.iter().map(|u| Decimal::from(*u as u64)).collect()
+    } else {
+        weights.to_vec()
+    };
+
+    let total = checked_sum(weights.iter().copied())?;
+
+    if total.is_zero() {
+        return Ok(vec![Decimal::ZERO; weights.len()]);
+    }
+
+    let mut shares = weights
+        .iter()
+        .map(|w| {
+            amount
+                .checked_mul(*w)
+                .and_then(|a| a.checked_div(total))
+                .map(|a| rounding.round(a))
+                .ok_or(TerminalError::Overflow)
+        })
+        .collect::<Result<Vec<_>, TerminalError>>()?;
+
+    let largest = weights
+        .iter()
+        .enumerate()
+        .fold(0, |best, (i, w)| if *w > weights[best] { i } else { best });
+
+    let left_over = amount
+        .checked_sub(checked_sum(shares.iter().copied())?)
+        .ok_or(TerminalError::Overflow)?;
+
+    shares[largest] += left_over;
+
+    Ok(shares)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rounding::Strategy;
+    use crate::Price;
+
+    use rust_decimal_macros::dec;
+
+    use std::time::{Duration, Instant};
+
+    /// Tries every number of units of each member going into the group. Any selection whose
+    /// size is a multiple of the group size can be split into that many deals.
+    fn brute_force(
+        catalog: &Catalog,
+        counts: &[(Sku, usize)],
+        members: &[Sku],
+        quantity: usize,
+        price: Decimal,
+    ) -> Decimal {
+        group(catalog, counts, members, quantity, price, 0).unwrap()
+    }
+
+    /// The cheapest cost of the remaining items when `taken` units are already in the group,
+    /// `None` if the group can't be filled exactly.
+    fn group(
+        catalog: &Catalog,
+        counts: &[(Sku, usize)],
+        members: &[Sku],
+        quantity: usize,
+        price: Decimal,
+        taken: usize,
+    ) -> Option<Decimal> {
+        match counts.split_first() {
+            None if taken.is_multiple_of(quantity) => {
+                Some(price * Decimal::from((taken / quantity) as u64))
+            }
+            None => None,
+            Some(((item, count), rest)) => (0..=*count)
+                .filter(|x| *x == 0 || members.contains(item))
+                .filter_map(|x| {
+                    let left = Cheapest::new(&catalog[item].prices, *count)
+                        .unwrap()
+                        .cost(count - x)
+                        .unwrap();
+
+                    group(catalog, rest, members, quantity, price, taken + x)
+                        .map(|deals| left + deals)
+                })
+                .min(),
+        }
+    }
+
+    fn catalog() -> Catalog {
+        let mut catalog = Catalog::new();
+
+        let tiers = |base: Decimal, bulk: Option<(usize, Decimal)>| {
+            let mut prices = vec![Price::new(0, base)];
+
+            if let Some((min, price)) = bulk {
+                prices.push(Price::new(min, price));
+            }
+
+            prices.into()
+        };
+
+        catalog.insert('A'.into(), tiers(dec!(4), Some((3, dec!(10)))));
+        catalog.insert('B'.into(), tiers(dec!(3.5), None));
+        catalog.insert('C'.into(), tiers(dec!(5), Some((2, dec!(8.5)))));
+        catalog.insert('D'.into(), tiers(dec!(1.25), None));
+
+        catalog
+    }
+
+    #[test]
+    fn it_matches_brute_force() {
+        let catalog = catalog();
+
+        let groups = [
+            (vec!['A', 'B', 'C'], 3, dec!(10)),
+            (vec!['A', 'C'], 2, dec!(8)),
+            (vec!['B', 'C', 'D'], 4, dec!(9.99)),
+        ];
+
+        for (members, quantity, price) in groups.iter() {
+            let promotion = Promotion::mix_and_match("group", members.clone(), *quantity, *price);
+
+            for a in 0..5 {
+                for b in 0..4 {
+                    for c in 0..5 {
+                        for d in 0..3 {
+                            let counts: Vec<(Sku, usize)> = vec![
+                                ('A'.into(), a),
+                                ('B'.into(), b),
+                                ('C'.into(), c),
+                                ('D'.into(), d),
+                            ];
+
+                            let basket: HashMap<Sku, usize> =
+                                counts.iter().filter(|&(_, n)| *n > 0).cloned().collect();
+
+                            let (lines, _) =
+                                counted_lines(&catalog, &basket, &[&promotion], &Rounding::new())
+                                    .unwrap();
+
+                            let total: Decimal = lines.iter().map(|l| l.subtotal).sum();
+
+                            assert_eq!(
+                                total,
+                                brute_force(
+                                    &catalog,
+                                    &counts,
+                                    &members.iter().map(|m| Sku::from(*m)).collect::<Vec<_>>(),
+                                    *quantity,
+                                    *price
+                                ),
+                                "{:?} with {:?}",
+                                counts,
+                                members
+                            );
+
+                            // deal shares add back up to what the deals cost
+                            let dealt: Decimal =
+                                lines.iter().flat_map(|l| &l.deals).map(|d| d.amount).sum();
+
+                            let units: usize =
+                                lines.iter().flat_map(|l| &l.deals).map(|d| d.units).sum();
+
+                            assert_eq!(units % quantity, 0);
+                            assert_eq!(dealt, price * Decimal::from((units / quantity) as u64));
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// What `counts` come to with `promotions` on offer.
+    fn total(catalog: &Catalog, promotions: &[&Promotion], counts: &[(char, usize)]) -> Decimal {
+        let counts = counts.iter().map(|(k, n)| (Sku::from(*k), *n)).collect();
+
+        let (lines, adjustments) =
+            counted_lines(catalog, &counts, promotions, &Rounding::new()).unwrap();
+
+        lines.iter().map(|l| l.subtotal).sum::<Decimal>()
+            + adjustments.iter().map(|a| a.amount).sum::<Decimal>()
+    }
+
+    #[test]
+    fn it_prices_a_promotion_on_its_own_the_same_as_searching() {
+        let catalog = catalog();
+
+        let promotions = [
+            Promotion::cheapest_free("3 for 2", vec!['A', 'B', 'C'], 3),
+            Promotion::bundle("Meal", vec![('A', 1), ('C', 2), ('D', 1)], dec!(12)),
+            Promotion::mix_and_match("2 for 8", vec!['A', 'C', 'D'], 2, dec!(8)),
+        ];
+
+        for promotion in promotions.iter() {
+            for a in 0..4 {
+                for b in 0..3 {
+                    for c in 0..4 {
+                        for d in 0..3 {
+                            let counts = [('A', a), ('B', b), ('C', c), ('D', d)];
+
+                            // the same promotion twice shares its items, so it is searched
+                            assert_eq!(
+                                total(&catalog, &[promotion], &counts),
+                                total(&catalog, &[promotion, promotion], &counts),
+                                "{:?} with {}",
+                                counts,
+                                promotion.name
+                            );
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Tries every number of times each of `sets` could be used on `units` units that cost
+    /// `unit` each, any of which can go into any set.
+    fn uses(units: usize, unit: Decimal, sets: &[(usize, Decimal)]) -> Decimal {
+        match sets.split_first() {
+            None => unit * Decimal::from(units as u64),
+            Some(((quantity, price), rest)) => (0..=units / quantity)
+                .map(|times| {
+                    *price * Decimal::from(times as u64)
+                        + uses(units - times * quantity, unit, rest)
+                })
+                .min()
+                .unwrap(),
+        }
+    }
+
+    /// Tries every number of units of each item going into each of `sets` that can take it, the
+    /// cheapest outcome where every set is filled exactly, with `taken` units already in each.
+    fn shared(
+        catalog: &Catalog,
+        counts: &[(char, usize)],
+        sets: &[(Vec<char>, usize, Decimal)],
+        taken: &[usize],
+    ) -> Option<Decimal> {
+        let ((item, count), rest) = match counts.split_first() {
+            Some(first) => first,
+            None => {
+                return sets
+                    .iter()
+                    .zip(taken)
+                    .map(|((_, quantity, price), n)| {
+                        Some(*price * Decimal::from((n / quantity) as u64))
+                            .filter(|_| n % quantity == 0)
+                    })
+                    .sum()
+            }
+        };
+
+        let mut splits = vec![vec![]];
+
+        for (items, _, _) in sets {
+            let most = if items.contains(item) { *count } else { 0 };
+
+            splits = splits
+                .into_iter()
+                .flat_map(|split: Vec<usize>| {
+                    (0..=most).map(move |n| {
+                        let mut split = split.clone();
+                        split.push(n);
+                        split
+                    })
+                })
+                .filter(|split| split.iter().sum::<usize>() <= *count)
+                .collect();
+        }
+
+        splits
+            .into_iter()
+            .filter_map(|split| {
+                let left = Cheapest::new(&catalog[&Sku::from(*item)].prices, *count)
+                    .unwrap()
+                    .cost(count - split.iter().sum::<usize>())
+                    .unwrap();
+
+                let taken: Vec<usize> = taken.iter().zip(&split).map(|(t, n)| t + n).collect();
+
+                shared(catalog, rest, sets, &taken).map(|sets| left + sets)
+            })
+            .min()
+    }
+
+    #[test]
+    fn it_prices_sets_that_share_items_like_brute_force() {
+        let catalog = catalog();
+
+        let two = (vec!['A', 'C', 'D'], 2, dec!(8));
+        let three = (vec!['A', 'B', 'C'], 3, dec!(10));
+
+        let promotions = [
+            Promotion::mix_and_match("2 for 8", two.0.clone(), two.1, two.2),
+            Promotion::mix_and_match("3 for 10", three.0.clone(), three.1, three.2),
+            Promotion::bundle("Meal", vec![('A', 1), ('C', 2), ('D', 1)], dec!(12)),
+        ];
+
+        let promotions: Vec<&Promotion> = promotions.iter().collect();
+
+        // each is too big to search
+        let baskets: Vec<[(char, usize); 4]> = vec![
+            [('A', 6), ('B', 5), ('C', 6), ('D', 4)],
+            [('A', 7), ('B', 4), ('C', 5), ('D', 5)],
+        ];
+
+        for counts in baskets {
+            let sets = [two.clone(), three.clone()];
+
+            let meal = [('A', 1), ('B', 0), ('C', 2), ('D', 1)];
+
+            let cheapest = (0..=counts[0].1)
+                .filter_map(|meals| {
+                    let mut left = counts;
+
+                    for ((_, n), (_, in_meal)) in left.iter_mut().zip(&meal) {
+                        *n = (*n).checked_sub(meals * in_meal)?;
+                    }
+
+                    shared(&catalog, &left, &sets, &[0, 0])
+                        .map(|sets| dec!(12) * Decimal::from(meals as u64) + sets)
+                })
+                .min()
+                .unwrap();
+
+            assert_eq!(
+                total(&catalog, &promotions, &counts),
+                cheapest,
+                "{:?}",
+                counts
+            );
+        }
+    }
+
+    #[test]
+    fn it_prices_large_baskets_without_trying_every_outcome() {
+        let mut catalog = Catalog::new();
+
+        for (item, price) in "ABCDEF".chars().zip(2..) {
+            catalog.insert(
+                item.into(),
+                vec![Price::new(0, Decimal::from(price))].into(),
+            );
+        }
+
+        let everything: Vec<char> = "ABCDEF".chars().collect();
+        let any_three = Promotion::mix_and_match("any 3", everything.clone(), 3, dec!(10));
+
+        let started = Instant::now();
+
+        // every unit over 3.33 goes into a set, with one B to make up the last one
+        let counts: Vec<(char, usize)> = everything.iter().map(|item| (*item, 8)).collect();
+
+        assert_eq!(total(&catalog, &[&any_three], &counts), dec!(147));
+
+        // the dearest units first, with every third one free
+        assert_eq!(
+            total(
+                &catalog,
+                &[&Promotion::cheapest_free("3 for 2", vec!['A', 'B', 'C'], 3)],
+                &[('A', 300), ('B', 300), ('C', 300)]
+            ),
+            dec!(1800)
+        );
+
+        let counts: Vec<(char, usize)> = everything.iter().map(|item| (*item, 200)).collect();
+
+        assert_eq!(total(&catalog, &[&any_three], &counts), dec!(3667));
+
+        // too many outcomes to search, but still the cheapest mix of the two sets
+        let mut ones = Catalog::new();
+
+        for item in "ABC".chars() {
+            ones.insert(item.into(), vec![Price::new(0, dec!(1))].into());
+        }
+
+        let two = Promotion::mix_and_match("2 for 1.50", vec!['A', 'B', 'C'], 2, dec!(1.50));
+        let three = Promotion::mix_and_match("3 for 2.10", vec!['A', 'B', 'C'], 3, dec!(2.10));
+
+        let baskets = vec![
+            (vec![('A', 9), ('B', 9), ('C', 10)], dec!(19.80)),
+            (vec![('A', 10), ('B', 10), ('C', 11)], dec!(21.90)),
+            (vec![('A', 65), ('B', 65)], dec!(91.20)),
+        ];
+
+        for (counts, expected) in baskets {
+            let units = counts.iter().map(|(_, n)| n).sum();
+            let cheapest = uses(units, dec!(1), &[(2, dec!(1.50)), (3, dec!(2.10))]);
+
+            assert_eq!(cheapest, expected);
+            assert_eq!(
+                total(&ones, &[&two, &three], &counts),
+                cheapest,
+                "{:?}",
+                counts
+            );
+        }
+
+        assert_eq!(total(&catalog, &[], &[('A', 20_000)]), dec!(40_000));
+
+        assert!(started.elapsed() < Duration::from_secs(10));
+    }
+
+    #[test]
+    fn it_splits_amounts_that_add_back_up() {
+        let shares = allocate(
+            dec!(10),
+            &[dec!(4), dec!(4), dec!(4)],
+            &[1, 1, 1],
+            &Rounding::new(),
+        )
+        .unwrap();
+
+        assert_eq!(shares, vec![dec!(3.34), dec!(3.33), dec!(3.33)]);
+
+        let shares = allocate(dec!(5), &[dec!(0), dec!(0)], &[3, 1], &Rounding::new()).unwrap();
+
+        assert_eq!(shares, vec![dec!(3.75), dec!(1.25)]);
+    }
+
+    /// The total of `basket` with just `promotion` on offer, and the adjustments it gave.
+    fn priced(promotion: Promotion, basket: &[(char, usize)]) -> (Decimal, Vec<Adjustment>) {
+        let counts = basket.iter().map(|(k, n)| (Sku::from(*k), *n)).collect();
+
+        let (lines, adjustments) =
+            counted_lines(&catalog(), &counts, &[&promotion], &Rounding::new()).unwrap();
+
+        let total = lines.iter().map(|l| l.subtotal).sum::<Decimal>()
+            + adjustments.iter().map(|a| a.amount).sum::<Decimal>();
+
+        (total, adjustments)
+    }
+
+    #[test]
+    fn it_gives_away_the_unit_that_saves_the_most() {
+        let (total, adjustments) = priced(
+            Promotion::cheapest_free("3 for 2", vec!['A', 'B', 'C'], 3),
+            &[('A', 1), ('B', 1), ('C', 2)],
+        );
+
+        // A, C and C with A free beats A, B and C with B free
+        assert_eq!(total, dec!(13.5));
+        assert_eq!(adjustments[0].amount, dec!(-4));
+        assert_eq!(adjustments[0].shares, vec![('A'.into(), dec!(-4))]);
+    }
+
+    #[test]
+    fn it_picks_between_tiers_and_discounts() {
+        let bogo = || Promotion::buy_get("BOGO", vec!['C'], 1, vec!['C'], 1, dec!(100));
+
+        // one free C beats the 2 for 8.50 tier
+        assert_eq!(priced(bogo(), &[('C', 3)]).0, dec!(10));
+
+        let (total, adjustments) = priced(bogo(), &[('C', 1)]);
+
+        assert_eq!(total, dec!(5));
+        assert!(adjustments.is_empty());
+    }
+
+    #[test]
+    fn it_rewards_the_cheaper_units_when_groups_overlap() {
+        let (total, adjustments) = priced(
+            Promotion::buy_get("BOGO", vec!['A', 'B'], 1, vec!['A', 'B'], 1, dec!(100)),
+            &[('A', 1), ('B', 1)],
+        );
+
+        assert_eq!(total, dec!(4));
+        assert_eq!(adjustments[0].shares, vec![('B'.into(), dec!(-3.5))]);
+    }
+
+    #[test]
+    fn it_rounds_percentage_discounts_to_the_minor_unit() {
+        let half_off = Promotion::buy_get("D half off", vec!['A'], 1, vec!['D'], 1, dec!(50));
+
+        let (total, adjustments) = priced(half_off.clone(), &[('A', 1), ('D', 1)]);
+
+        assert_eq!(adjustments[0].amount, dec!(-0.63));
+        assert_eq!(total, dec!(4.62));
+
+        // the terminal's rounding is used, here banker's rounding
+        let even = Rounding::new().strategy(Strategy::HalfEven);
+        let counts = [('A', 1), ('D', 1)]
+            .iter()
+            .map(|(k, n)| (Sku::from(*k), *n))
+            .collect();
+
+        let (_, adjustments) = counted_lines(&catalog(), &counts, &[&half_off], &even).unwrap();
+
+        assert_eq!(adjustments[0].amount, dec!(-0.62));
+
+        let tenth_off = Promotion::spend(
+            "10% off",
+            dec!(1),
+            Discount::Percent(dec!(10)),
+            Vec::<char>::new(),
+        );
+        let counts = vec![(Sku::from('D'), 1)].into_iter().collect();
+
+        let (lines, adjustments) =
+            counted_lines(&catalog(), &counts, &[&tenth_off], &even).unwrap();
+        let (basket, _) =
+            basket_adjustments(&lines, &adjustments, &[], &[&tenth_off], None, &even).unwrap();
+
+        assert_eq!(basket[0].amount, dec!(-0.12));
+    }
+
+    #[test]
+    fn it_forms_bundles_only_where_they_are_cheaper() {
+        let meal = || Promotion::bundle("Meal", vec![('A', 1), ('B', 1), ('D', 1)], dec!(8));
+
+        // 8 beats 4 + 3.50 + 1.25
+        assert_eq!(priced(meal(), &[('A', 1), ('B', 1), ('D', 1)]).0, dec!(8));
+
+        // the 3 for 10 tier on A beats a bundle and two A
+        assert_eq!(
+            priced(meal(), &[('A', 3), ('B', 1), ('D', 1)]).0,
+            dec!(14.75)
+        );
+
+        // a bundle next to the 3 for 10 tier, with the second B left over
+        assert_eq!(
+            priced(meal(), &[('A', 4), ('B', 2), ('D', 1)]).0,
+            dec!(21.5)
+        );
+
+        // two bundles
+        assert_eq!(priced(meal(), &[('A', 2), ('B', 2), ('D', 2)]).0, dec!(16));
+
+        // no D, no bundle
+        assert_eq!(priced(meal(), &[('A', 1), ('B', 1)]).0, dec!(7.5));
+    }
+
+    #[test]
+    fn it_measures_thresholds_after_item_discounts() {
+        let catalog = catalog();
+
+        let promotions = [
+            Promotion::buy_get("BOGO", vec!['C'], 1, vec!['C'], 1, dec!(100)),
+            Promotion::spend("$1 off $10", dec!(10), Discount::Amount(dec!(1)), vec!['D']),
+        ];
+
+        let promotions: Vec<&Promotion> = promotions.iter().collect();
+
+        let basket = |counts: &[(char, usize)]| {
+            let counts = counts.iter().map(|(k, n)| (Sku::from(*k), *n)).collect();
+
+            let (lines, adjustments) =
+                counted_lines(&catalog, &counts, &promotions, &Rounding::new()).unwrap();
+
+            basket_adjustments(
+                &lines,
+                &adjustments,
+                &[],
+                &promotions,
+                None,
+                &Rounding::new(),
+            )
+            .unwrap()
+            .0
+        };
+
+        // $13.50 on the shelf, $8.50 after the free C, and D doesn't count
+        assert!(basket(&[('B', 1), ('C', 2), ('D', 4)]).is_empty());
+
+        let adjustments = basket(&[('B', 2), ('C', 2), ('D', 4)]);
+
+        assert_eq!(adjustments[0].amount, dec!(-1));
+        assert_eq!(
+            adjustments[0].shares,
+            vec![('B'.into(), dec!(-0.58)), ('C'.into(), dec!(-0.42))]
+        );
+    }
+}
@@ -0,0 +1,436 @@
+use crate::schedule::Schedule;
+use crate::{Catalog, Sku, TerminalError, Unit};
+
+use rust_decimal::Decimal;
+
+use std::fmt;
+
+/// What a promotion does.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Rule {
+    /// Any `quantity` units from `items`, in any mix, for `price`.
+    MixAndMatch {
+        items: Vec<Sku>,
+        quantity: usize,
+        price: Decimal,
+    },
+    /// For every `buy` units from `items`, `get` units from `rewards` are `percent_off` their
+    /// `min: 0` price. The rewards can be the same items as the ones bought, in which case the
+    /// cheaper units are the ones rewarded.
+    BuyGet {
+        items: Vec<Sku>,
+        buy: usize,
+        rewards: Vec<Sku>,
+        get: usize,
+        percent_off: Decimal,
+    },
+    /// For every `quantity` units from `items`, the cheapest of them is free.
+    CheapestFree { items: Vec<Sku>, quantity: usize },
+    /// Exactly the given number of units of each item, for `price`.
+    Bundle {
+        items: Vec<(Sku, usize)>,
+        price: Decimal,
+    },
+    /// `discount` off the whole basket once at least `threshold` is spent on items other than
+    /// `excluding`. These are applied after every item has been priced and coupons taken off, by
+    /// priority and then in the order they were added, and each one only sees what is left after
+    /// the ones before it.
+    Spend {
+        threshold: Decimal,
+        discount: Discount,
+        excluding: Vec<Sku>,
+    },
+}
+
+/// How much a basket discount takes off.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Discount {
+    /// A fixed amount, never more than what was spent.
+    Amount(Decimal),
+    /// A percentage of what was spent, rounded to the terminal's minor unit.
+    Percent(Decimal),
+}
+
+/// A deal that goes beyond the price tiers of a single item. The name is what the customer sees
+/// on the receipt.
+///
+/// Set price promotions like `mix_and_match()` charge their price on the lines of the items that
+/// went into them. Discounts like `buy_get()` and `cheapest_free()` charge those items their
+/// `min: 0` price and take the discount off in a separate adjustment on the receipt.
+///
+/// ```
+/// # #[macro_use] extern crate scanner_terminal; #[macro_use] extern crate rust_decimal_macros; fn main() -> Result<(), scanner_terminal::TerminalError> {
+///     use scanner_terminal::{Promotion, Terminal, Price};
+///
+///     let mut terminal = setup_pricing!('A' => [{ price: 4 }]; 'B' => [{ price: 3.5 }]; 'C' => [{ price: 5 }])?;
+///
+///     terminal.add_promotion(Promotion::mix_and_match("Any 3 for $10", vec!['A', 'B', 'C'], 3, dec!(10)))?;
+///
+///     terminal.scan('A')?;
+///     terminal.scan('B')?;
+///     terminal.scan('B')?;
+///     terminal.scan('C')?;
+///
+///     // A, B and C go into the deal, the cheaper B is left over
+///     assert_eq!(terminal.total()?, dec!(13.5));
+/// # Ok(())
+/// # }
+/// ```
+///
+/// ```
+/// # #[macro_use] extern crate scanner_terminal; #[macro_use] extern crate rust_decimal_macros; fn main() -> Result<(), scanner_terminal::TerminalError> {
+///     use scanner_terminal::{Promotion, Terminal, Price};
+///
+///     let mut terminal = setup_pricing!('A' => [{ price: 4 }]; 'B' => [{ price: 3 }])?;
+///
+///     terminal.add_promotion(Promotion::buy_get("Buy 2 A get a B half off", vec!['A'], 2, vec!['B'], 1, dec!(50)))?;
+///
+///     terminal.scan('A')?;
+///     terminal.scan('A')?;
+///     terminal.scan('B')?;
+///
+///     let receipt = terminal.receipt()?;
+///
+///     assert_eq!(receipt.adjustments()[0].label, "Buy 2 A get a B half off");
+///     assert_eq!(receipt.adjustments()[0].amount, dec!(-1.5));
+///     assert_eq!(receipt.total(), dec!(9.5));
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Promotion {
+    /// Has to be unique among the terminal's promotions.
+    pub name: String,
+    pub rule: Rule,
+    /// Higher goes first, see `Terminal::add_promotion()`.
+    pub priority: i32,
+    /// Whether units discounted by this promotion can be discounted by others as well.
+    pub stackable: bool,
+    /// Only one promotion from an exclusivity group is used in a transaction.
+    pub group: Option<String>,
+    /// When the promotion runs, `None` for always.
+    pub schedule: Option<Schedule>,
+}
+
+/// Why a promotion was left out of a transaction, or of part of it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Reason {
+    /// Both are in `group` and the winner has the higher priority.
+    Priority { group: String },
+    /// Both are in `group` with the same priority, and the winner saves the customer more.
+    Savings { group: String },
+    /// Both are in `group` with the same priority and savings, and the winner was added first.
+    Order { group: String },
+    /// One of them isn't stackable and the winner got to the units first.
+    NotStackable,
+}
+
+/// A promotion that couldn't be used because of another one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Conflict {
+    pub promotion: String,
+    pub winner: String,
+    pub reason: Reason,
+}
+
+impl fmt::Display for Conflict {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} lost to {}: ", self.promotion, self.winner)?;
+
+        match &self.reason {
+            Reason::Priority { group } => write!(f, "higher priority in {}", group),
+            Reason::Savings { group } => write!(f, "saves more in {}", group),
+            Reason::Order { group } => write!(f, "added first in {}", group),
+            Reason::NotStackable => write!(f, "they can't be stacked"),
+        }
+    }
+}
+
+impl Promotion {
+    /// A stackable promotion with priority 0, no exclusivity group and no schedule.
+    pub fn new<S: Into<String>>(name: S, rule: Rule) -> Self {
+        Promotion {
+            name: name.into(),
+            rule,
+            priority: 0,
+            stackable: true,
+            group: None,
+            schedule: None,
+        }
+    }
+
+    pub fn priority(mut self, priority: i32) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    pub fn stackable(mut self, stackable: bool) -> Self {
+        self.stackable = stackable;
+        self
+    }
+
+    pub fn exclusive<S: Into<String>>(mut self, group: S) -> Self {
+        self.group = Some(group.into());
+        self
+    }
+
+    /// Only runs the promotion while `schedule` is in effect, at the time the basket is priced.
+    pub fn during(mut self, schedule: Schedule) -> Self {
+        self.schedule = Some(schedule);
+        self
+    }
+
+    pub fn mix_and_match<S, I, K>(name: S, items: I, quantity: usize, price: Decimal) -> Self
+    where
+        S: Into<String>,
+        I: IntoIterator<Item = K>,
+        K: Into<Sku>,
+    {
+        Promotion::new(
+            name,
+            Rule::MixAndMatch {
+                items: items.into_iter().map(Into::into).collect(),
+                quantity,
+                price,
+            },
+        )
+    }
+
+    /// Buy one get one free is `buy_get(name, vec![item], 1, vec![item], 1, dec!(100))`.
+    pub fn buy_get<S, I, K, R, L>(
+        name: S,
+        items: I,
+        buy: usize,
+        rewards: R,
+        get: usize,
+        percent_off: Decimal,
+    ) -> Self
+    where
+        S: Into<String>,
+        I: IntoIterator<Item = K>,
+        K: Into<Sku>,
+        R: IntoIterator<Item = L>,
+        L: Into<Sku>,
+    {
+        Promotion::new(
+            name,
+            Rule::BuyGet {
+                items: items.into_iter().map(Into::into).collect(),
+                buy,
+                rewards: rewards.into_iter().map(Into::into).collect(),
+                get,
+                percent_off,
+            },
+        )
+    }
+
+    pub fn cheapest_free<S, I, K>(name: S, items: I, quantity: usize) -> Self
+    where
+        S: Into<String>,
+        I: IntoIterator<Item = K>,
+        K: Into<Sku>,
+    {
+        Promotion::new(
+            name,
+            Rule::CheapestFree {
+                items: items.into_iter().map(Into::into).collect(),
+                quantity,
+            },
+        )
+    }
+
+    /// A meal deal of one A, one B and one D for $12 is
+    /// `bundle(name, vec![('A', 1), ('B', 1), ('D', 1)], dec!(12))`. As many bundles are formed as
+    /// bring the total down, the units left over are priced by their own tiers.
+    ///
+    /// ```
+    /// # #[macro_use] extern crate scanner_terminal; #[macro_use] extern crate rust_decimal_macros; fn main() -> Result<(), scanner_terminal::TerminalError> {
+    ///     use scanner_terminal::{Promotion, Terminal, Price};
+    ///
+    ///     let mut terminal = setup_pricing!('A' => [{ price: 6 }, { min: 2, price: 10 }]; 'B' => [{ price: 4 }]; 'D' => [{ price: 3 }])?;
+    ///
+    ///     terminal.add_promotion(Promotion::bundle("Meal deal", vec![('A', 1), ('B', 1), ('D', 1)], dec!(12)))?;
+    ///
+    ///     for item in &['A', 'A', 'A', 'B', 'D'] {
+    ///         terminal.scan(*item)?;
+    ///     }
+    ///
+    ///     // one meal deal and the 2 for $10 tier
+    ///     assert_eq!(terminal.total()?, dec!(22));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn bundle<S, I, K>(name: S, items: I, price: Decimal) -> Self
+    where
+        S: Into<String>,
+        I: IntoIterator<Item = (K, usize)>,
+        K: Into<Sku>,
+    {
+        Promotion::new(
+            name,
+            Rule::Bundle {
+                items: items.into_iter().map(|(k, n)| (k.into(), n)).collect(),
+                price,
+            },
+        )
+    }
+
+    /// "Spend $50, save $5" leaving out tobacco is
+    /// `spend(name, dec!(50), Discount::Amount(dec!(5)), vec!["TOBACCO"])`.
+    ///
+    /// ```
+    /// # #[macro_use] extern crate scanner_terminal; #[macro_use] extern crate rust_decimal_macros; fn main() -> Result<(), scanner_terminal::TerminalError> {
+    ///     use scanner_terminal::{Discount, Promotion, Terminal, Price};
+    ///
+    ///     let mut terminal = setup_pricing!('A' => [{ price: 30 }]; 'T' => [{ price: 12 }])?;
+    ///
+    ///     terminal.add_promotion(Promotion::spend("$5 off $50", dec!(50), Discount::Amount(dec!(5)), vec!['T']))?;
+    ///     terminal.add_promotion(Promotion::spend("10% off $20", dec!(20), Discount::Percent(dec!(10)), Vec::<char>::new()))?;
+    ///
+    ///     terminal.scan('A')?;
+    ///     terminal.scan('T')?;
+    ///     terminal.scan('T')?;
+    ///
+    ///     // $54 spent, but only $30 of it counts toward the first discount
+    ///     let receipt = terminal.receipt()?;
+    ///
+    ///     assert_eq!(receipt.adjustments().len(), 1);
+    ///     assert_eq!(receipt.adjustments()[0].amount, dec!(-5.4));
+    ///
+    ///     terminal.scan('A')?;
+    ///
+    ///     // $60 on A gets $5 off, then 10% off the $79 left
+    ///     assert_eq!(terminal.total()?, dec!(71.1));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn spend<S, I, K>(name: S, threshold: Decimal, discount: Discount, excluding: I) -> Self
+    where
+        S: Into<String>,
+        I: IntoIterator<Item = K>,
+        K: Into<Sku>,
+    {
+        Promotion::new(
+            name,
+            Rule::Spend {
+                threshold,
+                discount,
+                excluding: excluding.into_iter().map(Into::into).collect(),
+            },
+        )
+    }
+
+    /// Checks the promotion only names counted items from `catalog` and can actually be used.
+    pub(crate) fn validate(&self, catalog: &Catalog) -> Result<(), TerminalError> {
+        let invalid = |reason: String| TerminalError::InvalidPromotion {
+            name: self.name.clone(),
+            reason,
+        };
+
+        let counted = |items: &[Sku]| {
+            for item in items {
+                match catalog.get(item) {
+                    None => return Err(invalid(format!("unknown item {}", item))),
+                    Some(entry) if entry.unit != Unit::Each => {
+                        return Err(invalid(format!("item {} is sold by weight", item)))
+                    }
+                    Some(_) => {}
+                }
+            }
+
+            Ok(())
+        };
+
+        match &self.rule {
+            Rule::MixAndMatch {
+                items,
+                quantity,
+                price,
+            } => {
+                if items.is_empty() || *quantity == 0 {
+                    return Err(invalid("needs at least one item and unit".to_string()));
+                }
+
+                if price.is_sign_negative() && !price.is_zero() {
+                    return Err(invalid(format!("negative price {}", price)));
+                }
+
+                counted(items)?;
+            }
+            Rule::BuyGet {
+                items,
+                buy,
+                rewards,
+                get,
+                percent_off,
+            } => {
+                if items.is_empty() || rewards.is_empty() || *buy == 0 || *get == 0 {
+                    return Err(invalid(
+                        "needs at least one item and unit to buy and to get".to_string(),
+                    ));
+                }
+
+                if *percent_off <= Decimal::ZERO || *percent_off > Decimal::ONE_HUNDRED {
+                    return Err(invalid(format!("{}% off is out of range", percent_off)));
+                }
+
+                counted(items)?;
+                counted(rewards)?;
+            }
+            Rule::CheapestFree { items, quantity } => {
+                if items.is_empty() || *quantity < 2 {
+                    return Err(invalid("needs at least one item and two units".to_string()));
+                }
+
+                counted(items)?;
+            }
+            Rule::Bundle { items, price } => {
+                if items.is_empty() || items.iter().any(|(_, n)| *n == 0) {
+                    return Err(invalid("needs at least one unit of every item".to_string()));
+                }
+
+                if price.is_sign_negative() && !price.is_zero() {
+                    return Err(invalid(format!("negative price {}", price)));
+                }
+
+                let skus: Vec<Sku> = items.iter().map(|(item, _)| item.clone()).collect();
+
+                if let Some(item) = skus
+                    .iter()
+                    .find(|item| skus.iter().filter(|s| s == item).count() > 1)
+                {
+                    return Err(invalid(format!("item {} is listed twice", item)));
+                }
+
+                counted(&skus)?;
+            }
+            Rule::Spend {
+                threshold,
+                discount,
+                excluding,
+            } => {
+                if threshold.is_sign_negative() && !threshold.is_zero() {
+                    return Err(invalid(format!("negative threshold {}", threshold)));
+                }
+
+                match discount {
+                    Discount::Amount(amount) if *amount <= Decimal::ZERO => {
+                        return Err(invalid(format!("{} off is out of range", amount)))
+                    }
+                    Discount::Percent(percent)
+                        if *percent <= Decimal::ZERO || *percent > Decimal::ONE_HUNDRED =>
+                    {
+                        return Err(invalid(format!("{}% off is out of range", percent)))
+                    }
+                    _ => {}
+                }
+
+                if let Some(item) = excluding.iter().find(|item| !catalog.contains_key(item)) {
+                    return Err(invalid(format!("unknown item {}", item)));
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
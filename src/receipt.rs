@@ -0,0 +1,222 @@
+use crate::member::Member;
+use crate::rounding::{Rounding, Tender};
+use crate::tax::{Exemption, TaxLine};
+use crate::{Conflict, Price, Sku, TerminalError, Unit};
+
+use rust_decimal::Decimal;
+
+/// How many times a price tier was used for a line, and what that came to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TierUse {
+    pub price: Price,
+    pub times: usize,
+    pub subtotal: Decimal,
+}
+
+/// Units of a line that went into a promotion, and the part of the promotion's price they were
+/// charged.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DealUse {
+    pub promotion: String,
+    pub units: usize,
+    pub amount: Decimal,
+}
+
+/// Where the price of a line came from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Pricing {
+    /// A counted item, priced with the cheapest combination of its tiers. Units that went into a
+    /// promotion are in the line's `deals` instead.
+    Tiers(Vec<TierUse>),
+    /// A weighed item, charged `unit_price` per unit of weight.
+    Weight { unit_price: Decimal },
+    /// The price was overridden at the terminal, every unit is charged `unit_price`.
+    Override { unit_price: Decimal },
+    /// The price or weight was printed inside the barcode.
+    Barcode,
+}
+
+/// One item on a receipt.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Line {
+    pub item: Sku,
+    /// Units for counted items, weight in `unit` for weighed ones.
+    pub quantity: Decimal,
+    pub unit: Unit,
+    pub pricing: Pricing,
+    pub deals: Vec<DealUse>,
+    pub subtotal: Decimal,
+    /// What the tiers saved compared with buying every unit at the `min: 0` price.
+    pub savings: Decimal,
+    /// What rounding the line to the minor unit added to `subtotal`, zero unless the terminal
+    /// rounds per line.
+    pub rounding: Decimal,
+}
+
+/// An amount added to or taken off the total after the items were priced, like the discount
+/// from a promotion.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Adjustment {
+    /// What the customer sees on the receipt.
+    pub label: String,
+    /// Negative for discounts.
+    pub amount: Decimal,
+    /// How `amount` splits across the items it was given on, adding up to `amount`.
+    pub shares: Vec<(Sku, Decimal)>,
+}
+
+/// The line by line breakdown of a terminal's total, see `Terminal::receipt()`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Receipt {
+    pub(crate) lines: Vec<Line>,
+    pub(crate) adjustments: Vec<Adjustment>,
+    pub(crate) conflicts: Vec<Conflict>,
+    pub(crate) member: Option<Member>,
+    pub(crate) member_savings: Decimal,
+    pub(crate) points_tendered: Decimal,
+    pub(crate) points_redeemed: u64,
+    pub(crate) points_earned: u64,
+    pub(crate) taxes: Vec<TaxLine>,
+    pub(crate) exemption: Option<Exemption>,
+    pub(crate) rounding: Decimal,
+    pub(crate) tender: Option<Tender>,
+    pub(crate) cash_rounding: Decimal,
+    /// How the terminal rounds, so amounts are printed to its minor unit.
+    pub(crate) policy: Rounding,
+    // worked out with checked arithmetic when the receipt is priced
+    pub(crate) subtotal: Decimal,
+    pub(crate) savings: Decimal,
+    pub(crate) total: Decimal,
+    pub(crate) grand_total: Decimal,
+    pub(crate) amount_due: Decimal,
+}
+
+impl Receipt {
+    /// Counted and weighed items ordered by item, followed by barcode priced lines in the order
+    /// they were scanned.
+    pub fn lines(&self) -> &[Line] {
+        &self.lines
+    }
+
+    /// Discounts on items in the order of their promotions (by priority, then the order they
+    /// were added), followed by coupons and discounts on the whole basket in the order they were
+    /// applied.
+    pub fn adjustments(&self) -> &[Adjustment] {
+        &self.adjustments
+    }
+
+    /// Promotions that were left out, or left out of some units, because of other promotions.
+    pub fn conflicts(&self) -> &[Conflict] {
+        &self.conflicts
+    }
+
+    /// The member the transaction was priced for.
+    pub fn member(&self) -> Option<&Member> {
+        self.member.as_ref()
+    }
+
+    /// How much less the total is than it would have been without the member's prices, zero
+    /// when there is no member.
+    pub fn member_savings(&self) -> Decimal {
+        self.member_savings
+    }
+
+    /// What the member paid with points redeemed as tender.
+    pub fn points_tendered(&self) -> Decimal {
+        self.points_tendered
+    }
+
+    /// What is left to pay once points tendered are taken off the grand total, rounded for cash
+    /// when paid in cash.
+    pub fn amount_due(&self) -> Decimal {
+        self.amount_due
+    }
+
+    /// How the customer said they were paying.
+    pub fn tender(&self) -> Option<Tender> {
+        self.tender
+    }
+
+    /// What rounding a cash payment to the cash increment added to what is due.
+    pub fn cash_rounding(&self) -> Decimal {
+        self.cash_rounding
+    }
+
+    /// The points actually used, as a discount or as tender. Points redeemed for more than the
+    /// total comes to are left with the member.
+    pub fn points_redeemed(&self) -> u64 {
+        self.points_redeemed
+    }
+
+    /// The points the member earns with this transaction, zero when there is no member or no
+    /// loyalty program.
+    pub fn points_earned(&self) -> u64 {
+        self.points_earned
+    }
+
+    /// What the lines come to before any adjustments.
+    pub fn subtotal(&self) -> Decimal {
+        self.subtotal
+    }
+
+    /// What rounding the total to the minor unit added to it.
+    pub fn rounding(&self) -> Decimal {
+        self.rounding
+    }
+
+    /// What the lines come to after adjustments and rounding, before any tax charged on top.
+    pub fn total(&self) -> Decimal {
+        self.total
+    }
+
+    /// The tax charged by each jurisdiction in the terminal's tax table, in the table's order.
+    pub fn taxes(&self) -> &[TaxLine] {
+        &self.taxes
+    }
+
+    /// Why some or all of the transaction wasn't taxed.
+    pub fn exemption(&self) -> Option<&Exemption> {
+        self.exemption.as_ref()
+    }
+
+    /// All of the tax, whether it was charged on top or included in the prices.
+    pub fn tax(&self) -> Decimal {
+        self.taxes.iter().map(|t| t.amount).sum()
+    }
+
+    /// The total with tax. Tax included in the prices is already in the total, unless the
+    /// transaction was exempt from it.
+    pub fn grand_total(&self) -> Decimal {
+        self.grand_total
+    }
+
+    /// What each item comes to once its share of every adjustment is taken off, in the order
+    /// the items first appear.
+    pub(crate) fn net_amounts(&self) -> Result<Vec<(Sku, Decimal)>, TerminalError> {
+        let mut amounts: Vec<(Sku, Decimal)> = vec![];
+
+        let shares = self.adjustments.iter().flat_map(|a| &a.shares);
+
+        for (item, amount) in self
+            .lines
+            .iter()
+            .map(|l| (&l.item, l.subtotal))
+            .chain(shares.map(|(item, share)| (item, *share)))
+        {
+            match amounts.iter_mut().find(|(i, _)| i == item) {
+                Some((_, total)) => {
+                    *total = total.checked_add(amount).ok_or(TerminalError::Overflow)?
+                }
+                None => amounts.push((item.clone(), amount)),
+            }
+        }
+
+        Ok(amounts)
+    }
+
+    /// What the tiers saved on every line, plus the discounts. Tiers are measured against the
+    /// `min: 0` price the customer was charged, so this doesn't include `member_savings()`.
+    pub fn savings(&self) -> Decimal {
+        self.savings
+    }
+}
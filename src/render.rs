@@ -0,0 +1,417 @@
+//! Turns a `Receipt` into something that can be printed, either fixed width text or an ESC/POS
+//! byte stream for thermal receipt printers.
+//!
+//! ```
+//! # #[macro_use] extern crate scanner_terminal; #[macro_use] extern crate rust_decimal_macros; fn main() -> Result<(), scanner_terminal::TerminalError> {
+//!     use scanner_terminal::render::{Renderer, Width};
+//!     use scanner_terminal::{Terminal, Price};
+//!
+//!     let mut terminal = setup_pricing!('A' => [{ price: 2 }, { min: 4, price: 7 }])?;
+//!
+//!     for _ in 0..5 {
+//!         terminal.scan('A')?;
+//!     }
+//!
+//!     let text = Renderer::new(Width::Narrow).store("CORNER SHOP").render_text(&terminal.receipt()?);
+//!
+//!     assert!(text.lines().all(|l| l.chars().count() <= 32));
+//!     assert!(text.contains("TOTAL                       9.00"));
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::rounding::Rounding;
+use crate::{Line, Pricing, Receipt};
+
+use rust_decimal::Decimal;
+
+const ESC: u8 = 0x1b;
+const GS: u8 = 0x1d;
+
+/// Characters per line of the printer's paper.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Width {
+    /// 32 columns, usual for 58mm paper.
+    Narrow,
+    /// 42 columns, 80mm paper with the smaller font.
+    Regular,
+    /// 48 columns, 80mm paper with the larger font.
+    Wide,
+}
+
+impl Width {
+    pub fn columns(self) -> usize {
+        match self {
+            Width::Narrow => 32,
+            Width::Regular => 42,
+            Width::Wide => 48,
+        }
+    }
+}
+
+/// A line of receipt text, kept apart from how it is printed so text and ESC/POS output always
+/// have the same content.
+enum Row {
+    Title(String),
+    Text(String),
+    Strong(String),
+    Rule,
+    Blank,
+}
+
+/// Renders receipts with a store name and transaction ID at the top.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Renderer {
+    width: Width,
+    store: Option<String>,
+    transaction_id: Option<String>,
+    barcode: bool,
+}
+
+impl Renderer {
+    pub fn new(width: Width) -> Self {
+        Renderer {
+            width,
+            store: None,
+            transaction_id: None,
+            barcode: false,
+        }
+    }
+
+    /// Printed centered and bold at the top of the receipt.
+    pub fn store<S: Into<String>>(mut self, store: S) -> Self {
+        self.store = Some(store.into());
+        self
+    }
+
+    pub fn transaction_id<S: Into<String>>(mut self, id: S) -> Self {
+        self.transaction_id = Some(id.into());
+        self
+    }
+
+    /// Prints the transaction ID as a CODE128 barcode at the bottom of ESC/POS output. Has no
+    /// effect without a transaction ID or on plain text.
+    pub fn barcode(mut self, barcode: bool) -> Self {
+        self.barcode = barcode;
+        self
+    }
+
+    /// Fixed width text, one receipt line per line of text.
+    pub fn render_text(&self, receipt: &Receipt) -> String {
+        let columns = self.width.columns();
+
+        self.rows(receipt)
+            .into_iter()
+            .map(|row| match row {
+                Row::Title(text) => center(&text, columns),
+                Row::Text(text) | Row::Strong(text) => text,
+                Row::Rule => "-".repeat(columns),
+                Row::Blank => String::new(),
+            })
+            .map(|line| line + "\n")
+            .collect()
+    }
+
+    /// ESC/POS commands for a thermal printer, finishing with a paper cut.
+    pub fn render_escpos(&self, receipt: &Receipt) -> Vec<u8> {
+        let columns = self.width.columns();
+
+        // initialize the printer
+        let mut out = vec![ESC, b'@'];
+
+        for row in self.rows(receipt) {
+            match row {
+                Row::Title(text) => {
+                    out.extend_from_slice(&[ESC, b'a', 1, ESC, b'E', 1]);
+                    out.extend_from_slice(text.as_bytes());
+                    out.extend_from_slice(&[ESC, b'E', 0, ESC, b'a', 0]);
+                }
+                Row::Strong(text) => {
+                    out.extend_from_slice(&[ESC, b'E', 1]);
+                    out.extend_from_slice(text.as_bytes());
+                    out.extend_from_slice(&[ESC, b'E', 0]);
+                }
+                Row::Text(text) => out.extend_from_slice(text.as_bytes()),
+                Row::Rule => out.extend_from_slice("-".repeat(columns).as_bytes()),
+                Row::Blank => {}
+            }
+
+            out.push(b'\n');
+        }
+
+        if let Some(id) = self.transaction_id.as_ref().filter(|_| self.barcode) {
+            let data = format!("{{B{}", id);
+
+            // centered, 80 dots high, readable text below, then CODE128
+            out.extend_from_slice(&[ESC, b'a', 1, GS, b'h', 80, GS, b'H', 2]);
+            out.extend_from_slice(&[GS, b'k', 73, data.len().min(255) as u8]);
+            out.extend(data.bytes().take(255));
+            out.extend_from_slice(&[b'\n', ESC, b'a', 0]);
+        }
+
+        // feed past the cutter and cut
+        out.extend_from_slice(&[GS, b'V', 66, 0]);
+
+        out
+    }
+
+    fn rows(&self, receipt: &Receipt) -> Vec<Row> {
+        let columns = self.width.columns();
+        let policy = &receipt.policy;
+
+        let mut rows = vec![];
+
+        if let Some(store) = &self.store {
+            rows.push(Row::Title(store.clone()));
+        }
+
+        if let Some(id) = &self.transaction_id {
+            rows.push(Row::Text(fit("Transaction", id, columns)));
+        }
+
+        if let Some(member) = receipt.member() {
+            rows.push(Row::Text(fit(
+                &format!("Member ({})", member.level),
+                &member.id,
+                columns,
+            )));
+        }
+
+        rows.push(Row::Rule);
+
+        for line in receipt.lines() {
+            rows.push(Row::Text(fit(
+                line.item.as_str(),
+                &money(line.subtotal, policy),
+                columns,
+            )));
+
+            for detail in details(line, policy) {
+                rows.push(Row::Text(fit(&format!("  {}", detail), "", columns)));
+            }
+
+            for deal in &line.deals {
+                rows.push(Row::Text(fit(
+                    &format!("  {} in {}", deal.units, deal.promotion),
+                    &money(deal.amount, policy),
+                    columns,
+                )));
+            }
+
+            if !line.savings.is_zero() {
+                rows.push(Row::Text(fit(
+                    "  You saved",
+                    &money(line.savings, policy),
+                    columns,
+                )));
+            }
+        }
+
+        for adjustment in receipt.adjustments() {
+            rows.push(Row::Text(fit(
+                &adjustment.label,
+                &money(adjustment.amount, policy),
+                columns,
+            )));
+        }
+
+        if !receipt.rounding().is_zero() {
+            rows.push(Row::Text(fit(
+                "Rounding",
+                &money(receipt.rounding(), policy),
+                columns,
+            )));
+        }
+
+        rows.push(Row::Rule);
+
+        if let Some(exemption) = receipt.exemption() {
+            rows.push(Row::Text(fit(
+                &format!("Tax exempt, {}", exemption.reason),
+                &exemption.certificate,
+                columns,
+            )));
+        }
+
+        if receipt.taxes().iter().any(|t| !t.included) {
+            rows.push(Row::Text(fit(
+                "SUBTOTAL",
+                &money(receipt.total(), policy),
+                columns,
+            )));
+
+            for tax in receipt.taxes().iter().filter(|t| !t.included) {
+                rows.push(Row::Text(fit(
+                    &format!("{} tax", tax.jurisdiction),
+                    &money(tax.amount, policy),
+                    columns,
+                )));
+
+                if !tax.exempt.is_zero() {
+                    rows.push(Row::Text(fit(
+                        "  Exempt sales",
+                        &money(tax.exempt, policy),
+                        columns,
+                    )));
+                }
+            }
+        }
+
+        // tax included in the prices comes back off them when the transaction is exempt
+        for tax in receipt
+            .taxes()
+            .iter()
+            .filter(|t| t.included && !t.waived.is_zero())
+        {
+            rows.push(Row::Text(fit(
+                &format!(
+                    "{} {}% exempt on {}",
+                    tax.jurisdiction,
+                    tax.rate.unwrap_or_default().normalize(),
+                    money(tax.exempt, policy)
+                ),
+                &money(-tax.waived, policy),
+                columns,
+            )));
+        }
+
+        if !receipt.savings().is_zero() {
+            rows.push(Row::Text(fit(
+                "SAVINGS",
+                &money(receipt.savings(), policy),
+                columns,
+            )));
+        }
+
+        if !receipt.member_savings().is_zero() {
+            rows.push(Row::Text(fit(
+                "MEMBER SAVINGS",
+                &money(receipt.member_savings(), policy),
+                columns,
+            )));
+        }
+
+        rows.push(Row::Strong(fit(
+            "TOTAL",
+            &money(receipt.grand_total(), policy),
+            columns,
+        )));
+
+        for tax in receipt
+            .taxes()
+            .iter()
+            .filter(|t| t.included && !t.taxable.is_zero())
+        {
+            rows.push(Row::Text(fit(
+                &format!(
+                    "  incl. {} {}% on {}",
+                    tax.jurisdiction,
+                    tax.rate.unwrap_or_default().normalize(),
+                    money(tax.net(), policy)
+                ),
+                &money(tax.amount, policy),
+                columns,
+            )));
+        }
+
+        if !receipt.points_tendered().is_zero() {
+            rows.push(Row::Text(fit(
+                "Paid with points",
+                &money(-receipt.points_tendered(), policy),
+                columns,
+            )));
+        }
+
+        if !receipt.cash_rounding().is_zero() {
+            rows.push(Row::Text(fit(
+                "Cash rounding",
+                &money(receipt.cash_rounding(), policy),
+                columns,
+            )));
+        }
+
+        if !receipt.points_tendered().is_zero() || !receipt.cash_rounding().is_zero() {
+            rows.push(Row::Strong(fit(
+                "DUE",
+                &money(receipt.amount_due(), policy),
+                columns,
+            )));
+        }
+
+        if receipt.points_earned() > 0 {
+            rows.push(Row::Text(fit(
+                "Points earned",
+                &receipt.points_earned().to_string(),
+                columns,
+            )));
+        }
+
+        rows.push(Row::Blank);
+
+        rows
+    }
+}
+
+/// The lines under an item explaining how its subtotal was reached.
+fn details(line: &Line, policy: &Rounding) -> Vec<String> {
+    match &line.pricing {
+        Pricing::Tiers(tiers) => tiers
+            .iter()
+            .map(|tier| {
+                if tier.price.min == 0 {
+                    format!("{} @ {}", tier.times, money(tier.price.price, policy))
+                } else {
+                    format!(
+                        "{} x {} for {}",
+                        tier.times,
+                        tier.price.min,
+                        money(tier.price.price, policy)
+                    )
+                }
+            })
+            .collect(),
+        Pricing::Weight { unit_price } => vec![format!(
+            "{} {} @ {}/{}",
+            line.quantity,
+            line.unit,
+            money(*unit_price, policy),
+            line.unit
+        )],
+        Pricing::Override { unit_price } => {
+            vec![format!(
+                "{} @ {} override",
+                line.quantity,
+                money(*unit_price, policy)
+            )]
+        }
+        Pricing::Barcode => vec![],
+    }
+}
+
+/// `amount` rounded to the minor unit and padded to it, so 2 prints as 2.00 in cents.
+fn money(amount: Decimal, policy: &Rounding) -> String {
+    format!("{:.*}", policy.minor_unit as usize, policy.round(amount))
+}
+
+fn center(text: &str, columns: usize) -> String {
+    let text: String = text.chars().take(columns).collect();
+
+    let pad = (columns - text.chars().count()) / 2;
+
+    format!("{}{}", " ".repeat(pad), text)
+        .trim_end()
+        .to_string()
+}
+
+/// Puts `left` and `right` on the same line, cutting `left` short if they don't both fit.
+fn fit(left: &str, right: &str, columns: usize) -> String {
+    let right: String = right.chars().take(columns).collect();
+
+    let room = columns - right.chars().count();
+
+    let left: String = left.chars().take(room.saturating_sub(1)).collect();
+
+    let line = format!("{:<width$}{}", left, right, width = room);
+
+    line.trim_end().to_string()
+}
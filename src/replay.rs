@@ -0,0 +1,360 @@
+//! Reproduces the total of a past transaction from its saved journal, for when a customer
+//! disputes a receipt.
+//!
+//! ```
+//! # #[macro_use] extern crate scanner_terminal; #[macro_use] extern crate rust_decimal_macros; fn main() -> Result<(), scanner_terminal::TerminalError> {
+//!     use scanner_terminal::replay::{self, Discrepancy, SavedTransaction};
+//!     use scanner_terminal::{Terminal, Price};
+//!
+//!     let mut lane = setup_pricing!('A' => [{ price: 2 }, { min: 4, price: 7 }])?;
+//!
+//!     lane.set_catalog_version("2026-10-01");
+//!
+//!     for _ in 0..5 {
+//!         lane.scan('A')?;
+//!     }
+//!
+//!     let saved = lane.save()?.to_string();
+//!
+//!     // later on, with the catalog that was active at the time
+//!     let mut terminal = setup_pricing!('A' => [{ price: 2 }, { min: 4, price: 7 }])?;
+//!
+//!     terminal.set_catalog_version("2026-10-01");
+//!
+//!     let replayed = replay::replay(terminal, &SavedTransaction::parse(&saved)?)?;
+//!
+//!     assert!(replayed.matches());
+//!     assert_eq!(replayed.receipt.total(), dec!(9));
+//!
+//!     // with this week's pricing the total comes out differently
+//!     let mut terminal = setup_pricing!('A' => [{ price: 2 }, { min: 5, price: 8 }])?;
+//!
+//!     terminal.set_catalog_version("2026-10-08");
+//!
+//!     let replayed = replay::replay(terminal, &SavedTransaction::parse(&saved)?)?;
+//!
+//!     assert_eq!(replayed.discrepancies, vec![
+//!         Discrepancy::CatalogVersion { recorded: Some("2026-10-01".to_string()), replayed: Some("2026-10-08".to_string()) },
+//!         Discrepancy::Total { recorded: dec!(9), replayed: dec!(8) },
+//!         Discrepancy::GrandTotal { recorded: dec!(9), replayed: dec!(8) },
+//!         Discrepancy::AmountDue { recorded: dec!(9), replayed: dec!(8) },
+//!     ]);
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::journal::{self, Journal};
+use crate::schedule::FixedClock;
+use crate::{Receipt, Terminal, TerminalError};
+
+use rust_decimal::Decimal;
+
+use std::fmt;
+use std::str::FromStr;
+use std::time::SystemTime;
+
+/// A finished transaction as it is kept for later: the catalog version it was priced with, what
+/// the customer was charged, when it was priced and the journal of everything done at the
+/// terminal.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SavedTransaction {
+    pub catalog_version: Option<String>,
+    /// The receipt's total, before any tax charged on top.
+    pub total: Decimal,
+    /// The total with tax.
+    pub grand_total: Decimal,
+    /// What was left to pay after points and cash rounding.
+    pub amount_due: Decimal,
+    /// The terminal's time when the total was worked out, which decides the scheduled prices and
+    /// promotions it was priced with.
+    pub priced_at: SystemTime,
+    pub journal: Journal,
+}
+
+impl SavedTransaction {
+    /// Reads a transaction written out with `to_string()`.
+    pub fn parse(text: &str) -> Result<Self, TerminalError> {
+        let mut lines = text.splitn(6, '\n');
+
+        let mut header = |line: usize, name: &str| {
+            let invalid = || TerminalError::InvalidJournal {
+                line,
+                reason: format!("missing {}", name),
+            };
+
+            lines
+                .next()
+                .and_then(|l| l.strip_prefix(name))
+                .and_then(|l| l.strip_prefix('\t'))
+                .map(str::to_string)
+                .ok_or_else(invalid)
+        };
+
+        let catalog_version = Some(header(1, "catalog")?).filter(|v| !v.is_empty());
+
+        let mut amount = |line: usize, name: &str| {
+            let amount = header(line, name)?;
+
+            Decimal::from_str(&amount).map_err(|_| TerminalError::InvalidJournal {
+                line,
+                reason: format!("bad {}", name),
+            })
+        };
+
+        let total = amount(2, "total")?;
+        let grand_total = amount(3, "grand")?;
+        let amount_due = amount(4, "due")?;
+
+        let priced_at = header(5, "priced")?;
+
+        let priced_at =
+            journal::parse_timestamp(&priced_at).ok_or_else(|| TerminalError::InvalidJournal {
+                line: 5,
+                reason: "bad timestamp".to_string(),
+            })?;
+
+        let journal = Journal::parse(lines.next().unwrap_or("")).map_err(|e| match e {
+            // line numbers in the journal start after the five header lines
+            TerminalError::InvalidJournal { line, reason } => TerminalError::InvalidJournal {
+                line: line + 5,
+                reason,
+            },
+            e => e,
+        })?;
+
+        Ok(SavedTransaction {
+            catalog_version,
+            total,
+            grand_total,
+            amount_due,
+            priced_at,
+            journal,
+        })
+    }
+}
+
+impl fmt::Display for SavedTransaction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "catalog\t{}",
+            self.catalog_version.as_deref().unwrap_or("")
+        )?;
+        writeln!(f, "total\t{}", self.total)?;
+        writeln!(f, "grand\t{}", self.grand_total)?;
+        writeln!(f, "due\t{}", self.amount_due)?;
+        writeln!(f, "priced\t{}", journal::format_timestamp(self.priced_at))?;
+        write!(f, "{}", self.journal)
+    }
+}
+
+/// A way the replayed transaction differs from what was recorded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Discrepancy {
+    /// The terminal used for the replay doesn't have the catalog version that was recorded.
+    CatalogVersion {
+        recorded: Option<String>,
+        replayed: Option<String>,
+    },
+    Total {
+        recorded: Decimal,
+        replayed: Decimal,
+    },
+    /// The total with tax came out differently, say because the tax table changed.
+    GrandTotal {
+        recorded: Decimal,
+        replayed: Decimal,
+    },
+    /// What was left to pay came out differently, say because of points or cash rounding.
+    AmountDue {
+        recorded: Decimal,
+        replayed: Decimal,
+    },
+}
+
+/// The outcome of replaying a saved transaction.
+pub struct Replay {
+    /// The terminal with the saved journal replayed into it.
+    pub terminal: Terminal,
+    pub receipt: Receipt,
+    /// Empty when the replay reproduced the transaction exactly.
+    pub discrepancies: Vec<Discrepancy>,
+}
+
+impl Replay {
+    pub fn matches(&self) -> bool {
+        self.discrepancies.is_empty()
+    }
+}
+
+/// Replays `saved` into `terminal`, which should be a fresh terminal set up with the catalog
+/// for the saved catalog version. The terminal's clock is stopped at the time the transaction was
+/// priced. Differences are reported rather than treated as errors, errors are only returned when
+/// the journal can't be replayed at all.
+pub fn replay(mut terminal: Terminal, saved: &SavedTransaction) -> Result<Replay, TerminalError> {
+    terminal.set_clock(FixedClock::new(saved.priced_at));
+    terminal.restore(saved.journal.clone())?;
+
+    let receipt = terminal.receipt()?;
+
+    let mut discrepancies = vec![];
+
+    if terminal.catalog_version() != saved.catalog_version.as_deref() {
+        discrepancies.push(Discrepancy::CatalogVersion {
+            recorded: saved.catalog_version.clone(),
+            replayed: terminal.catalog_version().map(str::to_string),
+        });
+    }
+
+    if receipt.total() != saved.total {
+        discrepancies.push(Discrepancy::Total {
+            recorded: saved.total,
+            replayed: receipt.total(),
+        });
+    }
+
+    if receipt.grand_total() != saved.grand_total {
+        discrepancies.push(Discrepancy::GrandTotal {
+            recorded: saved.grand_total,
+            replayed: receipt.grand_total(),
+        });
+    }
+
+    if receipt.amount_due() != saved.amount_due {
+        discrepancies.push(Discrepancy::AmountDue {
+            recorded: saved.amount_due,
+            replayed: receipt.amount_due(),
+        });
+    }
+
+    Ok(Replay {
+        terminal,
+        receipt,
+        discrepancies,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tax::{Category, Jurisdiction, TaxTable};
+    use crate::{parse_price, setup_pricing, Price};
+
+    use rust_decimal_macros::dec;
+
+    use std::time::{Duration, UNIX_EPOCH};
+
+    #[test]
+    fn it_reports_tax_that_comes_out_differently() -> Result<(), TerminalError> {
+        let state = |rate| {
+            TaxTable::new().jurisdiction(Jurisdiction::new("State").rate(Category::General, rate))
+        };
+
+        let mut lane = setup_pricing!('A' => [{ price: 10 }])?;
+
+        lane.set_tax_table(state(dec!(5)))?;
+        lane.scan('A')?;
+
+        let saved = SavedTransaction::parse(&lane.save()?.to_string())?;
+
+        assert_eq!(saved.grand_total, dec!(10.50));
+        assert_eq!(saved.amount_due, dec!(10.50));
+
+        // the items come to the same but the tax rate has gone up since
+        let mut terminal = setup_pricing!('A' => [{ price: 10 }])?;
+
+        terminal.set_tax_table(state(dec!(6)))?;
+
+        let replayed = replay(terminal, &saved)?;
+
+        assert_eq!(
+            replayed.discrepancies,
+            vec![
+                Discrepancy::GrandTotal {
+                    recorded: dec!(10.50),
+                    replayed: dec!(10.60)
+                },
+                Discrepancy::AmountDue {
+                    recorded: dec!(10.50),
+                    replayed: dec!(10.60)
+                },
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn it_reports_where_a_saved_transaction_is_broken() -> Result<(), TerminalError> {
+        let saved = "catalog\t\ntotal\t4\ngrand\t4.2\ndue\t4.2\npriced\t1760000002.000000000\n1\t1760000000.000000000\tscan\tA\n3\t1760000001.000000000\tscan\tA\n";
+
+        assert_eq!(
+            SavedTransaction::parse(saved),
+            Err(TerminalError::InvalidJournal {
+                line: 7,
+                reason: "out of sequence".to_string()
+            })
+        );
+
+        assert_eq!(
+            SavedTransaction::parse("total\t4\n"),
+            Err(TerminalError::InvalidJournal {
+                line: 1,
+                reason: "missing catalog".to_string()
+            })
+        );
+
+        let saved = SavedTransaction::parse(&saved.replace("3\t", "2\t"))?;
+
+        assert_eq!(
+            SavedTransaction::parse("catalog\t\ntotal\t4\ngrand\tfour\n"),
+            Err(TerminalError::InvalidJournal {
+                line: 3,
+                reason: "bad grand".to_string()
+            })
+        );
+
+        assert_eq!(saved.catalog_version, None);
+        assert_eq!(saved.grand_total, dec!(4.2));
+        assert_eq!(saved.journal.entries().len(), 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn it_rejects_timestamps_that_dont_fit() -> Result<(), TerminalError> {
+        let bad = |line| TerminalError::InvalidJournal {
+            line,
+            reason: "bad timestamp".to_string(),
+        };
+
+        // nanoseconds that would carry over into seconds that are already at their limit
+        assert_eq!(
+            Journal::parse("1\t18446744073709551615.1000000000\tclear\n"),
+            Err(bad(1))
+        );
+
+        for priced in &[
+            "18446744073709551615.1000000000",
+            "18446744073709551615.999999999",
+            "1760000000.5",
+            "1760000000.+00000005",
+        ] {
+            let saved = format!(
+                "catalog\t\ntotal\t4\ngrand\t4\ndue\t4\npriced\t{}\n",
+                priced
+            );
+
+            assert_eq!(SavedTransaction::parse(&saved), Err(bad(5)), "{}", priced);
+        }
+
+        let saved = "catalog\t\ntotal\t4\ngrand\t4\ndue\t4\npriced\t1760000000.500000000\n";
+
+        assert_eq!(
+            SavedTransaction::parse(saved)?.priced_at,
+            UNIX_EPOCH + Duration::from_millis(1_760_000_000_500)
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,305 @@
+//! How amounts are rounded to what can actually be paid.
+//!
+//! ```
+//! # #[macro_use] extern crate scanner_terminal; #[macro_use] extern crate rust_decimal_macros; fn main() -> Result<(), scanner_terminal::TerminalError> {
+//!     use scanner_terminal::rounding::{Rounding, Scope, Strategy, Tender};
+//!     use scanner_terminal::{Terminal, Price};
+//!
+//!     let mut terminal = setup_pricing!('A' => [{ price: 1.005 }]; 'B' => [{ price: 1.005 }])?;
+//!
+//!     terminal.scan('A')?;
+//!     terminal.scan('B')?;
+//!
+//!     assert_eq!(terminal.total()?, dec!(2.01));
+//!
+//!     terminal.set_rounding(Rounding::new().scope(Scope::PerLine))?;
+//!     assert_eq!(terminal.total()?, dec!(2.02));
+//!
+//!     terminal.set_rounding(Rounding::new().scope(Scope::PerLine).strategy(Strategy::HalfEven))?;
+//!     assert_eq!(terminal.total()?, dec!(2.00));
+//!
+//!     // cash is rounded to the nearest 5 cents, cards are charged to the cent
+//!     terminal.set_rounding(Rounding::new().cash(dec!(0.05)))?;
+//!     assert_eq!(terminal.receipt()?.amount_due(), dec!(2.01));
+//!
+//!     terminal.tender(Tender::Cash)?;
+//!
+//!     let receipt = terminal.receipt()?;
+//!
+//!     assert_eq!(receipt.cash_rounding(), dec!(-0.01));
+//!     assert_eq!(receipt.amount_due(), dec!(2.00));
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::TerminalError;
+
+use rust_decimal::{Decimal, RoundingStrategy};
+
+use std::fmt;
+
+/// Which way amounts exactly halfway between two minor units go.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Strategy {
+    /// Away from zero, the way scales do.
+    #[default]
+    HalfUp,
+    /// To the even minor unit, also called banker's rounding.
+    HalfEven,
+}
+
+/// What gets rounded to the minor unit.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Scope {
+    /// Every line, before the lines are added up and discounted.
+    PerLine,
+    /// Only the total.
+    #[default]
+    PerTransaction,
+}
+
+/// How the customer pays what is due.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Tender {
+    Card,
+    Cash,
+}
+
+impl Tender {
+    pub(crate) fn parse(name: &str) -> Option<Tender> {
+        match name {
+            "card" => Some(Tender::Card),
+            "cash" => Some(Tender::Cash),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Tender {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Tender::Card => "card",
+            Tender::Cash => "cash",
+        };
+
+        write!(f, "{}", name)
+    }
+}
+
+/// The rounding a terminal applies, see `Terminal::set_rounding()`. Whatever the scope, the
+/// total is always rounded to the minor unit, with the difference kept on the receipt.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rounding {
+    pub strategy: Strategy,
+    pub scope: Scope,
+    /// Decimal places in the currency's minor unit, 2 for cents.
+    pub minor_unit: u32,
+    /// What cash payments are rounded to, when the smallest coins aren't in use.
+    pub cash: Option<Decimal>,
+}
+
+impl Default for Rounding {
+    fn default() -> Self {
+        Rounding {
+            strategy: Strategy::default(),
+            scope: Scope::default(),
+            minor_unit: 2,
+            cash: None,
+        }
+    }
+}
+
+impl Rounding {
+    /// Rounds the total half up to cents, with no cash rounding.
+    pub fn new() -> Self {
+        Rounding::default()
+    }
+
+    pub fn strategy(mut self, strategy: Strategy) -> Self {
+        self.strategy = strategy;
+        self
+    }
+
+    pub fn scope(mut self, scope: Scope) -> Self {
+        self.scope = scope;
+        self
+    }
+
+    /// Rounds to `places` decimal places, 0 for a currency without a minor unit.
+    pub fn minor_unit(mut self, places: u32) -> Self {
+        self.minor_unit = places;
+        self
+    }
+
+    /// Rounds what is paid in cash to the nearest `increment`, 0.05 say.
+    pub fn cash(mut self, increment: Decimal) -> Self {
+        self.cash = Some(increment);
+        self
+    }
+
+    /// Rounds `amount` to the minor unit.
+    pub fn round(&self, amount: Decimal) -> Decimal {
+        amount.round_dp_with_strategy(self.minor_unit, self.midpoint())
+    }
+
+    /// Rounds `amount` to the cash increment, or to the minor unit without one.
+    pub(crate) fn round_cash(&self, amount: Decimal) -> Result<Decimal, TerminalError> {
+        let increment = match self.cash {
+            Some(increment) => increment,
+            None => return Ok(self.round(amount)),
+        };
+
+        amount
+            .checked_div(increment)
+            .map(|units| units.round_dp_with_strategy(0, self.midpoint()))
+            .and_then(|units| units.checked_mul(increment))
+            .ok_or(TerminalError::Overflow)
+    }
+
+    pub(crate) fn validate(&self) -> Result<(), TerminalError> {
+        if self.minor_unit > 28 {
+            return Err(TerminalError::InvalidRounding(format!(
+                "{} decimal places is more than an amount can have",
+                self.minor_unit
+            )));
+        }
+
+        match self.cash {
+            Some(increment) if increment <= Decimal::ZERO => Err(TerminalError::InvalidRounding(
+                format!("cash increment {} isn't positive", increment),
+            )),
+            Some(increment) if self.round(increment) != increment => {
+                Err(TerminalError::InvalidRounding(format!(
+                    "cash increment {} is smaller than the minor unit",
+                    increment
+                )))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn midpoint(&self) -> RoundingStrategy {
+        match self.strategy {
+            Strategy::HalfUp => RoundingStrategy::MidpointAwayFromZero,
+            Strategy::HalfEven => RoundingStrategy::MidpointNearestEven,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::journal::Journal;
+    use crate::render::{Renderer, Width};
+    use crate::{parse_price, setup_pricing, Price};
+
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn it_rounds_fractional_cents_to_the_minor_unit() -> Result<(), TerminalError> {
+        let mut terminal = setup_pricing!('A' => [{ price: 1.333 }]; 'B' => [{ price: 120.4 }])?;
+
+        terminal.set_quantity('A', 3)?;
+
+        let receipt = terminal.receipt()?;
+
+        assert_eq!(receipt.total(), dec!(4.00));
+        assert_eq!(receipt.rounding(), dec!(0.001));
+
+        terminal.scan('B')?;
+        terminal.set_rounding(Rounding::new().minor_unit(0))?;
+
+        assert_eq!(terminal.total()?, dec!(124));
+
+        // amounts are printed to the minor unit too
+        let text = Renderer::new(Width::Regular).render_text(&terminal.receipt()?);
+
+        assert!(text
+            .lines()
+            .any(|l| l.starts_with("TOTAL") && l.ends_with(" 124")));
+        assert!(text
+            .lines()
+            .any(|l| l.starts_with("B") && l.ends_with(" 120")));
+
+        assert_eq!(
+            terminal.set_rounding(Rounding::new().cash(dec!(0))),
+            Err(TerminalError::InvalidRounding(
+                "cash increment 0 isn't positive".to_string()
+            ))
+        );
+        assert_eq!(
+            terminal.set_rounding(Rounding::new().minor_unit(0).cash(dec!(0.5))),
+            Err(TerminalError::InvalidRounding(
+                "cash increment 0.5 is smaller than the minor unit".to_string()
+            ))
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn it_keeps_per_line_rounding_out_of_the_savings() -> Result<(), TerminalError> {
+        let mut terminal = setup_pricing!('A' => [{ price: 1.005 }]; 'B' => [{ price: 2.115 }])?;
+
+        terminal.set_rounding(Rounding::new().scope(Scope::PerLine))?;
+        terminal.scan('A')?;
+        terminal.scan('B')?;
+
+        let receipt = terminal.receipt()?;
+
+        assert_eq!(receipt.lines()[0].rounding, dec!(0.005));
+        assert_eq!(receipt.lines()[1].rounding, dec!(0.005));
+        assert_eq!(receipt.total(), dec!(3.13));
+        assert_eq!(receipt.savings(), dec!(0));
+
+        terminal.set_rounding(
+            Rounding::new()
+                .scope(Scope::PerLine)
+                .strategy(Strategy::HalfEven),
+        )?;
+
+        let receipt = terminal.receipt()?;
+
+        assert_eq!(receipt.lines()[0].rounding, dec!(-0.005));
+        assert_eq!(receipt.total(), dec!(3.12));
+        assert_eq!(receipt.savings(), dec!(0));
+        assert!(!Renderer::new(Width::Regular)
+            .render_text(&receipt)
+            .contains("You saved"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn it_only_rounds_cash_to_the_cash_increment() -> Result<(), TerminalError> {
+        let mut terminal = setup_pricing!('A' => [{ price: 3.48 }])?;
+
+        terminal.set_rounding(Rounding::new().cash(dec!(0.05)))?;
+        terminal.scan('A')?;
+        terminal.tender(Tender::Card)?;
+
+        assert_eq!(terminal.receipt()?.amount_due(), dec!(3.48));
+
+        terminal.tender(Tender::Cash)?;
+
+        let receipt = terminal.receipt()?;
+
+        assert_eq!(receipt.tender(), Some(Tender::Cash));
+        assert_eq!(receipt.cash_rounding(), dec!(0.02));
+        assert_eq!(receipt.grand_total(), dec!(3.48));
+        assert_eq!(receipt.amount_due(), dec!(3.50));
+
+        let text = Renderer::new(Width::Regular).render_text(&receipt);
+
+        assert!(text.contains("Cash rounding"));
+        assert!(text.contains("DUE"));
+
+        // the tender is kept with the rest of the transaction
+        assert_eq!(
+            &Journal::parse(&terminal.journal().to_string())?,
+            terminal.journal()
+        );
+
+        Ok(())
+    }
+}
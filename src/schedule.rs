@@ -0,0 +1,252 @@
+//! When prices and promotions are in effect, and the clock a terminal reads the time from.
+//!
+//! ```
+//!     use scanner_terminal::schedule::{Schedule, Weekday, Window};
+//!
+//!     use std::time::{Duration, UNIX_EPOCH};
+//!
+//!     let happy_hour = Schedule::new().window(Window::new(&Weekday::WEEKDAYS, (16, 0), (18, 0)));
+//!
+//!     // Friday 2 October 2026, 16:30 and 18:00 UTC
+//!     let half_past_four = UNIX_EPOCH + Duration::from_secs(1_790_958_600);
+//!     let six = half_past_four + Duration::from_secs(90 * 60);
+//!
+//!     assert!(happy_hour.contains(half_past_four, 0));
+//!     assert!(!happy_hour.contains(six, 0));
+//!
+//!     // the same instant is 18:30 two hours east of UTC
+//!     assert!(!happy_hour.contains(half_past_four, 120));
+//! ```
+
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Where a terminal gets the time from, both for pricing and for the journal. Clocks are
+/// `Send + Sync` so a terminal can be moved to another thread.
+pub trait Clock: Send + Sync {
+    fn now(&self) -> SystemTime;
+}
+
+/// The computer's clock, used by terminals unless they are given another one.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+/// A clock that only moves when told to. Clones share the same time, so a clone kept after
+/// handing one to a terminal can move the terminal's time along.
+#[derive(Debug, Clone)]
+pub struct FixedClock(Arc<Mutex<SystemTime>>);
+
+impl FixedClock {
+    pub fn new(time: SystemTime) -> Self {
+        FixedClock(Arc::new(Mutex::new(time)))
+    }
+
+    pub fn set(&self, time: SystemTime) {
+        *self.time() = time;
+    }
+
+    pub fn advance(&self, by: Duration) {
+        *self.time() += by;
+    }
+
+    // a panic while the time was held can't leave it half written, so a poisoned lock is fine
+    fn time(&self) -> MutexGuard<'_, SystemTime> {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl Clock for FixedClock {
+    fn now(&self) -> SystemTime {
+        *self.time()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Weekday {
+    Mon,
+    Tue,
+    Wed,
+    Thu,
+    Fri,
+    Sat,
+    Sun,
+}
+
+impl Weekday {
+    pub const WEEKDAYS: [Weekday; 5] = [
+        Weekday::Mon,
+        Weekday::Tue,
+        Weekday::Wed,
+        Weekday::Thu,
+        Weekday::Fri,
+    ];
+
+    pub const ALL: [Weekday; 7] = [
+        Weekday::Mon,
+        Weekday::Tue,
+        Weekday::Wed,
+        Weekday::Thu,
+        Weekday::Fri,
+        Weekday::Sat,
+        Weekday::Sun,
+    ];
+
+    fn previous(self) -> Weekday {
+        Weekday::ALL[(self as usize + 6) % 7]
+    }
+}
+
+/// A time of day that repeats on some days of the week, in the store's local time.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Window {
+    pub days: Vec<Weekday>,
+    /// Minutes after midnight the window opens.
+    pub from: u32,
+    /// Minutes after midnight the window closes, not included in the window. A window that closes
+    /// at or before it opens runs past midnight into the next day.
+    pub to: u32,
+}
+
+impl Window {
+    /// A window from `from` up to `to`, both given as (hour, minute). `(24, 0)` is the end of
+    /// the day.
+    pub fn new(days: &[Weekday], from: (u32, u32), to: (u32, u32)) -> Self {
+        Window {
+            days: days.to_vec(),
+            from: from.0 * 60 + from.1,
+            to: to.0 * 60 + to.1,
+        }
+    }
+
+    fn contains(&self, day: Weekday, minute: u32) -> bool {
+        if self.from < self.to {
+            return self.days.contains(&day) && minute >= self.from && minute < self.to;
+        }
+
+        (self.days.contains(&day) && minute >= self.from)
+            || (self.days.contains(&day.previous()) && minute < self.to)
+    }
+}
+
+/// When something is in effect: from `starts` up to `ends`, and within those only during one
+/// of `windows` if there are any.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct Schedule {
+    pub starts: Option<SystemTime>,
+    /// Not included in the schedule.
+    pub ends: Option<SystemTime>,
+    pub windows: Vec<Window>,
+}
+
+impl Schedule {
+    /// A schedule that is always in effect, until it is narrowed down.
+    pub fn new() -> Self {
+        Schedule::default()
+    }
+
+    pub fn starts(mut self, time: SystemTime) -> Self {
+        self.starts = Some(time);
+        self
+    }
+
+    pub fn ends(mut self, time: SystemTime) -> Self {
+        self.ends = Some(time);
+        self
+    }
+
+    pub fn window(mut self, window: Window) -> Self {
+        self.windows.push(window);
+        self
+    }
+
+    /// Whether `time` is in the schedule, with windows read in local time `utc_offset` minutes
+    /// east of UTC.
+    pub fn contains(&self, time: SystemTime, utc_offset: i32) -> bool {
+        if self.starts.is_some_and(|starts| time < starts)
+            || self.ends.is_some_and(|ends| time >= ends)
+        {
+            return false;
+        }
+
+        if self.windows.is_empty() {
+            return true;
+        }
+
+        let (day, minute) = local(time, utc_offset);
+
+        self.windows.iter().any(|w| w.contains(day, minute))
+    }
+}
+
+/// The day of the week and minute of the day at `time`, `utc_offset` minutes east of UTC.
+fn local(time: SystemTime, utc_offset: i32) -> (Weekday, u32) {
+    let seconds = match time.duration_since(UNIX_EPOCH) {
+        Ok(since) => since.as_secs() as i64,
+        Err(e) => -(e.duration().as_secs() as i64),
+    };
+
+    let seconds = seconds + i64::from(utc_offset) * 60;
+
+    let days = seconds.div_euclid(86_400);
+
+    // 1 January 1970 was a Thursday
+    let day = Weekday::ALL[(days + 3).rem_euclid(7) as usize];
+
+    (day, (seconds.rem_euclid(86_400) / 60) as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Monday 5 October 2026, midnight UTC.
+    fn monday() -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(1_791_158_400)
+    }
+
+    fn at(day: u64, hour: u64, minute: u64) -> SystemTime {
+        monday() + Duration::from_secs(((day * 24 + hour) * 60 + minute) * 60)
+    }
+
+    #[test]
+    fn it_finds_the_local_day_and_minute() {
+        assert_eq!(local(monday(), 0), (Weekday::Mon, 0));
+        assert_eq!(local(at(6, 23, 59), 0), (Weekday::Sun, 23 * 60 + 59));
+        assert_eq!(local(monday(), -60), (Weekday::Sun, 23 * 60));
+        assert_eq!(
+            local(UNIX_EPOCH - Duration::from_secs(60), 0),
+            (Weekday::Wed, 23 * 60 + 59)
+        );
+    }
+
+    #[test]
+    fn it_runs_windows_past_midnight() {
+        let late = Schedule::new().window(Window::new(&[Weekday::Fri], (22, 0), (2, 0)));
+
+        assert!(!late.contains(at(4, 21, 59), 0));
+        assert!(late.contains(at(4, 22, 0), 0));
+        assert!(late.contains(at(5, 1, 59), 0));
+        assert!(!late.contains(at(5, 2, 0), 0));
+        // Thursday night isn't in it
+        assert!(!late.contains(at(4, 1, 0), 0));
+    }
+
+    #[test]
+    fn it_keeps_windows_within_the_dates() {
+        let weekly = Schedule::new()
+            .starts(at(1, 0, 0))
+            .ends(at(8, 0, 0))
+            .window(Window::new(&Weekday::ALL, (9, 0), (17, 0)));
+
+        assert!(!weekly.contains(at(0, 10, 0), 0));
+        assert!(weekly.contains(at(1, 10, 0), 0));
+        assert!(weekly.contains(at(7, 16, 59), 0));
+        assert!(!weekly.contains(at(8, 10, 0), 0));
+    }
+}
@@ -0,0 +1,60 @@
+use std::fmt;
+
+/// Identifies an item in the catalog. Anything that can be written out as text can be used, so
+/// single letters, alphanumeric codes, numeric item codes and barcodes all work.
+///
+/// ```
+///     use scanner_terminal::Sku;
+///
+///     assert_eq!(Sku::from('A'), Sku::from("A"));
+///     assert_eq!(Sku::from(4011_u64).as_str(), "4011");
+///     assert_eq!(Sku::from("BAN-001").to_string(), "BAN-001");
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Sku(String);
+
+impl Sku {
+    pub fn new<S: Into<String>>(code: S) -> Self {
+        Sku(code.into())
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for Sku {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl From<char> for Sku {
+    fn from(code: char) -> Self {
+        Sku(code.to_string())
+    }
+}
+
+impl From<&str> for Sku {
+    fn from(code: &str) -> Self {
+        Sku(code.to_string())
+    }
+}
+
+impl From<String> for Sku {
+    fn from(code: String) -> Self {
+        Sku(code)
+    }
+}
+
+impl From<u64> for Sku {
+    fn from(code: u64) -> Self {
+        Sku(code.to_string())
+    }
+}
+
+impl From<&Sku> for Sku {
+    fn from(sku: &Sku) -> Self {
+        sku.clone()
+    }
+}
@@ -0,0 +1,612 @@
+//! Sales tax, charged by every jurisdiction the store is in on top of the total, or VAT that is
+//! already included in the prices.
+//!
+//! ```
+//! # #[macro_use] extern crate scanner_terminal; #[macro_use] extern crate rust_decimal_macros; fn main() -> Result<(), scanner_terminal::TerminalError> {
+//!     use scanner_terminal::tax::{Category, Jurisdiction, TaxTable};
+//!     use scanner_terminal::{Item, Price, Terminal};
+//!
+//!     use std::collections::HashMap;
+//!
+//!     let mut prices = HashMap::new();
+//!
+//!     prices.insert('A', Item::new(vec![Price::new(0, dec!(10))]));
+//!     prices.insert('B', Item::new(vec![Price::new(0, dec!(4))]).tax(Category::Food));
+//!     prices.insert('C', Item::new(vec![Price::new(0, dec!(20))]).tax(Category::Alcohol));
+//!
+//!     let mut terminal = Terminal::new(prices)?;
+//!
+//!     terminal.set_tax_table(TaxTable::new()
+//!         .jurisdiction(Jurisdiction::new("State").rate(Category::General, dec!(6)).rate(Category::Alcohol, dec!(6)).rate(Category::Food, dec!(2)))
+//!         .jurisdiction(Jurisdiction::new("County").rate(Category::General, dec!(1)).rate(Category::Alcohol, dec!(1)))
+//!         .jurisdiction(Jurisdiction::new("City").rate(Category::Alcohol, dec!(3))))?;
+//!
+//!     terminal.scan('A')?;
+//!     terminal.scan('B')?;
+//!     terminal.scan('C')?;
+//!
+//!     let receipt = terminal.receipt()?;
+//!
+//!     assert_eq!(receipt.total(), dec!(34));
+//!     // 6% of 30 and 2% of 4, 1% of 30 and 3% of 20
+//!     assert_eq!(receipt.taxes().iter().map(|t| t.amount).collect::<Vec<_>>(), vec![dec!(1.88), dec!(0.30), dec!(0.60)]);
+//!     assert_eq!(receipt.grand_total(), dec!(36.78));
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::rounding::Rounding;
+use crate::{Catalog, Receipt, TerminalError};
+
+use rust_decimal::Decimal;
+
+use std::fmt;
+
+/// What kind of tax an item is charged.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Category {
+    Food,
+    General,
+    Alcohol,
+    /// Never taxed, whatever the jurisdictions charge.
+    Exempt,
+}
+
+impl fmt::Display for Category {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Category::Food => "food",
+            Category::General => "general",
+            Category::Alcohol => "alcohol",
+            Category::Exempt => "exempt",
+        };
+
+        write!(f, "{}", name)
+    }
+}
+
+impl Category {
+    pub(crate) fn parse(name: &str) -> Option<Category> {
+        match name {
+            "food" => Some(Category::Food),
+            "general" => Some(Category::General),
+            "alcohol" => Some(Category::Alcohol),
+            "exempt" => Some(Category::Exempt),
+            _ => None,
+        }
+    }
+}
+
+/// A state, county, city or district that charges its own tax.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Jurisdiction {
+    pub name: String,
+    /// Percentages charged on each category, categories that aren't listed aren't taxed.
+    pub rates: Vec<(Category, Decimal)>,
+}
+
+impl Jurisdiction {
+    pub fn new<S: Into<String>>(name: S) -> Self {
+        Jurisdiction {
+            name: name.into(),
+            rates: vec![],
+        }
+    }
+
+    /// Charges `percent` on `category`, replacing any rate already set for it.
+    pub fn rate(mut self, category: Category, percent: Decimal) -> Self {
+        self.rates.retain(|(c, _)| *c != category);
+        self.rates.push((category, percent));
+        self
+    }
+
+    fn percent(&self, category: Category) -> Option<Decimal> {
+        self.rates
+            .iter()
+            .find(|(c, _)| *c == category && category != Category::Exempt)
+            .map(|(_, percent)| *percent)
+    }
+}
+
+/// Every jurisdiction whose tax is charged at the store, each one charged on the same amounts.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TaxTable {
+    pub jurisdictions: Vec<Jurisdiction>,
+    /// Whether prices already include the tax, as VAT does, rather than having it charged on
+    /// top.
+    pub included: bool,
+    pub rounding: TaxRounding,
+}
+
+impl TaxTable {
+    pub fn new() -> Self {
+        TaxTable::default()
+    }
+
+    pub fn jurisdiction(mut self, jurisdiction: Jurisdiction) -> Self {
+        self.jurisdictions.push(jurisdiction);
+        self
+    }
+
+    /// Takes prices as already including the tax, which is pulled back out of them rather than
+    /// charged on top.
+    ///
+    /// ```
+    /// # #[macro_use] extern crate scanner_terminal; #[macro_use] extern crate rust_decimal_macros; fn main() -> Result<(), scanner_terminal::TerminalError> {
+    ///     use scanner_terminal::tax::{Category, Jurisdiction, TaxTable};
+    ///     use scanner_terminal::{Item, Price, Terminal};
+    ///
+    ///     use std::collections::HashMap;
+    ///
+    ///     let mut prices = HashMap::new();
+    ///
+    ///     prices.insert('A', Item::new(vec![Price::new(0, dec!(12))]));
+    ///     prices.insert('B', Item::new(vec![Price::new(0, dec!(2.10))]).tax(Category::Food));
+    ///
+    ///     let mut terminal = Terminal::new(prices)?;
+    ///
+    ///     terminal.set_tax_table(TaxTable::new()
+    ///         .jurisdiction(Jurisdiction::new("VAT").rate(Category::General, dec!(20)).rate(Category::Food, dec!(5)))
+    ///         .included())?;
+    ///
+    ///     terminal.scan('A')?;
+    ///     terminal.scan('B')?;
+    ///
+    ///     let receipt = terminal.receipt()?;
+    ///
+    ///     assert_eq!(receipt.grand_total(), dec!(14.10));
+    ///     assert_eq!(receipt.taxes().iter().map(|t| (t.rate, t.amount, t.net())).collect::<Vec<_>>(), vec![
+    ///         (Some(dec!(20)), dec!(2.00), dec!(10.00)),
+    ///         (Some(dec!(5)), dec!(0.10), dec!(2.00)),
+    ///     ]);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn included(mut self) -> Self {
+        self.included = true;
+        self
+    }
+
+    pub fn rounding(mut self, rounding: TaxRounding) -> Self {
+        self.rounding = rounding;
+        self
+    }
+
+    pub(crate) fn validate(&self) -> Result<(), TerminalError> {
+        for j in &self.jurisdictions {
+            if let Some((_, rate)) = j.rates.iter().find(|(_, r)| r.is_sign_negative()) {
+                return Err(TerminalError::InvalidTaxRate {
+                    jurisdiction: j.name.clone(),
+                    rate: *rate,
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The tax each jurisdiction charges on what the items in `receipt` came to once their
+    /// discounts were taken off.
+    ///
+    /// Tax charged on top comes to one line per jurisdiction. Tax included in the prices is
+    /// pulled back out at every rate an item is charged, so it comes to one line per rate that
+    /// any of the items were charged at, highest first.
+    ///
+    /// Items covered by `exemption` are left out of what is taxed and counted as exempt sales
+    /// instead.
+    pub(crate) fn taxes(
+        &self,
+        catalog: &Catalog,
+        receipt: &Receipt,
+        exemption: Option<&Exemption>,
+        rounding: &Rounding,
+    ) -> Result<Vec<TaxLine>, TerminalError> {
+        let amounts: Vec<(Category, Decimal, bool)> = receipt
+            .net_amounts()?
+            .into_iter()
+            .map(|(item, amount)| {
+                let category = catalog.get(&item).map_or(Category::General, |i| i.tax);
+                let exempt = exemption.is_some_and(|e| e.covers(category));
+
+                (category, amount.max(Decimal::ZERO), exempt)
+            })
+            .collect();
+
+        let mut taxes = vec![];
+
+        for j in &self.jurisdictions {
+            if !self.included {
+                taxes.push(self.band(j, None, &amounts, rounding)?);
+                continue;
+            }
+
+            let mut rates: Vec<Decimal> = vec![];
+
+            for (category, _, _) in &amounts {
+                match j.percent(*category) {
+                    Some(percent) if !rates.contains(&percent) => rates.push(percent),
+                    _ => {}
+                }
+            }
+
+            rates.sort_by(|a, b| b.cmp(a));
+
+            for rate in rates {
+                taxes.push(self.band(j, Some(rate), &amounts, rounding)?);
+            }
+        }
+
+        Ok(taxes)
+    }
+
+    /// The tax `jurisdiction` charges on the items it charges `rate` on, or on every item it
+    /// taxes when there's no rate.
+    fn band(
+        &self,
+        jurisdiction: &Jurisdiction,
+        rate: Option<Decimal>,
+        amounts: &[(Category, Decimal, bool)],
+        rounding: &Rounding,
+    ) -> Result<TaxLine, TerminalError> {
+        let mut taxable = Decimal::ZERO;
+        let mut tax = Decimal::ZERO;
+        let mut exempt = Decimal::ZERO;
+        let mut waived = Decimal::ZERO;
+
+        for (category, amount, is_exempt) in amounts {
+            let percent = match jurisdiction.percent(*category) {
+                Some(percent) if rate.is_none_or(|r| r == percent) => percent,
+                _ => continue,
+            };
+
+            // included tax is pulled out of the price with every jurisdiction's tax in it
+            let base = if self.included {
+                self.jurisdictions
+                    .iter()
+                    .filter_map(|j| j.percent(*category))
+                    .try_fold(Decimal::ONE_HUNDRED, |sum, p| sum.checked_add(p))
+                    .ok_or(TerminalError::Overflow)?
+            } else {
+                Decimal::ONE_HUNDRED
+            };
+
+            let line = amount
+                .checked_mul(percent)
+                .and_then(|t| t.checked_div(base))
+                .ok_or(TerminalError::Overflow)?;
+
+            let line = match self.rounding {
+                TaxRounding::PerLine => rounding.round(line),
+                TaxRounding::PerInvoice => line,
+            };
+
+            let (sales, total) = if *is_exempt {
+                (&mut exempt, &mut waived)
+            } else {
+                (&mut taxable, &mut tax)
+            };
+
+            *sales = sales.checked_add(*amount).ok_or(TerminalError::Overflow)?;
+            *total = total.checked_add(line).ok_or(TerminalError::Overflow)?;
+        }
+
+        Ok(TaxLine {
+            jurisdiction: jurisdiction.name.clone(),
+            rate,
+            taxable,
+            amount: rounding.round(tax),
+            included: self.included,
+            exempt,
+            waived: rounding.round(waived),
+        })
+    }
+}
+
+/// Why a transaction isn't charged tax, for a school or a charity say. The exemption is kept in
+/// the transaction's journal with the rest of it.
+///
+/// ```
+/// # #[macro_use] extern crate scanner_terminal; #[macro_use] extern crate rust_decimal_macros; fn main() -> Result<(), scanner_terminal::TerminalError> {
+///     use scanner_terminal::tax::{Category, Exemption, Jurisdiction, TaxTable};
+///     use scanner_terminal::{Item, Price, Terminal};
+///
+///     use std::collections::HashMap;
+///
+///     let mut prices = HashMap::new();
+///
+///     prices.insert('A', Item::new(vec![Price::new(0, dec!(10))]));
+///     prices.insert('B', Item::new(vec![Price::new(0, dec!(4))]).tax(Category::Food));
+///
+///     let mut terminal = Terminal::new(prices)?;
+///
+///     terminal.set_tax_table(TaxTable::new()
+///         .jurisdiction(Jurisdiction::new("State").rate(Category::General, dec!(6)).rate(Category::Food, dec!(2))))?;
+///
+///     terminal.scan('A')?;
+///     terminal.scan('B')?;
+///     terminal.exempt_tax(Exemption::new("EX-20417", "school").only(Category::General))?;
+///
+///     let receipt = terminal.receipt()?;
+///     let state = &receipt.taxes()[0];
+///
+///     assert_eq!((state.taxable, state.amount), (dec!(4), dec!(0.08)));
+///     assert_eq!((state.exempt, state.waived), (dec!(10), dec!(0.60)));
+///     assert_eq!(receipt.grand_total(), dec!(14.08));
+///     assert_eq!(receipt.exemption().map(|e| e.certificate.as_str()), Some("EX-20417"));
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Exemption {
+    /// The number on the customer's exemption certificate.
+    pub certificate: String,
+    pub reason: String,
+    /// The categories that aren't taxed, every category when empty.
+    pub categories: Vec<Category>,
+}
+
+impl Exemption {
+    pub fn new<C: Into<String>, R: Into<String>>(certificate: C, reason: R) -> Self {
+        Exemption {
+            certificate: certificate.into(),
+            reason: reason.into(),
+            categories: vec![],
+        }
+    }
+
+    /// Only exempts items in `category`, along with any other categories given.
+    pub fn only(mut self, category: Category) -> Self {
+        if !self.categories.contains(&category) {
+            self.categories.push(category);
+        }
+        self
+    }
+
+    pub(crate) fn covers(&self, category: Category) -> bool {
+        self.categories.is_empty() || self.categories.contains(&category)
+    }
+}
+
+/// When tax is rounded to the minor unit, with the terminal's rounding.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TaxRounding {
+    /// The tax on each item is rounded, then added up.
+    PerLine,
+    /// The tax on all of the items is added up, then rounded.
+    #[default]
+    PerInvoice,
+}
+
+/// The tax one jurisdiction charges on a transaction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TaxLine {
+    pub jurisdiction: String,
+    /// The rate the tax was pulled out at, when it was included in the prices.
+    pub rate: Option<Decimal>,
+    /// What the items it taxes came to, with the tax when it was included in the prices.
+    pub taxable: Decimal,
+    pub amount: Decimal,
+    /// Whether the tax was already in the prices rather than charged on top.
+    pub included: bool,
+    /// What the items left out for the transaction's exemption came to.
+    pub exempt: Decimal,
+    /// The tax the exemption saved. When the tax was included in the prices it comes off the
+    /// grand total.
+    pub waived: Decimal,
+}
+
+impl TaxLine {
+    /// What the items it taxes came to without the tax.
+    pub fn net(&self) -> Decimal {
+        if self.included {
+            self.taxable - self.amount
+        } else {
+            self.taxable
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::coupon::Coupon;
+    use crate::{Discount, Item, Price, Promotion, Terminal};
+
+    use rust_decimal_macros::dec;
+
+    use std::collections::HashMap;
+
+    #[test]
+    fn it_taxes_what_items_come_to_after_discounts() -> Result<(), TerminalError> {
+        let mut prices = HashMap::new();
+
+        prices.insert('A', Item::new(vec![Price::new(0, dec!(10))]));
+        prices.insert(
+            'B',
+            Item::new(vec![Price::new(0, dec!(5))]).tax(Category::Exempt),
+        );
+        prices.insert(
+            'C',
+            Item::new(vec![Price::new(0, dec!(3))]).tax(Category::Food),
+        );
+
+        let mut terminal = Terminal::new(prices)?;
+
+        let state = Jurisdiction::new("State")
+            .rate(Category::General, dec!(5))
+            .rate(Category::Exempt, dec!(5))
+            .rate(Category::Food, dec!(1.5));
+
+        assert_eq!(
+            terminal.set_tax_table(
+                TaxTable::new()
+                    .jurisdiction(Jurisdiction::new("City").rate(Category::Food, dec!(-1)))
+            ),
+            Err(TerminalError::InvalidTaxRate {
+                jurisdiction: "City".to_string(),
+                rate: dec!(-1)
+            })
+        );
+
+        terminal.set_tax_table(TaxTable::new().jurisdiction(state))?;
+        terminal.add_coupon(Coupon::new(
+            "A2",
+            "$2 off A",
+            vec!['A'],
+            Discount::Amount(dec!(2)),
+        ))?;
+        terminal.add_promotion(Promotion::spend(
+            "10% off",
+            dec!(0),
+            Discount::Percent(dec!(10)),
+            Vec::<char>::new(),
+        ))?;
+
+        terminal.scan('A')?;
+        terminal.scan('B')?;
+        terminal.scan('C')?;
+        terminal.redeem("A2")?;
+
+        // A comes to 7.20 and C to 2.70, B is never taxed
+        let receipt = terminal.receipt()?;
+
+        assert_eq!(receipt.total(), dec!(14.4));
+        assert_eq!(
+            receipt.taxes(),
+            &[TaxLine {
+                jurisdiction: "State".to_string(),
+                rate: None,
+                taxable: dec!(9.9),
+                amount: dec!(0.40),
+                included: false,
+                exempt: dec!(0),
+                waived: dec!(0),
+            }]
+        );
+        assert_eq!(receipt.grand_total(), dec!(14.8));
+
+        Ok(())
+    }
+
+    #[test]
+    fn it_pulls_out_included_tax_per_line_or_per_invoice() -> Result<(), TerminalError> {
+        let mut prices = HashMap::new();
+
+        prices.insert('A', Item::new(vec![Price::new(0, dec!(0.99))]));
+        prices.insert('B', Item::new(vec![Price::new(0, dec!(0.99))]));
+        prices.insert(
+            'C',
+            Item::new(vec![Price::new(0, dec!(1.05))]).tax(Category::Food),
+        );
+
+        let mut terminal = Terminal::new(prices)?;
+
+        terminal.scan('A')?;
+        terminal.scan('B')?;
+        terminal.scan('C')?;
+
+        let vat = TaxTable::new()
+            .jurisdiction(
+                Jurisdiction::new("VAT")
+                    .rate(Category::General, dec!(20))
+                    .rate(Category::Food, dec!(5)),
+            )
+            .included();
+
+        let bands = |terminal: &Terminal| -> Result<Vec<_>, TerminalError> {
+            let receipt = terminal.receipt()?;
+
+            assert_eq!(receipt.grand_total(), dec!(3.03));
+
+            Ok(receipt
+                .taxes()
+                .iter()
+                .map(|t| (t.rate, t.taxable, t.amount))
+                .collect())
+        };
+
+        // 0.165 on each of A and B, and 0.05 on C
+        terminal.set_tax_table(vat.clone())?;
+
+        assert_eq!(
+            bands(&terminal)?,
+            vec![
+                (Some(dec!(20)), dec!(1.98), dec!(0.33)),
+                (Some(dec!(5)), dec!(1.05), dec!(0.05))
+            ]
+        );
+
+        terminal.set_tax_table(vat.rounding(TaxRounding::PerLine))?;
+
+        assert_eq!(
+            bands(&terminal)?,
+            vec![
+                (Some(dec!(20)), dec!(1.98), dec!(0.34)),
+                (Some(dec!(5)), dec!(1.05), dec!(0.05))
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn it_takes_exempt_sales_out_of_included_tax() -> Result<(), TerminalError> {
+        let mut prices = HashMap::new();
+
+        prices.insert('A', Item::new(vec![Price::new(0, dec!(12))]));
+        prices.insert(
+            'B',
+            Item::new(vec![Price::new(0, dec!(2.10))]).tax(Category::Food),
+        );
+
+        let mut terminal = Terminal::new(prices)?;
+
+        terminal.set_tax_table(
+            TaxTable::new()
+                .jurisdiction(
+                    Jurisdiction::new("VAT")
+                        .rate(Category::General, dec!(20))
+                        .rate(Category::Food, dec!(5)),
+                )
+                .included(),
+        )?;
+
+        terminal.scan('A')?;
+        terminal.scan('B')?;
+
+        assert_eq!(
+            terminal.exempt_tax(Exemption::new(" ", "charity")),
+            Err(TerminalError::MissingCertificate)
+        );
+        assert_eq!(terminal.charge_tax(), Err(TerminalError::NotExempt));
+
+        terminal.exempt_tax(Exemption::new("CH 1180", "charity"))?;
+
+        let receipt = terminal.receipt()?;
+
+        assert_eq!(
+            receipt
+                .taxes()
+                .iter()
+                .map(|t| (t.amount, t.exempt, t.waived))
+                .collect::<Vec<_>>(),
+            vec![
+                (dec!(0), dec!(12), dec!(2.00)),
+                (dec!(0), dec!(2.10), dec!(0.10))
+            ]
+        );
+        assert_eq!(receipt.grand_total(), dec!(12));
+
+        // the certificate is kept with the rest of the transaction
+        let journal = crate::journal::Journal::parse(&terminal.journal().to_string())?;
+
+        assert_eq!(&journal, terminal.journal());
+
+        terminal.charge_tax()?;
+
+        assert_eq!(terminal.exemption(), None);
+        assert_eq!(terminal.grand_total()?, dec!(14.10));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,113 @@
+//! Compares rendered receipts with the expected output in `tests/golden`, so changes to the
+//! layout or the printer commands show up without needing a printer.
+//!
+//! Run with `UPDATE_GOLDEN=1` to rewrite the files after an intended change.
+
+use rust_decimal_macros::dec;
+
+use scanner_terminal::render::{Renderer, Width};
+use scanner_terminal::{Item, Price, Promotion, Receipt, Sku, Terminal, TerminalError, Unit};
+
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+fn receipt() -> Result<Receipt, TerminalError> {
+    let mut prices: HashMap<Sku, Item> = HashMap::new();
+
+    prices.insert(
+        'A'.into(),
+        Item::new(vec![Price::new(0, dec!(2)), Price::new(4, dec!(7))]),
+    );
+    prices.insert('B'.into(), Item::weighed(Unit::Lb, dec!(1.99)));
+    prices.insert(
+        "WHOLEWHEAT-BREAD-LARGE-SLICED-LOAF".into(),
+        Item::new(vec![Price::new(0, dec!(3.49))]),
+    );
+
+    let mut terminal = Terminal::new(prices)?;
+
+    terminal.add_promotion(Promotion::buy_get(
+        "Loaf + A half off",
+        vec!["WHOLEWHEAT-BREAD-LARGE-SLICED-LOAF"],
+        1,
+        vec!['A'],
+        1,
+        dec!(50),
+    ))?;
+
+    for _ in 0..5 {
+        terminal.scan('A')?;
+    }
+
+    terminal.scan_weight('B', dec!(1.25), Unit::Lb)?;
+    terminal.scan("WHOLEWHEAT-BREAD-LARGE-SLICED-LOAF")?;
+
+    terminal.receipt()
+}
+
+fn renderer(width: Width) -> Renderer {
+    Renderer::new(width)
+        .store("CORNER SHOP")
+        .transaction_id("0001-000042")
+        .barcode(true)
+}
+
+fn check_golden(name: &str, rendered: &[u8]) {
+    let path: PathBuf = [env!("CARGO_MANIFEST_DIR"), "tests", "golden", name]
+        .iter()
+        .collect();
+
+    if env::var_os("UPDATE_GOLDEN").is_some() {
+        fs::write(&path, rendered).unwrap();
+    }
+
+    let expected = fs::read(&path).unwrap();
+
+    assert!(
+        expected == rendered,
+        "{} doesn't match, got:\n{}",
+        name,
+        String::from_utf8_lossy(rendered)
+    );
+}
+
+#[test]
+fn it_renders_text_at_every_width() -> Result<(), TerminalError> {
+    let receipt = receipt()?;
+
+    for (width, name) in [
+        (Width::Narrow, "receipt_32.txt"),
+        (Width::Regular, "receipt_42.txt"),
+        (Width::Wide, "receipt_48.txt"),
+    ]
+    .iter()
+    {
+        let text = renderer(*width).render_text(&receipt);
+
+        assert!(text.lines().all(|l| l.chars().count() <= width.columns()));
+
+        check_golden(name, text.as_bytes());
+    }
+
+    Ok(())
+}
+
+#[test]
+fn it_renders_escpos() -> Result<(), TerminalError> {
+    let receipt = receipt()?;
+
+    check_golden(
+        "receipt_42.escpos",
+        &renderer(Width::Regular).render_escpos(&receipt),
+    );
+    check_golden(
+        "receipt_32_no_barcode.escpos",
+        &renderer(Width::Narrow)
+            .barcode(false)
+            .render_escpos(&receipt),
+    );
+
+    Ok(())
+}